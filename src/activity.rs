@@ -0,0 +1,104 @@
+use crate::Project;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+/// Where the last activity time of a project comes from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ActivitySource {
+    /// The newest modification time in the project's source tree, and at the top of its build outputs.
+    #[default]
+    Mtime,
+    /// The date of the last commit in the enclosing git repository, falls back to `Mtime` outside of a repository.
+    Git,
+}
+
+/// Returns a time after `cutoff` at which `project` was active, or `None` if it has been idle since `cutoff`.
+pub fn recent_activity(project: &Project, source: ActivitySource, cutoff: SystemTime) -> Option<SystemTime> {
+    match source {
+        ActivitySource::Git => match last_commit(&project.root) {
+            Some(time) => (time > cutoff).then_some(time),
+            None => newer_mtime(project, cutoff),
+        },
+        ActivitySource::Mtime => newer_mtime(project, cutoff),
+    }
+}
+
+#[inline(always)]
+fn last_commit(dir: &Path) -> Option<SystemTime> {
+    let output = Command::new("git")
+        .args(["log", "-1", "--format=%ct"])
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let secs = std::str::from_utf8(&output.stdout).ok()?.trim().parse().ok()?;
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Returns when `project` was last active, which requires walking all of its sources.
+pub fn last_activity(project: &Project, source: ActivitySource) -> Option<SystemTime> {
+    if source == ActivitySource::Git
+        && let Some(time) = last_commit(&project.root)
+    {
+        return Some(time);
+    }
+    let mut newest = None;
+    walk_mtimes(project, |time| {
+        newest = newest.max(Some(time));
+        false
+    });
+    newest
+}
+
+/// Looks for anything modified after `cutoff`, stopping at the first one.
+#[inline(always)]
+fn newer_mtime(project: &Project, cutoff: SystemTime) -> Option<SystemTime> {
+    let mut newer = None;
+    walk_mtimes(project, |time| {
+        newer = Some(time).filter(|&time| time > cutoff);
+        newer.is_some()
+    });
+    newer
+}
+
+/// Calls `f` with the modification times of the project's sources and the top of its build outputs,
+/// until it returns `true`.
+fn walk_mtimes(project: &Project, mut f: impl FnMut(SystemTime) -> bool) {
+    let mut visit = |path: &Path| fs::symlink_metadata(path).and_then(|m| m.modified()).is_ok_and(&mut f);
+    let outputs = project.kind.output_paths(&project.root);
+    // A build touches the top of its outputs, so there's no need to walk all of them.
+    for output in &outputs {
+        if visit(output) {
+            return;
+        }
+        for entry in fs::read_dir(output).into_iter().flatten().flatten() {
+            if visit(&entry.path()) {
+                return;
+            }
+        }
+    }
+    let mut dirs = vec![project.root.clone()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if outputs.iter().any(|output| path.starts_with(output)) {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else { continue };
+            if metadata.modified().is_ok_and(&mut f) {
+                return;
+            }
+            let hidden = path.file_name().and_then(OsStr::to_str).is_some_and(|name| name.starts_with('.'));
+            if metadata.is_dir() && !hidden {
+                dirs.push(path);
+            }
+        }
+    }
+}
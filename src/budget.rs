@@ -0,0 +1,183 @@
+use crate::{ActivitySource, DiskUsage, Project, last_activity};
+use std::io::Result;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// Age at which a project's score is twice its size.
+const STALE_AFTER: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// A project considered for cleaning towards a disk space target.
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub project: Project,
+    /// Disk usage of the project's build outputs.
+    pub size: DiskUsage,
+    pub last_activity: Option<SystemTime>,
+}
+
+impl Candidate {
+    /// Measures `project` and finds its last activity.
+    #[inline(always)]
+    pub fn new(project: Project, source: ActivitySource) -> Self {
+        Self { size: project.kind.measure(&project.root), last_activity: last_activity(&project, source), project }
+    }
+
+    /// How worthwhile cleaning this project is: its reclaimable size, growing with every [`STALE_AFTER`]
+    /// it has been idle. A project with no known activity counts as just active.
+    #[inline(always)]
+    pub fn score(&self, now: SystemTime) -> f64 {
+        let age = self.last_activity.and_then(|time| now.duration_since(time).ok()).unwrap_or_default();
+        self.size.allocated as f64 * (1.0 + age.as_secs_f64() / STALE_AFTER.as_secs_f64())
+    }
+}
+
+/// Sorts `candidates` by descending [`Candidate::score`], the largest and stalest first.
+pub fn rank(candidates: &mut [Candidate]) {
+    let now = SystemTime::now();
+    candidates.sort_by(|a, b| b.score(now).total_cmp(&a.score(now)));
+}
+
+/// Tracks the space that became available on the filesystems of a set of roots.
+#[derive(Clone, Debug)]
+pub struct DiskBudget {
+    target: u64,
+    /// One root per filesystem.
+    roots: Vec<PathBuf>,
+    initial: u64,
+}
+
+impl DiskBudget {
+    /// Starts tracking `target` bytes to free on the filesystems holding `roots`.
+    pub fn new(target: u64, roots: &[PathBuf]) -> Result<Self> {
+        let mut filesystems = Vec::new();
+        let mut unique_roots = Vec::new();
+        for root in roots {
+            let stats = os_statfs::statfs(root)?;
+            // Without a filesystem id every root is counted.
+            if stats.id.is_none() || !filesystems.contains(&stats.id) {
+                filesystems.push(stats.id);
+                unique_roots.push(root.clone());
+            }
+        }
+        let mut budget = Self { target, roots: unique_roots, initial: 0 };
+        budget.initial = budget.available()?;
+        Ok(budget)
+    }
+
+    #[inline(always)]
+    pub fn target(&self) -> u64 {
+        self.target
+    }
+
+    /// Space available to unprivileged users on all the filesystems.
+    #[inline(always)]
+    fn available(&self) -> Result<u64> {
+        self.roots.iter().map(|root| os_statfs::statfs(root).map(|stats| stats.available)).sum()
+    }
+
+    /// How much more space is available than when tracking started.
+    #[inline(always)]
+    pub fn gained(&self) -> Result<u64> {
+        Ok(self.available()?.saturating_sub(self.initial))
+    }
+
+    #[inline(always)]
+    pub fn reached(&self) -> Result<bool> {
+        Ok(self.gained()? >= self.target)
+    }
+}
+
+struct FsStats {
+    /// Identifies the filesystem, if the platform has such an id.
+    id: Option<u64>,
+    available: u64,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_vendor = "apple"))]
+#[allow(non_camel_case_types)]
+mod os_statfs {
+    use super::FsStats;
+    use std::ffi::{CString, c_char, c_int, c_ulong};
+    use std::io::{Error, Result};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    #[cfg(not(target_vendor = "apple"))]
+    type fsblkcnt_t = u64;
+    #[cfg(target_vendor = "apple")]
+    type fsblkcnt_t = u32;
+
+    /// `struct statvfs` with the field order shared by glibc, musl, bionic and Apple's libc.
+    #[repr(C)]
+    struct statvfs {
+        f_bsize: c_ulong,
+        f_frsize: c_ulong,
+        f_blocks: fsblkcnt_t,
+        f_bfree: fsblkcnt_t,
+        f_bavail: fsblkcnt_t,
+        f_files: fsblkcnt_t,
+        f_ffree: fsblkcnt_t,
+        f_favail: fsblkcnt_t,
+        f_fsid: c_ulong,
+        // Room for the remaining fields, which differ between platforms.
+        f_spare: [u64; 16],
+    }
+
+    unsafe extern "C" {
+        #[cfg_attr(all(target_env = "gnu", target_pointer_width = "32"), link_name = "statvfs64")]
+        fn statvfs(path: *const c_char, buf: *mut statvfs) -> c_int;
+    }
+
+    pub(super) fn statfs(path: &Path) -> Result<FsStats> {
+        let path = CString::new(path.as_os_str().as_bytes()).map_err(Error::other)?;
+        let mut buf: statvfs = unsafe { std::mem::zeroed() };
+        if unsafe { statvfs(path.as_ptr(), &mut buf) } != 0 {
+            return Err(Error::last_os_error());
+        }
+        // `f_bavail` is in units of the fragment size.
+        let available = buf.f_bavail as u64 * buf.f_frsize as u64;
+        Ok(FsStats { id: Some(buf.f_fsid as u64), available })
+    }
+}
+
+#[cfg(windows)]
+#[allow(clippy::upper_case_acronyms)]
+mod os_statfs {
+    use super::FsStats;
+    use std::ffi::c_int;
+    use std::io::{Error, Result};
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+    use std::ptr;
+
+    type BOOL = c_int;
+    unsafe extern "system" {
+        fn GetDiskFreeSpaceExW(
+            directory_name: *const u16,
+            free_bytes_available_to_caller: *mut u64,
+            total_number_of_bytes: *mut u64,
+            total_number_of_free_bytes: *mut u64,
+        ) -> BOOL;
+    }
+
+    pub(super) fn statfs(path: &Path) -> Result<FsStats> {
+        let path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        let mut available = 0;
+        // If the function fails, the return value is zero.
+        if unsafe { GetDiskFreeSpaceExW(path.as_ptr(), &mut available, ptr::null_mut(), ptr::null_mut()) } == 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(FsStats { id: None, available })
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_vendor = "apple", windows)))]
+mod os_statfs {
+    use super::FsStats;
+    use std::io::{Error, ErrorKind, Result};
+    use std::path::Path;
+
+    pub(super) fn statfs(_path: &Path) -> Result<FsStats> {
+        Err(Error::new(ErrorKind::Unsupported, "reading the free disk space isn't supported on this platform"))
+    }
+}
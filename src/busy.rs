@@ -0,0 +1,198 @@
+//! Signs that a project is being built right now, cleaning it then would break the build and likely the cleaner
+//! too: the locks Cargo and Gradle hold while they build, a `.ninja_lock`, and on Linux processes running in the
+//! project. The active Python virtual environment counts too, removing it would break the shell using it.
+
+use crate::cargo::CargoTarget;
+use crate::{Project, RuleKind};
+use std::env;
+use std::fs::{self, File, TryLockError};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// The processes are listed again when the last list is older than this.
+const PROCESSES_MAX_AGE: Duration = Duration::from_secs(1);
+
+/// How deep lock files are looked for in `.gradle`, they are at most in `.gradle/<version>/<cache>/`.
+const GRADLE_LOCK_DEPTH: usize = 3;
+
+/// Finds the builds running in projects.
+pub(crate) struct BusyCheck {
+    /// The processes running in a directory, other than code-clean, what started it and its cleaners.
+    processes: Vec<Process>,
+    listed: Option<Instant>,
+}
+
+struct Process {
+    pid: u32,
+    name: String,
+    cwd: PathBuf,
+}
+
+impl BusyCheck {
+    #[inline(always)]
+    pub(crate) fn new() -> Self {
+        Self { processes: Vec::new(), listed: None }
+    }
+
+    /// Describes the build running in `project`, `None` if it looks idle.
+    pub(crate) fn active_build(&mut self, project: &Project) -> Option<String> {
+        if let Some(lock) = held_lock(project) {
+            return Some(format!("{} is locked by a running build", lock.display()));
+        }
+        if let Some(venv) = env::var_os("VIRTUAL_ENV").filter(|venv| !venv.is_empty())
+            && fs::canonicalize(venv).is_ok_and(|venv| fs::canonicalize(&project.root).is_ok_and(|root| root == venv))
+        {
+            return Some(format!("{} is the active virtual environment", project.root.display()));
+        }
+        let ninja_lock = project.root.join(".ninja_lock");
+        if ninja_lock.exists() {
+            return Some(format!("{} shows a running build", ninja_lock.display()));
+        }
+        let process = self.process_in(&project.root)?;
+        Some(format!("process {} ({}) is running in {}", process.pid, process.name, process.cwd.display()))
+    }
+
+    fn process_in(&mut self, dir: &Path) -> Option<&Process> {
+        if self.listed.is_none_or(|listed| listed.elapsed() > PROCESSES_MAX_AGE) {
+            self.processes = os_busy::processes();
+            self.listed = Some(Instant::now());
+        }
+        let dir = fs::canonicalize(dir).ok()?;
+        self.processes.iter().find(|process| process.cwd.starts_with(&dir))
+    }
+}
+
+/// A lock file of `project` some build holds.
+fn held_lock(project: &Project) -> Option<PathBuf> {
+    let mut candidates = Vec::new();
+    if project.kind.kind == Some(RuleKind::Cargo)
+        && let Ok(target) = CargoTarget::resolve(&project.marker)
+    {
+        // `target/debug/.cargo-lock`, or `target/<triple>/debug/.cargo-lock` when cross-compiling.
+        for profile in subdirs(&target.dir) {
+            candidates.extend(subdirs(&profile).map(|dir| dir.join(".cargo-lock")));
+            candidates.push(profile.join(".cargo-lock"));
+        }
+    }
+    collect_locks(&project.root.join(".gradle"), GRADLE_LOCK_DEPTH, &mut candidates);
+    candidates.into_iter().find(|path| is_locked(path))
+}
+
+#[inline(always)]
+fn subdirs(dir: &Path) -> impl Iterator<Item = PathBuf> {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .map(|entry| entry.path())
+}
+
+/// The `*.lock` files in `dir`, and in its subdirectories up to `depth` levels down.
+fn collect_locks(dir: &Path, depth: usize, locks: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() && depth > 1 => collect_locks(&path, depth - 1, locks),
+            Ok(file_type) if file_type.is_file() && path.extension().is_some_and(|ext| ext == "lock") => {
+                locks.push(path)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Whether another process holds a lock on the file at `path`. Cargo takes `flock` locks, Gradle byte range locks
+/// which `flock` doesn't see on Linux.
+fn is_locked(path: &Path) -> bool {
+    let Ok(file) = File::open(path) else { return false };
+    match file.try_lock_shared() {
+        Err(TryLockError::WouldBlock) => true,
+        // Only held until the file is closed.
+        Ok(()) | Err(TryLockError::Error(_)) => os_busy::has_record_lock(&file),
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod os_busy {
+    use super::Process;
+    use std::collections::{HashMap, HashSet};
+    use std::ffi::{c_int, c_short};
+    use std::fs::{self, File};
+    use std::os::fd::AsRawFd;
+    use std::process;
+
+    const F_GETLK: c_int = 5;
+    const F_WRLCK: c_short = 1;
+    const F_UNLCK: c_short = 2;
+
+    #[repr(C)]
+    struct Flock {
+        l_type: c_short,
+        l_whence: c_short,
+        l_start: i64,
+        l_len: i64,
+        l_pid: i32,
+    }
+
+    unsafe extern "C" {
+        fn fcntl(fd: c_int, cmd: c_int, ...) -> c_int;
+    }
+
+    /// Whether another process holds a byte range lock anywhere in `file`.
+    pub(super) fn has_record_lock(file: &File) -> bool {
+        // Asks which lock would keep us from locking the whole file for writing.
+        let mut lock = Flock { l_type: F_WRLCK, l_whence: 0, l_start: 0, l_len: 0, l_pid: 0 };
+        let res = unsafe { fcntl(file.as_raw_fd(), F_GETLK, &mut lock as *mut Flock) };
+        res != -1 && lock.l_type != F_UNLCK
+    }
+
+    /// The processes with a working directory we can read, except code-clean, its ancestors and its descendants.
+    pub(super) fn processes() -> Vec<Process> {
+        let mut parents = HashMap::new();
+        let mut processes = Vec::new();
+        for entry in fs::read_dir("/proc").into_iter().flatten().flatten() {
+            let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) else { continue };
+            let dir = entry.path();
+            let Ok(stat) = fs::read_to_string(dir.join("stat")) else { continue };
+            // `pid (name) state ppid ...`, the name can contain anything so the fields come after its last `)`.
+            let Some((head, fields)) = stat.rsplit_once(')') else { continue };
+            let Some(ppid) = fields.split_whitespace().nth(1).and_then(|ppid| ppid.parse::<u32>().ok()) else {
+                continue;
+            };
+            parents.insert(pid, ppid);
+            if let Ok(cwd) = fs::read_link(dir.join("cwd")) {
+                let name = head.split_once('(').map_or("", |(_, name)| name).to_owned();
+                processes.push(Process { pid, name, cwd });
+            }
+        }
+        let ancestry = |pid: u32| {
+            // Bounded, in case the processes were reparented while they were listed.
+            std::iter::successors(Some(pid), |pid| parents.get(pid).copied().filter(|&ppid| ppid != 0))
+                .take(parents.len() + 1)
+        };
+        let own = process::id();
+        let ancestors: HashSet<u32> = ancestry(own).collect();
+        processes.retain(|process| !ancestors.contains(&process.pid) && !ancestry(process.pid).any(|pid| pid == own));
+        processes
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod os_busy {
+    use super::Process;
+    use std::fs::File;
+
+    /// Byte range locks are seen by `try_lock_shared` already.
+    #[inline(always)]
+    pub(super) fn has_record_lock(_file: &File) -> bool {
+        false
+    }
+
+    /// Working directories of other processes are only read on Linux.
+    #[inline(always)]
+    pub(super) fn processes() -> Vec<Process> {
+        Vec::new()
+    }
+}
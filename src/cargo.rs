@@ -0,0 +1,191 @@
+//! Where Cargo puts the build outputs of a manifest, so a workspace is cleaned once rather than once per member,
+//! and a target directory shared with other projects is left alone.
+
+use crate::toml::{self, Table, Value};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// The target directory of a manifest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct CargoTarget {
+    /// The root of the workspace the manifest is in, or the directory of the manifest.
+    pub(crate) root: PathBuf,
+    pub(crate) dir: PathBuf,
+    /// What set the target directory, `None` for the `target` directory of the workspace.
+    pub(crate) source: Option<String>,
+}
+
+impl CargoTarget {
+    /// Resolves the target directory `cargo clean` would delete when run in the workspace of `manifest`. Fails
+    /// with the reason it is unknown if a configuration file sets it in a way that can't be parsed.
+    pub(crate) fn resolve(manifest: &Path) -> Result<Self, String> {
+        let root = workspace_root(manifest);
+        let (dir, source) = match configured_target_dir(&root)? {
+            Some((dir, source)) => (normalize(&dir), Some(source)),
+            None => (root.join("target"), None),
+        };
+        Ok(Self { root, dir, source })
+    }
+
+    /// Whether the target directory is outside the workspace, where other projects may build into it too.
+    #[inline(always)]
+    pub(crate) fn is_external(&self) -> bool {
+        !self.dir.starts_with(&self.root)
+    }
+}
+
+/// The fields of a manifest that place it in a workspace.
+#[derive(Default)]
+struct Manifest {
+    /// Has a `[workspace]` table.
+    is_workspace: bool,
+    /// `package.workspace`, the path of the workspace root.
+    workspace: Option<String>,
+    /// `workspace.exclude`, paths relative to the workspace root.
+    exclude: Vec<String>,
+}
+
+impl Manifest {
+    /// `None` if there is no manifest at `path`.
+    fn read(path: &Path) -> Option<Self> {
+        let src = fs::read_to_string(path).ok()?;
+        let Ok(document) = toml::parse(&src) else {
+            // Manifests can use TOML the configuration parser doesn't support, the header is enough then.
+            let is_workspace = src.lines().any(|line| {
+                let line = line.trim_start();
+                line.starts_with("[workspace]") || line.starts_with("[workspace.")
+            });
+            return Some(Self { is_workspace, ..Self::default() });
+        };
+        let workspace = table(&document, "workspace");
+        let exclude = match workspace.and_then(|workspace| workspace.get("exclude")) {
+            Some(Value::Array(paths)) => paths.iter().filter_map(string).map(str::to_owned).collect(),
+            _ => Vec::new(),
+        };
+        let package = table(&document, "package");
+        let member_of = package.and_then(|package| package.get("workspace")).and_then(string).map(str::to_owned);
+        Some(Self { is_workspace: workspace.is_some(), workspace: member_of, exclude })
+    }
+}
+
+#[inline(always)]
+fn table<'a>(table: &'a Table, key: &str) -> Option<&'a Table> {
+    match table.get(key) {
+        Some(Value::Table(table)) => Some(table),
+        _ => None,
+    }
+}
+
+#[inline(always)]
+fn string(value: &Value) -> Option<&str> {
+    match value {
+        Value::String(s) => Some(s),
+        _ => None,
+    }
+}
+
+/// The directory of the workspace `manifest` belongs to, which is the closest ancestor with a `[workspace]` that
+/// doesn't exclude it, unless the manifest names its workspace.
+fn workspace_root(manifest: &Path) -> PathBuf {
+    let dir = manifest.parent().unwrap_or(Path::new(""));
+    let own = Manifest::read(manifest).unwrap_or_default();
+    if own.is_workspace {
+        return dir.to_path_buf();
+    }
+    if let Some(root) = own.workspace {
+        return normalize(&dir.join(root));
+    }
+    for ancestor in dir.ancestors().skip(1) {
+        let Some(candidate) = Manifest::read(&ancestor.join("Cargo.toml")) else { continue };
+        let relative = dir.strip_prefix(ancestor).unwrap_or(dir);
+        if candidate.is_workspace && !candidate.exclude.iter().any(|excluded| relative.starts_with(excluded)) {
+            return ancestor.to_path_buf();
+        }
+    }
+    dir.to_path_buf()
+}
+
+/// The target directory set by the environment or a `.cargo/config.toml` for Cargo running in `dir`, with what
+/// set it.
+fn configured_target_dir(dir: &Path) -> Result<Option<(PathBuf, String)>, String> {
+    for var in ["CARGO_TARGET_DIR", "CARGO_BUILD_TARGET_DIR"] {
+        if let Some(target) = env::var_os(var).filter(|target| !target.is_empty()) {
+            return Ok(Some((dir.join(target), var.to_owned())));
+        }
+    }
+    // The closest configuration wins, the one in Cargo's home comes last.
+    let cargo_home = env::var_os("CARGO_HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cargo")));
+    let config_dirs = dir.ancestors().map(|ancestor| ancestor.join(".cargo")).chain(cargo_home);
+    for config_dir in config_dirs {
+        for name in ["config.toml", "config"] {
+            let config = config_dir.join(name);
+            if let Some(target) = config_target_dir(&config)? {
+                // Relative to the directory containing `.cargo`.
+                let base = config_dir.parent().unwrap_or(&config_dir);
+                return Ok(Some((base.join(target), config.display().to_string())));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// `build.target-dir` of a Cargo configuration file, an error if the file sets it but it can't be parsed.
+#[inline(always)]
+fn config_target_dir(config: &Path) -> Result<Option<OsString>, String> {
+    let Ok(src) = fs::read_to_string(config) else { return Ok(None) };
+    let error = match toml::parse(&src) {
+        Ok(document) => {
+            let build = table(&document, "build");
+            return Ok(build.and_then(|build| build.get("target-dir")).and_then(string).map(OsString::from));
+        }
+        Err(error) => error,
+    };
+    // Configurations can use TOML the parser doesn't support, only the `target-dir` line has to be read then.
+    let Some(line) = target_dir_line(&src) else { return Ok(None) };
+    match toml::parse(line).as_ref().ok().and_then(|line| line.get("target-dir")).and_then(string) {
+        Some(target) => Ok(Some(OsString::from(target))),
+        None => Err(format!("{} can't be parsed: {error}", config.display())),
+    }
+}
+
+/// The `target-dir` line of the `[build]` table in `src`, with the key made relative to the table.
+fn target_dir_line(src: &str) -> Option<&str> {
+    let mut table = "";
+    for line in src.lines().map(str::trim) {
+        if let Some(header) = line.strip_prefix('[') {
+            table = header.split(']').next().unwrap_or_default().trim();
+            continue;
+        }
+        let line = match table {
+            "" => line.strip_prefix("build.").map(str::trim_start),
+            "build" => Some(line),
+            _ => None,
+        };
+        let Some(line) = line else { continue };
+        if line.strip_prefix("target-dir").is_some_and(|rest| rest.trim_start().starts_with('=')) {
+            return Some(line);
+        }
+    }
+    None
+}
+
+/// Removes the `.` and `..` components of `path` without touching the file system.
+#[inline(always)]
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
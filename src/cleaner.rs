@@ -0,0 +1,41 @@
+use crate::Project;
+use std::ffi::OsString;
+use std::fmt;
+use std::io::Result;
+use std::path::PathBuf;
+
+/// What has to be done to clean a project.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Run `program` with `args` inside `dir`.
+    Command { program: OsString, args: Vec<OsString>, dir: PathBuf },
+    /// Recursively remove these directories, or files, in-process.
+    Remove(Vec<PathBuf>),
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Command { program, args, .. } => {
+                f.write_str(&program.to_string_lossy())?;
+                for arg in args {
+                    write!(f, " {}", arg.to_string_lossy())?;
+                }
+                Ok(())
+            }
+            Action::Remove(paths) => {
+                f.write_str("rm -rf")?;
+                for path in paths {
+                    write!(f, " {}", path.display())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Decides how a detected project gets cleaned, every [`Rule`](crate::Rule) is a cleaner.
+pub trait Cleaner {
+    /// Returns the action that cleans `project`, or `None` if there's nothing to clean.
+    fn plan(&self, project: &Project) -> Result<Option<Action>>;
+}
@@ -0,0 +1,402 @@
+use code_clean::{ActivitySource, MAX_KIDS, parse_duration, parse_size};
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::path::{self, PathBuf};
+use std::time::Duration;
+
+pub(crate) const USAGE: &str = "\
+Find build projects and clean their build outputs
+
+Usage: code-clean [COMMAND] [OPTIONS] [PATH]...
+       code-clean purge [--older-than <AGE>] [--quarantine <DIR>]
+       code-clean restore [--quarantine <DIR>] [ID]...
+
+Commands:
+  clean    Clean every detected project (default)
+  scan     List the detected projects without touching them
+  report   Show how much space every project's build outputs use
+  tui      Pick the projects to clean from an interactive list
+  purge    Delete what was quarantined, or only what was quarantined more than --older-than ago
+  restore  Move quarantined directories back to where they were, without an ID list them
+
+Arguments:
+  [PATH]...  Root directories to search [default: the current directory]
+
+Options:
+  -j, --jobs <N>  Maximum number of cleaners running at once [default: 768]
+      --dry-run   Print what `clean` would do without doing it
+      --older-than <AGE>
+                  Skip projects active within AGE, like 30d, 12h or 2w
+      --timeout <DURATION>
+                  Terminate cleaners running for longer than DURATION, like 5m, rules can set their own
+      --free <SIZE>
+                  Clean the largest and stalest projects first, until SIZE more disk space is
+                  available, like 50G or 512M
+      --activity <SOURCE>
+                  How to find a project's last activity for --older-than and --free [default: mtime]
+                  mtime: newest modification time of its sources and build outputs
+                  git: date of the last commit in its git repository
+      --resume    Continue the last clean of the same paths where a signal stopped it, skipping the
+                  projects it cleaned
+      --no-exec   Never run anything from the projects, delete their build outputs directly and skip
+                  the projects that can only be cleaned by running a command
+      --no-sandbox
+                  Run cleaners with the full environment, and on Linux without restricting their writes
+                  to the project directory
+      --no-network
+                  Run cleaners without network access, on Linux
+      --trash     Move deleted directories to the trash instead
+      --quarantine <DIR>
+                  Move deleted directories into DIR instead, which must be on the same filesystem
+                  [default for purge and restore: ~/.local/share/code-clean/quarantine]
+      --format <FORMAT>
+                  Output format of clean, scan and report [default: text]
+                  text: human readable lines
+                  json: a single JSON document with every event and a summary, printed at the end
+                  ndjson: one JSON event per line as they happen, ending with a summary
+  -v, --verbose   Also print every command that is run
+  -q, --quiet     Only print errors
+  -h, --help      Print help
+  -V, --version   Print version
+
+Exit status:
+  0  Success
+  1  An error stopped code-clean, or another one is cleaning the same paths
+  2  A dry run found something to clean
+  3  A first SIGINT or SIGTERM stopped the cleaning, after the running cleaners finished
+  128+N  A second signal N killed the running cleaners
+";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Subcommand {
+    Clean,
+    Scan,
+    Report,
+    Tui,
+    Purge,
+    Restore,
+}
+
+impl Subcommand {
+    const ALL: [Subcommand; 6] = [
+        Subcommand::Clean,
+        Subcommand::Scan,
+        Subcommand::Report,
+        Subcommand::Tui,
+        Subcommand::Purge,
+        Subcommand::Restore,
+    ];
+
+    #[inline(always)]
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|command| command.name() == name)
+    }
+
+    #[inline(always)]
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Subcommand::Clean => "clean",
+            Subcommand::Scan => "scan",
+            Subcommand::Report => "report",
+            Subcommand::Tui => "tui",
+            Subcommand::Purge => "purge",
+            Subcommand::Restore => "restore",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Format {
+    #[default]
+    Text,
+    Json,
+    Ndjson,
+}
+
+#[derive(Debug)]
+pub(crate) struct Args {
+    pub(crate) subcommand: Subcommand,
+    /// Absolute paths of the directories to search.
+    pub(crate) roots: Vec<PathBuf>,
+    /// Quarantine entries to restore.
+    pub(crate) ids: Vec<String>,
+    pub(crate) jobs: usize,
+    pub(crate) dry_run: bool,
+    pub(crate) older_than: Option<Duration>,
+    /// How long a cleaner may run, unless its rule says otherwise.
+    pub(crate) timeout: Option<Duration>,
+    /// Bytes to free with `--free`.
+    pub(crate) free: Option<u64>,
+    pub(crate) activity: ActivitySource,
+    /// Continue the interrupted run of the same roots.
+    pub(crate) resume: bool,
+    /// Only delete files, for projects that can't be trusted to run code.
+    pub(crate) no_exec: bool,
+    /// Confine the cleaners, unless `--no-sandbox`.
+    pub(crate) sandbox: bool,
+    pub(crate) isolate_network: bool,
+    pub(crate) trash: bool,
+    pub(crate) quarantine: Option<PathBuf>,
+    pub(crate) verbosity: Verbosity,
+    pub(crate) format: Format,
+}
+
+#[derive(Debug)]
+pub(crate) enum Parsed {
+    Run(Args),
+    Help,
+    Version,
+}
+
+/// An invalid command line, printed together with a hint to use `--help`.
+#[derive(Debug)]
+pub(crate) struct CliError(String);
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n\nFor more information, try '--help'.", self.0)
+    }
+}
+
+impl std::error::Error for CliError {}
+
+macro_rules! bail {
+    ($($arg:tt)*) => {
+        return Err(CliError(format!($($arg)*)))
+    };
+}
+
+/// Parses the command line arguments, without the program name.
+pub(crate) fn parse(args: impl IntoIterator<Item = OsString>) -> Result<Parsed, CliError> {
+    let mut args = args.into_iter();
+    let mut subcommand = None;
+    let mut roots = Vec::new();
+    let mut jobs = None;
+    let mut dry_run = false;
+    let mut older_than = None;
+    let mut free = None;
+    let mut timeout = None;
+    let mut resume = false;
+    let mut no_exec = false;
+    let (mut no_sandbox, mut no_network) = (false, false);
+    let mut trash = false;
+    let mut quarantine = None;
+    let mut activity = None;
+    let mut format = None;
+    let (mut verbose, mut quiet) = (false, false);
+    let mut only_paths = false;
+
+    while let Some(arg) = args.next() {
+        let Some(arg_str) = arg.to_str().filter(|s| !only_paths && s.starts_with('-') && s.len() > 1) else {
+            // Only the first positional argument can be a subcommand.
+            match arg.to_str().filter(|_| subcommand.is_none() && roots.is_empty()).and_then(Subcommand::from_name) {
+                Some(command) => subcommand = Some(command),
+                None => roots.push(PathBuf::from(arg)),
+            }
+            continue;
+        };
+        // Split `--flag=value` and `-jVALUE`.
+        let (flag, inline_value) = match arg_str.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+            _ if arg_str.starts_with("-j") && arg_str.len() > 2 => ("-j", Some(arg_str[2..].to_string())),
+            _ => (arg_str, None),
+        };
+        match flag {
+            "--" => only_paths = true,
+            "-h" | "--help" => return Ok(Parsed::Help),
+            "-V" | "--version" => return Ok(Parsed::Version),
+            "-j" | "--jobs" => {
+                let value = take_value(flag, inline_value, &mut args)?;
+                jobs = match value.parse::<usize>() {
+                    Ok(0) => bail!("invalid value '0' for '{flag}': must be at least 1"),
+                    Ok(jobs) => Some(jobs),
+                    Err(err) => bail!("invalid value '{value}' for '{flag}': {err}"),
+                };
+            }
+            "--older-than" => {
+                let value = take_value(flag, inline_value, &mut args)?;
+                older_than = match parse_duration(&value) {
+                    Ok(duration) => Some(duration),
+                    Err(err) => bail!("invalid value '{value}' for '{flag}': {err}"),
+                };
+            }
+            "--timeout" => {
+                let value = take_value(flag, inline_value, &mut args)?;
+                timeout = match parse_duration(&value) {
+                    Ok(Duration::ZERO) => bail!("invalid value '{value}' for '{flag}': must be more than 0s"),
+                    Ok(duration) => Some(duration),
+                    Err(err) => bail!("invalid value '{value}' for '{flag}': {err}"),
+                };
+            }
+            "--free" => {
+                let value = take_value(flag, inline_value, &mut args)?;
+                free = match parse_size(&value) {
+                    Ok(0) => bail!("invalid value '{value}' for '{flag}': must be more than 0"),
+                    Ok(size) => Some(size),
+                    Err(err) => bail!("invalid value '{value}' for '{flag}': {err}"),
+                };
+            }
+            "--quarantine" => quarantine = Some(PathBuf::from(take_value(flag, inline_value, &mut args)?)),
+            "--activity" => {
+                activity = match take_value(flag, inline_value, &mut args)?.as_str() {
+                    "mtime" => Some(ActivitySource::Mtime),
+                    "git" => Some(ActivitySource::Git),
+                    value => bail!("invalid value '{value}' for '{flag}': expected 'mtime' or 'git'"),
+                };
+            }
+            "--format" => {
+                format = match take_value(flag, inline_value, &mut args)?.as_str() {
+                    "text" => Some(Format::Text),
+                    "json" => Some(Format::Json),
+                    "ndjson" => Some(Format::Ndjson),
+                    value => bail!("invalid value '{value}' for '{flag}': expected 'text', 'json' or 'ndjson'"),
+                };
+            }
+            _ if inline_value.is_some() => bail!("'{flag}' doesn't take a value"),
+            "--dry-run" => dry_run = true,
+            "--resume" => resume = true,
+            "--no-exec" => no_exec = true,
+            "--no-sandbox" => no_sandbox = true,
+            "--no-network" => no_network = true,
+            "--trash" => trash = true,
+            "-v" | "--verbose" => verbose = true,
+            "-q" | "--quiet" => quiet = true,
+            _ if flag.starts_with("--") => bail!("unexpected argument '{flag}'"),
+            // Combined short flags, like `-vq`.
+            _ if flag.len() > 2 => bail!("unexpected argument '{flag}', short flags can't be combined"),
+            _ => bail!("unexpected argument '{flag}'"),
+        }
+    }
+
+    let subcommand = subcommand.unwrap_or(Subcommand::Clean);
+    let verbosity = match (verbose, quiet) {
+        (true, true) => bail!("'--verbose' and '--quiet' can't be used together"),
+        (true, false) => Verbosity::Verbose,
+        (false, true) => Verbosity::Quiet,
+        (false, false) => Verbosity::Normal,
+    };
+    use Subcommand::{Clean, Purge, Restore};
+    let restricted: [(&str, bool, &[Subcommand]); 12] = [
+        ("--dry-run", dry_run, &[Clean]),
+        ("--older-than", older_than.is_some(), &[Clean, Purge]),
+        ("--timeout", timeout.is_some(), &[Clean]),
+        ("--free", free.is_some(), &[Clean]),
+        ("--activity", activity.is_some(), &[Clean]),
+        ("--resume", resume, &[Clean]),
+        ("--no-exec", no_exec, &[Clean, Subcommand::Tui]),
+        ("--no-sandbox", no_sandbox, &[Clean, Subcommand::Tui]),
+        ("--no-network", no_network, &[Clean, Subcommand::Tui]),
+        ("--trash", trash, &[Clean]),
+        ("--quarantine", quarantine.is_some(), &[Clean, Purge, Restore]),
+        ("--format", format.is_some(), &[Clean, Subcommand::Scan, Subcommand::Report]),
+    ];
+    for (flag, used, allowed) in restricted {
+        if used && !allowed.contains(&subcommand) {
+            let mut allowed: Vec<_> = allowed.iter().map(|command| format!("'{}'", command.name())).collect();
+            let last = allowed.pop().expect("Every flag is allowed somewhere");
+            if allowed.is_empty() {
+                bail!("'{flag}' can only be used with {last}");
+            }
+            bail!("'{flag}' can only be used with {} or {last}", allowed.join(", "));
+        }
+    }
+    if activity.is_some() && older_than.is_none() && free.is_none() {
+        bail!("'--activity' requires '--older-than' or '--free'");
+    }
+    if resume && (dry_run || free.is_some()) {
+        bail!("'--resume' can't be used with '--dry-run' or '--free', which don't record what they clean");
+    }
+    if no_exec && activity == Some(ActivitySource::Git) {
+        bail!("'--no-exec' can't be used with '--activity git', which runs git in the projects");
+    }
+    if no_sandbox && no_network {
+        bail!("'--no-network' needs the sandbox, it can't be used with '--no-sandbox'");
+    }
+    if trash && quarantine.is_some() {
+        bail!("'--trash' and '--quarantine' can't be used together");
+    }
+    if free.is_some() && (trash || quarantine.is_some()) {
+        bail!("'--free' can't be used with '--trash' or '--quarantine', they don't free any space");
+    }
+    if let Some(dir) = &mut quarantine {
+        *dir = match path::absolute(&*dir) {
+            Ok(dir) => dir,
+            Err(err) => bail!("invalid path '{}': {err}", dir.display()),
+        };
+    }
+    let mut ids = Vec::new();
+    match subcommand {
+        Restore => {
+            for id in roots.drain(..) {
+                match id.into_os_string().into_string() {
+                    Ok(id) => ids.push(id),
+                    Err(id) => bail!("invalid id '{}'", id.display()),
+                }
+            }
+        }
+        Purge if !roots.is_empty() => bail!("'purge' doesn't take paths"),
+        _ if roots.is_empty() => roots.push(PathBuf::from(".")),
+        _ => {}
+    }
+    for root in &mut roots {
+        if !root.is_dir() {
+            bail!("'{}' is not a directory", root.display());
+        }
+        // Canonical, so two spellings of the same directory are one root, for the locks and the journal too.
+        *root = match fs::canonicalize(&*root) {
+            Ok(root) => root,
+            Err(err) => bail!("invalid path '{}': {err}", root.display()),
+        };
+    }
+    // A root inside another one is walked with it already, parents sort before their children.
+    roots.sort();
+    let mut outer: Vec<PathBuf> = Vec::with_capacity(roots.len());
+    for root in roots.drain(..) {
+        if !outer.iter().any(|kept| root.starts_with(kept)) {
+            outer.push(root);
+        }
+    }
+    roots = outer;
+    let jobs = jobs.unwrap_or(MAX_KIDS);
+    let activity = activity.unwrap_or_default();
+    let format = format.unwrap_or_default();
+    Ok(Parsed::Run(Args {
+        subcommand,
+        roots,
+        ids,
+        jobs,
+        dry_run,
+        older_than,
+        timeout,
+        free,
+        activity,
+        resume,
+        no_exec,
+        sandbox: !no_sandbox,
+        isolate_network: no_network,
+        trash,
+        quarantine,
+        verbosity,
+        format,
+    }))
+}
+
+#[inline(always)]
+fn take_value(
+    flag: &str,
+    inline_value: Option<String>,
+    args: &mut impl Iterator<Item = OsString>,
+) -> Result<String, CliError> {
+    match inline_value.or_else(|| args.next().and_then(|v| v.into_string().ok())) {
+        Some(value) => Ok(value),
+        None => bail!("'{flag}' requires a value"),
+    }
+}
@@ -0,0 +1,176 @@
+use std::io::Read;
+use std::io::{ErrorKind, Result};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+/// How much of a child's output is kept, the end is the most useful part of a failure.
+pub(crate) const OUTPUT_LIMIT: usize = 64 * 1024;
+
+/// The last bytes of an output and how many were dropped before them.
+#[derive(Default)]
+pub(crate) struct Tail {
+    bytes: Vec<u8>,
+    dropped: u64,
+}
+
+impl Tail {
+    #[inline(always)]
+    fn push(&mut self, chunk: &[u8]) {
+        self.bytes.extend_from_slice(chunk);
+        if self.bytes.len() > OUTPUT_LIMIT {
+            let excess = self.bytes.len() - OUTPUT_LIMIT;
+            self.bytes.drain(..excess);
+            self.dropped += excess as u64;
+        }
+    }
+
+    #[inline(always)]
+    fn to_text(&self) -> String {
+        let output = String::from_utf8_lossy(&self.bytes);
+        match self.dropped {
+            0 => output.into_owned(),
+            dropped => format!("[{dropped} bytes skipped]\n{output}"),
+        }
+    }
+}
+
+/// Reads a pipe while the child runs, so a child writing more than the pipe buffer never blocks. Only the last
+/// [`OUTPUT_LIMIT`] bytes are kept.
+pub(crate) enum Drain {
+    /// Read on a thread of its own.
+    Thread {
+        tail: Arc<Mutex<Tail>>,
+        /// Disconnected once the pipe is closed.
+        eof: Receiver<()>,
+    },
+    /// Read by the loop waiting on the children, whenever the pipe is ready.
+    #[cfg(target_os = "linux")]
+    Polled(PolledPipe),
+}
+
+impl Drain {
+    pub(crate) fn spawn(mut pipe: impl Read + Send + 'static) -> Result<Self> {
+        let tail = Arc::new(Mutex::new(Tail::default()));
+        let (eof_sender, eof) = mpsc::channel();
+        let thread_tail = Arc::clone(&tail);
+        thread::Builder::new().name("code-clean-drain".into()).stack_size(64 * 1024).spawn(move || {
+            let _eof_sender = eof_sender;
+            let mut buf = vec![0; 8 * 1024];
+            loop {
+                match pipe.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => thread_tail.lock().unwrap_or_else(PoisonError::into_inner).push(&buf[..n]),
+                    // A signal handled on this thread, see `handle_interrupts`.
+                    Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                    // Read errors end the output just like the end of the pipe does.
+                    Err(_) => break,
+                }
+            }
+        })?;
+        Ok(Self::Thread { tail, eof })
+    }
+
+    /// Waits up to `timeout` for the pipe to close, and returns what was read so far.
+    ///
+    /// The pipe can outlive the child when it is inherited by a process left running in the background,
+    /// like a build daemon, so this never waits for longer than `timeout`.
+    pub(crate) fn finish(self, timeout: Duration) -> String {
+        match self {
+            Self::Thread { tail, eof } => {
+                let _ = eof.recv_timeout(timeout);
+                tail.lock().unwrap_or_else(PoisonError::into_inner).to_text()
+            }
+            #[cfg(target_os = "linux")]
+            Self::Polled(pipe) => pipe.finish(timeout),
+        }
+    }
+}
+
+/// A non-blocking pipe, read whenever it is ready instead of on a thread.
+#[cfg(target_os = "linux")]
+pub(crate) struct PolledPipe {
+    pipe: std::process::ChildStderr,
+    tail: Tail,
+    eof: bool,
+}
+
+#[cfg(target_os = "linux")]
+impl PolledPipe {
+    pub(crate) fn new(pipe: std::process::ChildStderr) -> Result<Self> {
+        os_poll::set_nonblocking(&pipe)?;
+        Ok(Self { pipe, tail: Tail::default(), eof: false })
+    }
+
+    #[inline(always)]
+    pub(crate) fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        std::os::fd::AsRawFd::as_raw_fd(&self.pipe)
+    }
+
+    /// Reads everything the pipe holds right now, and returns whether it was closed.
+    pub(crate) fn read_available(&mut self) -> bool {
+        let mut buf = [0; 8 * 1024];
+        while !self.eof {
+            match self.pipe.read(&mut buf) {
+                Ok(0) => self.eof = true,
+                Ok(n) => self.tail.push(&buf[..n]),
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => self.eof = true,
+            }
+        }
+        self.eof
+    }
+
+    /// See [`Drain::finish`].
+    fn finish(mut self, timeout: Duration) -> String {
+        let deadline = std::time::Instant::now() + timeout;
+        while !self.read_available() {
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            if remaining.is_zero() || !os_poll::wait_readable(&self.pipe, remaining) {
+                break;
+            }
+        }
+        self.tail.to_text()
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod os_poll {
+    use std::ffi::{c_int, c_short, c_ulong};
+    use std::io::{Error, Result};
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::time::Duration;
+
+    const F_GETFL: c_int = 3;
+    const F_SETFL: c_int = 4;
+    const O_NONBLOCK: c_int = 0o4000;
+    const POLLIN: c_short = 1;
+    #[repr(C)]
+    struct PollFd {
+        fd: RawFd,
+        events: c_short,
+        revents: c_short,
+    }
+    unsafe extern "C" {
+        fn fcntl(fd: c_int, cmd: c_int, ...) -> c_int;
+        fn poll(fds: *mut PollFd, nfds: c_ulong, timeout: c_int) -> c_int;
+    }
+
+    pub(super) fn set_nonblocking(fd: &impl AsRawFd) -> Result<()> {
+        let flags = unsafe { fcntl(fd.as_raw_fd(), F_GETFL) };
+        if flags == -1 || unsafe { fcntl(fd.as_raw_fd(), F_SETFL, flags | O_NONBLOCK) } == -1 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Waits up to `timeout` for `fd` to be readable or closed, returns `false` if it wasn't.
+    #[inline(always)]
+    pub(super) fn wait_readable(fd: &impl AsRawFd, timeout: Duration) -> bool {
+        let mut fds = PollFd { fd: fd.as_raw_fd(), events: POLLIN, revents: 0 };
+        let timeout = c_int::try_from(timeout.as_micros().div_ceil(1000)).unwrap_or(c_int::MAX);
+        unsafe { poll(&mut fds, 1, timeout) > 0 }
+    }
+}
@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+const UNITS: &[(char, u64)] = &[('w', 7 * 24 * 60 * 60), ('d', 24 * 60 * 60), ('h', 60 * 60), ('m', 60), ('s', 1)];
+
+/// Parses a duration like `30d`, `12h`, `5m` or `45s` (`w` is weeks).
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let Some(unit) = s.chars().last().filter(char::is_ascii_alphabetic) else {
+        return Err(format!("missing a unit in '{s}', expected one of s, m, h, d, w"));
+    };
+    let Some(&(_, seconds)) = UNITS.iter().find(|&&(u, _)| u == unit) else {
+        return Err(format!("unknown unit '{unit}' in '{s}', expected one of s, m, h, d, w"));
+    };
+    let amount: u64 = s[..s.len() - 1].parse().map_err(|err| format!("invalid duration '{s}': {err}"))?;
+    amount.checked_mul(seconds).map(Duration::from_secs).ok_or_else(|| format!("duration '{s}' is too large"))
+}
+
+/// Formats a duration like `30d` or `90m`, in the largest unit or the one after it if that's exact,
+/// otherwise rounded down to the largest unit.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let largest = UNITS.iter().position(|&(_, seconds)| secs >= seconds).unwrap_or(UNITS.len() - 1);
+    let exact = UNITS[largest..].iter().take(2).find(|&&(_, seconds)| secs.is_multiple_of(seconds));
+    let &(unit, seconds) = exact.unwrap_or(&UNITS[largest]);
+    format!("{}{unit}", secs / seconds)
+}
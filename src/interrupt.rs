@@ -0,0 +1,112 @@
+//! Counting SIGINT and SIGTERM instead of dying on them, so cleaning can stop in an orderly way.
+//!
+//! The first signal asks to stop starting cleaners, the second one to kill the running ones. After that the
+//! default handlers are back, so a third signal ends the process right away.
+
+use std::io::Result;
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+
+static RECEIVED: AtomicUsize = AtomicUsize::new(0);
+static LAST_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+/// Installs the handlers of SIGINT and SIGTERM, or of Ctrl-C and Ctrl-Break on Windows.
+#[inline(always)]
+pub fn handle_interrupts() -> Result<()> {
+    os_signal::install()
+}
+
+/// Number of signals received since [`handle_interrupts`].
+#[inline(always)]
+pub fn interrupt_count() -> usize {
+    RECEIVED.load(Ordering::SeqCst)
+}
+
+/// The last signal received, Ctrl-C and Ctrl-Break count as SIGINT on Windows.
+#[inline(always)]
+pub fn interrupt_signal() -> Option<i32> {
+    Some(LAST_SIGNAL.load(Ordering::SeqCst)).filter(|&signal| signal != 0)
+}
+
+/// Records `signal`, and returns how many signals were received with it. Only async-signal-safe atomics here.
+#[inline(always)]
+fn record(signal: i32) -> usize {
+    LAST_SIGNAL.store(signal, Ordering::SeqCst);
+    RECEIVED.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+#[cfg(unix)]
+#[allow(non_camel_case_types)]
+mod os_signal {
+    use std::ffi::c_int;
+    use std::io::{Error, Result};
+
+    type sighandler_t = usize;
+    const SIG_DFL: sighandler_t = 0;
+    const SIG_ERR: sighandler_t = !0;
+    const SIGINT: c_int = 2;
+    const SIGTERM: c_int = 15;
+    unsafe extern "C" {
+        fn signal(signum: c_int, handler: sighandler_t) -> sighandler_t;
+        fn siginterrupt(signum: c_int, flag: c_int) -> c_int;
+    }
+
+    extern "C" fn on_signal(signum: c_int) {
+        if super::record(signum) >= 2 {
+            unsafe {
+                signal(SIGINT, SIG_DFL);
+                signal(SIGTERM, SIG_DFL);
+            }
+        }
+    }
+
+    pub(super) fn install() -> Result<()> {
+        for signum in [SIGINT, SIGTERM] {
+            if unsafe { signal(signum, on_signal as extern "C" fn(c_int) as sighandler_t) } == SIG_ERR {
+                return Err(Error::last_os_error());
+            }
+            // Blocking calls like waitpid fail with EINTR instead of being restarted, so waiting on cleaners
+            // notices the signal.
+            if unsafe { siginterrupt(signum, 1) } != 0 {
+                return Err(Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+#[allow(clippy::upper_case_acronyms)]
+mod os_signal {
+    use std::ffi::{c_int, c_ulong};
+    use std::io::{Error, Result};
+
+    type DWORD = c_ulong;
+    type BOOL = c_int;
+    const CTRL_C_EVENT: DWORD = 0;
+    const CTRL_BREAK_EVENT: DWORD = 1;
+    const SIGINT: i32 = 2;
+    const TRUE: BOOL = 1;
+    const FALSE: BOOL = 0;
+    unsafe extern "system" {
+        fn SetConsoleCtrlHandler(handler: Option<unsafe extern "system" fn(DWORD) -> BOOL>, add: BOOL) -> BOOL;
+    }
+
+    /// Runs on a thread of its own, returning `FALSE` leaves the event to the default handler.
+    unsafe extern "system" fn on_ctrl(ctrl_type: DWORD) -> BOOL {
+        match ctrl_type {
+            CTRL_C_EVENT | CTRL_BREAK_EVENT if super::interrupt_count() < 2 => {
+                super::record(SIGINT);
+                TRUE
+            }
+            _ => FALSE,
+        }
+    }
+
+    pub(super) fn install() -> Result<()> {
+        // If the function fails, the return value is zero.
+        if unsafe { SetConsoleCtrlHandler(Some(on_ctrl), TRUE) } == 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+}
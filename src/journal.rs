@@ -0,0 +1,254 @@
+//! A record of the projects a run cleaned, so an interrupted run can be resumed.
+//!
+//! The journal of a run is a TOML file, appended to as the run goes: a `[run]` table with its roots, a
+//! `[[project]]` table every time a project starts or finishes cleaning, and if the run is interrupted, the
+//! directories it didn't scan yet as `[[frontier]]` tables. The `[end]` table is written last. Projects found but
+//! not finished are in it as pending, so resuming cleans them before scanning the frontier.
+//!
+//! A run that crashed or was killed leaves a journal without an `[end]`, maybe with its last entry cut short.
+//! Resuming it cleans the projects it didn't finish, then scans its roots again for those it never got to.
+
+use crate::cargo::CargoTarget;
+use crate::lock::key;
+use crate::toml::{self, Table, Value};
+use crate::{PendingDir, Project, RuleKind, RuleSet};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Where a project of the journal is at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProjectState {
+    /// Found, or started and not finished yet.
+    Pending,
+    Done,
+    /// Its cleaner failed, resuming tries again.
+    Failed,
+}
+
+impl ProjectState {
+    #[inline(always)]
+    fn name(self) -> &'static str {
+        match self {
+            ProjectState::Pending => "pending",
+            ProjectState::Done => "done",
+            ProjectState::Failed => "failed",
+        }
+    }
+}
+
+/// The journal a run appends to, see the [module documentation](self).
+#[derive(Debug)]
+pub struct Journal {
+    file: File,
+}
+
+/// What an interrupted run left to do.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Resume {
+    /// Found and not cleaned, or their cleaner failed.
+    pub projects: Vec<Project>,
+    /// Not scanned yet.
+    pub dirs: Vec<PendingDir>,
+    /// The rules and canonical roots, or target directories for Cargo, of the projects the journal lists, left out
+    /// when the scan finds them again. Only set when the run stopped without saving where it was, and its roots are
+    /// scanned again.
+    pub known: HashSet<(String, PathBuf)>,
+}
+
+impl Resume {
+    /// Whether the journal lists `project`, or another member of its workspace.
+    #[inline(always)]
+    pub fn is_known(&self, project: &Project) -> bool {
+        !self.known.is_empty() && self.known.contains(&identity(project))
+    }
+}
+
+impl Journal {
+    /// `$XDG_STATE_HOME/code-clean/journal/<key>.toml`, the journal of the runs cleaning `roots`.
+    #[inline(always)]
+    pub fn default_path(roots: &[PathBuf]) -> Option<PathBuf> {
+        Some(state_dir()?.join("journal").join(format!("{}.toml", key(roots))))
+    }
+
+    /// Starts the journal of a run at `path`, replacing the last one. `pending` are the projects carried over from
+    /// the run it resumes.
+    pub fn create(path: &Path, roots: &[PathBuf], pending: &[Project]) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let secs = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+        let roots: Vec<_> = roots.iter().map(|root| quote_path(root)).collect();
+        let mut header = format!("[run]\nroots = [{}]\nstarted = {secs}\n", roots.join(", "));
+        for project in pending {
+            header.push_str(&project_entry(project, ProjectState::Pending));
+        }
+        // Renamed over the last journal, which stays whole if this run stops right away.
+        let partial = path.with_extension("toml.partial");
+        fs::write(&partial, header)?;
+        fs::rename(&partial, path)?;
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Self { file })
+    }
+
+    /// Records where `project` is at.
+    #[inline(always)]
+    pub fn record(&mut self, project: &Project, state: ProjectState) -> Result<()> {
+        self.file.write_all(project_entry(project, state).as_bytes())
+    }
+
+    /// Records what an interrupted scan had left, see [`Scanner::stop`](crate::Scanner::stop).
+    pub fn record_frontier(&mut self, dirs: &[PendingDir], projects: &[Project]) -> Result<()> {
+        let mut out = BufWriter::new(&self.file);
+        for project in projects {
+            out.write_all(project_entry(project, ProjectState::Pending).as_bytes())?;
+        }
+        for dir in dirs {
+            let owned: Vec<_> = dir.owned.iter().map(|output| quote_path(output)).collect();
+            write!(out, "\n[[frontier]]\npath = {}\nowned = [{}]\n", quote_path(&dir.path), owned.join(", "))?;
+        }
+        out.flush()
+    }
+
+    /// Ends the journal, a run that wasn't `interrupted` leaves nothing to resume.
+    #[inline(always)]
+    pub fn end(mut self, interrupted: bool) -> Result<()> {
+        write!(self.file, "\n[end]\ninterrupted = {interrupted}\n")
+    }
+
+    /// Reads what the run that wrote the journal at `path` left to do, `None` if it finished. Projects are matched
+    /// to `rules` by name, those of rules that don't exist anymore are left out.
+    pub fn load(path: &Path, rules: &RuleSet) -> Result<Option<Resume>> {
+        let src = match fs::read_to_string(path) {
+            Ok(src) => src,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return Err(Error::new(ErrorKind::NotFound, "there is no run of these paths to resume"));
+            }
+            Err(err) => return Err(err),
+        };
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidData, format!("{}: {msg}", path.display()));
+        let read = |src: &str| toml::parse(src).map_err(|err| err.to_string()).and_then(|doc| read(&doc, rules));
+        let resume = read(&src);
+        // Only the last entry of a run that didn't end can be cut short, the ones before were appended whole.
+        let resume = match src.rfind("\n[") {
+            Some(last) if resume.is_err() && !src.contains("\n[end]\n") => read(&src[..last]),
+            _ => resume,
+        };
+        resume.map_err(invalid)
+    }
+}
+
+/// What the run of a journal left to do, see [`Journal::load`].
+fn read(document: &Table, rules: &RuleSet) -> std::result::Result<Option<Resume>, String> {
+    let mut resume = Resume::default();
+    let ended = match document.get("end") {
+        Some(Value::Table(end)) if end.get("interrupted") == Some(&Value::Boolean(false)) => return Ok(None),
+        Some(_) => true,
+        None => false,
+    };
+    // The last state of every project wins, in the order they were found, but a cleaned one stays done. A killed
+    // run may list the projects of its frontier again, and so the members of workspaces it cleaned.
+    let mut states: Vec<(Project, bool)> = Vec::new();
+    let mut known: HashMap<(String, PathBuf), usize> = HashMap::new();
+    for entry in tables(document, "project") {
+        let (Some(name), Some(root), Some(marker), Some(state)) =
+            (string(entry, "rule"), string(entry, "root"), string(entry, "marker"), string(entry, "state"))
+        else {
+            return Err("a [[project]] lacks its rule, root, marker or state".into());
+        };
+        let Some(rule) = rules.rules().iter().find(|rule| rule.name == name) else { continue };
+        let unfinished = state != ProjectState::Done.name();
+        let project = Project { kind: rule.clone(), root: root.into(), marker: marker.into() };
+        match known.entry(identity(&project)) {
+            Entry::Occupied(index) => states[*index.get()].1 &= unfinished,
+            Entry::Vacant(entry) => {
+                entry.insert(states.len());
+                states.push((project, unfinished));
+            }
+        }
+    }
+    if !ended {
+        let Some(Value::Table(run)) = document.get("run") else { return Err("the [run] is missing".into()) };
+        let Some(Value::Array(roots)) = run.get("roots") else { return Err("the [run] lacks its roots".into()) };
+        let roots = roots.iter().filter_map(|root| match root {
+            Value::String(root) => Some(PendingDir { path: root.into(), owned: Vec::new() }),
+            _ => None,
+        });
+        resume.dirs.extend(roots);
+        resume.known = known.into_keys().collect();
+    }
+    resume.projects = states.into_iter().filter(|&(_, unfinished)| unfinished).map(|(project, _)| project).collect();
+    for entry in tables(document, "frontier") {
+        let Some(path) = string(entry, "path") else { return Err("a [[frontier]] lacks its path".into()) };
+        let owned = match entry.get("owned") {
+            Some(Value::Array(owned)) => owned.iter().filter_map(|output| match output {
+                Value::String(output) => Some(PathBuf::from(output)),
+                _ => None,
+            }),
+            _ => return Err("a [[frontier]] lacks its owned outputs".into()),
+        };
+        resume.dirs.push(PendingDir { path: path.into(), owned: owned.collect() });
+    }
+    Ok(Some(resume))
+}
+
+/// The rule of `project` and what it cleans: its canonical root, or the target directory of a Cargo project, which
+/// the other members of its workspace share.
+fn identity(project: &Project) -> (String, PathBuf) {
+    let target = match project.kind.kind {
+        Some(RuleKind::Cargo) => CargoTarget::resolve(&project.marker).ok().map(|target| target.dir),
+        _ => None,
+    };
+    let path = target.unwrap_or_else(|| project.root.clone());
+    let path = fs::canonicalize(&path).unwrap_or(path);
+    (project.kind.name.clone(), path)
+}
+
+#[inline(always)]
+fn project_entry(project: &Project, state: ProjectState) -> String {
+    format!(
+        "\n[[project]]\nstate = \"{}\"\nrule = {}\nroot = {}\nmarker = {}\n",
+        state.name(),
+        toml::quote(&project.kind.name),
+        quote_path(&project.root),
+        quote_path(&project.marker)
+    )
+}
+
+/// Paths that aren't valid UTF-8 are written lossily, resuming doesn't find them.
+#[inline(always)]
+fn quote_path(path: &Path) -> String {
+    toml::quote(&path.to_string_lossy())
+}
+
+#[inline(always)]
+fn tables<'a>(document: &'a Table, key: &str) -> impl Iterator<Item = &'a Table> {
+    let entries = match document.get(key) {
+        Some(Value::Array(entries)) => entries.as_slice(),
+        _ => &[],
+    };
+    entries.iter().filter_map(|entry| match entry {
+        Value::Table(table) => Some(table),
+        _ => None,
+    })
+}
+
+#[inline(always)]
+fn string<'a>(table: &'a Table, key: &str) -> Option<&'a str> {
+    match table.get(key) {
+        Some(Value::String(s)) => Some(s),
+        _ => None,
+    }
+}
+
+/// `$XDG_STATE_HOME/code-clean`, or `~/.local/state/code-clean`.
+pub(crate) fn state_dir() -> Option<PathBuf> {
+    let state_home = env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()).map(PathBuf::from).or_else(|| {
+        env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")).map(|home| Path::new(&home).join(".local/state"))
+    })?;
+    Some(state_home.join("code-clean"))
+}
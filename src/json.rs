@@ -0,0 +1,132 @@
+//! A minimal JSON writer for `--format json` and `--format ndjson`.
+
+use code_clean::DiskUsage;
+use std::fmt::{Display, Write};
+use std::path::Path;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+/// Version of the event schema, bumped whenever a field changes meaning or is removed.
+pub(crate) const SCHEMA_VERSION: u64 = 1;
+
+/// Builds a JSON object, one field at a time.
+pub(crate) struct JsonObject(String);
+
+impl JsonObject {
+    #[inline(always)]
+    pub(crate) fn new() -> Self {
+        Self(String::from("{"))
+    }
+
+    #[inline(always)]
+    fn key(&mut self, key: &str) {
+        if self.0.len() > 1 {
+            self.0.push(',');
+        }
+        escape(&mut self.0, key);
+        self.0.push(':');
+    }
+
+    #[inline(always)]
+    pub(crate) fn str(mut self, key: &str, value: &str) -> Self {
+        self.key(key);
+        escape(&mut self.0, value);
+        self
+    }
+
+    #[inline(always)]
+    pub(crate) fn path(self, key: &str, value: &Path) -> Self {
+        self.str(key, &value.to_string_lossy())
+    }
+
+    #[inline(always)]
+    pub(crate) fn opt_path(self, key: &str, value: Option<&Path>) -> Self {
+        match value {
+            Some(value) => self.path(key, value),
+            None => self.raw(key, "null"),
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn num(mut self, key: &str, value: impl Display) -> Self {
+        self.key(key);
+        write!(self.0, "{value}").expect("Writing to a String never fails");
+        self
+    }
+
+    #[inline(always)]
+    pub(crate) fn bool(self, key: &str, value: bool) -> Self {
+        self.raw(key, if value { "true" } else { "false" })
+    }
+
+    #[inline(always)]
+    pub(crate) fn strs<S: AsRef<str>>(mut self, key: &str, values: impl IntoIterator<Item = S>) -> Self {
+        self.key(key);
+        self.0.push('[');
+        for (i, value) in values.into_iter().enumerate() {
+            if i > 0 {
+                self.0.push(',');
+            }
+            escape(&mut self.0, value.as_ref());
+        }
+        self.0.push(']');
+        self
+    }
+
+    /// `value` must already be valid JSON.
+    #[inline(always)]
+    pub(crate) fn raw(mut self, key: &str, value: &str) -> Self {
+        self.key(key);
+        self.0.push_str(value);
+        self
+    }
+
+    #[inline(always)]
+    pub(crate) fn usage(self, key: &str, usage: DiskUsage) -> Self {
+        let usage = JsonObject::new().num("apparent_bytes", usage.apparent).num("allocated_bytes", usage.allocated);
+        self.raw(key, &usage.finish())
+    }
+
+    #[inline(always)]
+    pub(crate) fn duration(self, key: &str, duration: Duration) -> Self {
+        self.num(key, duration.as_secs_f64())
+    }
+
+    /// `exit_code` and `signal`, either can be `null`.
+    pub(crate) fn exit_status(self, status: ExitStatus) -> Self {
+        #[cfg(unix)]
+        let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+        #[cfg(not(unix))]
+        let signal: Option<i32> = None;
+        let this = match status.code() {
+            Some(code) => self.num("exit_code", code),
+            None => self.raw("exit_code", "null"),
+        };
+        match signal {
+            Some(signal) => this.num("signal", signal),
+            None => this.raw("signal", "null"),
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn finish(mut self) -> String {
+        self.0.push('}');
+        self.0
+    }
+}
+
+fn escape(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => write!(out, "\\u{:04x}", c as u32).expect("Writing to a String never fails"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
@@ -0,0 +1,42 @@
+//! Finds build projects in a directory tree and cleans their build outputs.
+//!
+//! The [`Scanner`] walks a tree and yields the [`Project`]s detected by a [`RuleSet`], each [`Rule`] is a
+//! [`Cleaner`] that plans an [`Action`], and the [`ChildrenManager`] runs those actions in parallel while
+//! reporting [`Event`]s to an [`EventHandler`].
+
+mod activity;
+mod budget;
+mod busy;
+mod cargo;
+mod cleaner;
+mod drain;
+mod duration;
+mod interrupt;
+mod journal;
+mod lock;
+mod manager;
+mod project;
+mod quarantine;
+mod reactor;
+mod removal;
+mod rule;
+mod sandbox;
+mod scanner;
+mod size;
+mod toml;
+mod trash;
+
+pub use activity::{ActivitySource, last_activity, recent_activity};
+pub use budget::{Candidate, DiskBudget, rank};
+pub use cleaner::{Action, Cleaner};
+pub use duration::{format_duration, parse_duration};
+pub use interrupt::{handle_interrupts, interrupt_count, interrupt_signal};
+pub use journal::{Journal, ProjectState, Resume};
+pub use lock::RootLock;
+pub use manager::{ChildrenManager, Disposal, Event, EventHandler, MAX_KIDS, Options};
+pub use project::Project;
+pub use quarantine::{Quarantine, QuarantineEntry};
+pub use rule::{Fallback, Rule, RuleAction, RuleKind, RuleSet};
+pub use scanner::{PendingDir, ScanError, Scanner};
+pub use size::{Bytes, DiskUsage, SizeWalker, parse_size};
+pub use trash::move_to_trash;
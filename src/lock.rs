@@ -0,0 +1,98 @@
+//! Keeping two code-clean runs out of the same tree, like a cron job and someone running it by hand.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Error, ErrorKind, Result, Write};
+use std::path::{Path, PathBuf};
+
+/// Locks on the roots of a run, released when it is dropped.
+///
+/// Every directory has a lock file named after its canonical path in `$XDG_RUNTIME_DIR/code-clean`. A run locks
+/// those of its roots exclusively and those of their ancestors shared, so another run fails to start on the same
+/// roots, on a directory inside them or on one they are in.
+#[derive(Debug)]
+pub struct RootLock {
+    /// The locks are held until the files are closed.
+    _files: Vec<File>,
+}
+
+impl RootLock {
+    /// Locks every root, fails if one of them overlaps with the roots of another run.
+    pub fn acquire(roots: &[PathBuf]) -> Result<Self> {
+        let dir =
+            lock_dir().ok_or_else(|| Error::other("can't find a directory for lock files, set XDG_RUNTIME_DIR"))?;
+        fs::create_dir_all(&dir)?;
+        // Whether each directory is a root, once, a lock file can't be locked twice by the same run.
+        let mut locks = BTreeMap::new();
+        for root in roots {
+            let root = fs::canonicalize(root).unwrap_or_else(|_| root.clone());
+            for ancestor in root.ancestors().skip(1) {
+                locks.entry(ancestor.to_path_buf()).or_insert(false);
+            }
+            locks.insert(root, true);
+        }
+        // The roots first, so a run of the same root is told about it.
+        let mut locks: Vec<_> = locks.into_iter().collect();
+        locks.sort_by_key(|&(_, is_root)| !is_root);
+        let mut files = Vec::with_capacity(locks.len());
+        for (locked, is_root) in locks {
+            let path = dir.join(format!("{}.lock", key(std::slice::from_ref(&locked))));
+            let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+            let result = if is_root { file.try_lock() } else { file.try_lock_shared() };
+            match result {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => {
+                    let what = if is_root { "or a directory in it" } else { "which contains one of the paths" };
+                    let msg = format!(
+                        "another code-clean is already cleaning {} {what} (locked {})",
+                        locked.display(),
+                        path.display()
+                    );
+                    return Err(Error::new(ErrorKind::WouldBlock, msg));
+                }
+                Err(TryLockError::Error(err)) => return Err(err),
+            }
+            if is_root {
+                // Only to tell which root a lock file is for.
+                file.set_len(0)?;
+                writeln!(file, "{}", locked.display())?;
+            }
+            files.push(file);
+        }
+        Ok(Self { _files: files })
+    }
+}
+
+/// A file name for `paths`, which is the same for the same paths in every run.
+pub(crate) fn key(paths: &[PathBuf]) -> String {
+    // FNV-1a, `DefaultHasher` may change between Rust releases.
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for path in paths {
+        for &byte in path.as_os_str().as_encoded_bytes().iter().chain(&[0]) {
+            hash = (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    format!("{hash:016x}")
+}
+
+/// `$XDG_RUNTIME_DIR/code-clean`. Cron jobs usually run without `XDG_RUNTIME_DIR`, so on Linux it falls back to
+/// the directory it usually is, `/run/user/<uid>`, and then to `$XDG_STATE_HOME/code-clean/locks`.
+fn lock_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        return Some(Path::new(&dir).join("code-clean"));
+    }
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        // Owned by the effective user id, like everything of this process.
+        if let Ok(metadata) = fs::metadata("/proc/self") {
+            let dir = PathBuf::from(format!("/run/user/{}", metadata.uid()));
+            if dir.is_dir() {
+                return Some(dir.join("code-clean"));
+            }
+        }
+    }
+    Some(crate::journal::state_dir()?.join("locks"))
+}
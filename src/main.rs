@@ -1,191 +1,238 @@
-use std::ffi::OsStr;
-use std::process::ChildStderr;
+mod cli;
+mod json;
+mod tui;
+
+use cli::{Args, Format, Parsed, Subcommand, USAGE, Verbosity};
+use code_clean::{
+    Action, Bytes, Candidate, ChildrenManager, DiskBudget, DiskUsage, Disposal, Event, EventHandler, Journal, Options,
+    PendingDir, Project, ProjectState, Quarantine, Resume, RootLock, RuleSet, ScanError, Scanner, SizeWalker,
+    format_duration, handle_interrupts, interrupt_count, interrupt_signal, rank,
+};
+use json::JsonObject;
 use std::{
+    collections::HashSet,
     env::{self, current_dir},
-    fs,
-    io::{self, Error, Read, Result, Write},
-    path::{Path, PathBuf},
-    process::{Child, Command, ExitStatus, Stdio},
-    str::FromStr,
+    io::{self, Error, ErrorKind, Result, Write},
+    iter, mem,
+    path::Path,
+    process::{ExitCode, ExitStatus},
+    time::{Instant, SystemTime},
 };
 
-macro_rules! try_continue {
-    ($stderr_manager:expr, $expr:expr, $path:ident) => {
-        match $expr {
-            Ok(val) => val,
-            Err(err) => {
-                $stderr_manager.log_err(&$path, err)?;
-                continue;
-            }
-        }
-    };
-}
-
-// We don't want to overwhelm the system with open files
-const MAX_KIDS: usize = 512 + 256;
+type ScanResult = std::result::Result<Project, ScanError>;
 
+/// Failures of cleaners that only mean there was nothing to clean.
 #[inline(always)]
-fn should_ignore(path: &Path) -> bool {
-    const IGNORE_LIST: &[&str] = &["node_modules"];
-    IGNORE_LIST.iter().any(|&ignore| path.ends_with(ignore))
+fn is_ignored_failure(child_stderr: &str) -> bool {
+    const IGNORE_LIST: &[&str] = &["No rule to make target"];
+    IGNORE_LIST.iter().any(|&s| child_stderr.contains(s))
 }
 
-#[inline(always)]
-fn is_hidden(path: &Path) -> bool {
-    path.file_name().and_then(OsStr::to_str).map(|s| s.starts_with('.')).unwrap_or(false)
-}
+// Exit status of a dry run that found something to clean (1 is already used for errors).
+const DRY_RUN_PENDING: u8 = 2;
+// Exit status after a signal stopped the cleaning, once the running cleaners finished.
+const INTERRUPTED: u8 = 3;
 
-struct ChildrenManager {
-    kids: Vec<ChildProcess>,
-    max_kids: usize,
+/// Prints the events of the [`ChildrenManager`], and the results of `scan` and `report`.
+struct Reporter {
     stdout: io::StdoutLock<'static>,
     stderr: StdErrManager,
-    log_command: bool,
+    verbosity: Verbosity,
+    format: Format,
+    /// `[kind] root: reason` of every skipped project, for the summary.
+    skipped: Vec<String>,
+    /// Cleaners that failed, and errors.
+    failed: usize,
+    errors: usize,
+    /// Events buffered until the summary with `--format json`.
+    events: Vec<String>,
+    /// Where the cleaned projects are recorded, see [`Journal`].
+    journal: Option<Journal>,
 }
 
-impl ChildrenManager {
+impl Reporter {
     #[inline(always)]
-    fn new(cap: usize, log_command: bool) -> Self {
+    fn new(verbosity: Verbosity, format: Format) -> Self {
         Self {
-            kids: Vec::with_capacity(cap),
-            max_kids: cap,
             stdout: io::stdout().lock(),
             stderr: StdErrManager::new(),
-            log_command,
+            verbosity,
+            format,
+            skipped: Vec::new(),
+            failed: 0,
+            errors: 0,
+            events: Vec::new(),
+            journal: None,
         }
     }
-    #[inline(always)]
-    fn push_wait(&mut self, kid: ChildProcess) -> Result<()> {
-        // IMPORTANT: Add the child FIRST, before calling wait_remove.
-        // Otherwise, waitpid could return this child's PID before we track it.
-        self.kids.push(kid);
 
-        // Now enforce the limit
-        if self.kids.len() >= self.max_kids {
-            self.try_wait_remove()?;
-        }
-        // If no sub-processes have exited yet, we have to wait for one to exit.
-        if self.kids.len() >= self.max_kids {
-            self.wait_remove()?;
-        }
-        Ok(())
+    #[inline(always)]
+    fn is_text(&self) -> bool {
+        self.format == Format::Text
     }
 
+    /// Starts a JSON event of type `kind`.
     #[inline(always)]
-    fn try_wait_remove(&mut self) -> Result<()> {
-        let mut i = 0;
-        while i < self.kids.len() {
-            if self.kids[i].try_wait_log(&mut self.stderr)? {
-                self.kids.swap_remove(i);
-            } else {
-                i += 1;
-            }
+    fn object(&self, kind: &str) -> JsonObject {
+        match self.format {
+            // Every line stands on its own, the JSON document has the version at its top.
+            Format::Ndjson => JsonObject::new().num("schema_version", json::SCHEMA_VERSION).str("type", kind),
+            _ => JsonObject::new().str("type", kind),
         }
-        Ok(())
     }
 
     #[inline(always)]
-    fn wait_remove(&mut self) -> Result<()> {
-        match os_wait::wait_on_children(&self.kids) {
-            Err(err) => self.stderr.log_os_err(err),
-            Ok((status, idx)) => self.kids.swap_remove(idx).log_output(status, &mut self.stderr),
-        }
+    fn project_object(&self, kind: &str, project: &Project) -> JsonObject {
+        self.object(kind)
+            .path("path", &project.root)
+            .path("marker", &project.marker)
+            .str("ecosystem", &project.kind.name)
     }
 
     #[inline(always)]
-    fn handle_path(&mut self, path: &Path) -> Result<()> {
-        let child = path
-            .file_name()
-            .and_then(OsStr::to_str)
-            .and_then(|file_name| match file_name {
-                "Cargo.toml" => Some(self.new_child_cargo_clean(path)),
-                "Makefile" => Some(self.new_child_make_clean(path)),
-                "build.ninja" => Some(self.new_child_ninja_clean(path)),
-                "gradlew" => Some(self.new_child_gradlew_clean(path)),
-                ".git" => Some(self.new_child_git_clean(path)),
-                "package.json" => {
-                    self.new_child_node_modules(&path.with_file_name("node_modules")).map(|_| None).transpose()
-                }
-                _ => None,
-            })
-            .transpose()?;
-        if let Some(child) = child {
-            self.push_wait(child)?;
+    fn emit(&mut self, object: JsonObject) -> Result<()> {
+        match self.format {
+            Format::Text => Ok(()),
+            Format::Json => {
+                self.events.push(object.finish());
+                Ok(())
+            }
+            Format::Ndjson => writeln!(&mut self.stdout, "{}", object.finish()),
         }
-        Ok(())
     }
 
-    #[inline(always)]
-    fn print_command(&mut self, program: &str, args: &[&OsStr], path: &Path) -> Result<()> {
-        if !self.log_command {
-            return Ok(());
-        }
-        write!(&mut self.stdout, "[{path}]: {program} ", path = path.display())?;
-        for arg in args.iter().map(|s| s.to_str().expect("Expect valid utf-8")) {
-            write!(&mut self.stdout, "{arg}")?;
+    /// Ends the output with a summary, the JSON document is only written here.
+    fn summary(&mut self, summary: JsonObject) -> Result<()> {
+        let summary = summary.num("errors", self.errors);
+        match self.format {
+            Format::Text => Ok(()),
+            Format::Json => {
+                let events = format!("[{}]", self.events.join(","));
+                let document = JsonObject::new()
+                    .num("schema_version", json::SCHEMA_VERSION)
+                    .raw("events", &events)
+                    .raw("summary", &summary.finish());
+                writeln!(&mut self.stdout, "{}", document.finish())
+            }
+            Format::Ndjson => self.emit(summary),
         }
-        writeln!(&mut self.stdout)
     }
 
-    #[inline(always)]
-    fn new_child_node_modules(&mut self, path: &Path) -> Result<()> {
-        assert!(path.ends_with("node_modules"));
-        // use symlink_metadata to make sure it's a directory and not follow the symlink
-        if !path.exists() || !fs::symlink_metadata(path)?.is_dir() {
-            return Ok(());
-        }
-        if self.log_command {
-            writeln!(&mut self.stdout, "[{path}]: rm -rf ", path = path.display())?;
+    fn log_err(&mut self, path: Option<&Path>, error: &Error) -> Result<()> {
+        self.errors += 1;
+        match (self.format, path) {
+            (Format::Text, Some(path)) => self.stderr.log_err(&path, error),
+            (Format::Text, None) => self.stderr.log_os_err(error),
+            _ => {
+                let object = self.object("error").opt_path("path", path).str("message", &error.to_string());
+                self.emit(object)
+            }
         }
-        fs::remove_dir_all(path)
     }
+}
 
+impl EventHandler for Reporter {
     #[inline(always)]
-    fn new_child(&mut self, program: &str, args: &[&OsStr], path: &Path) -> Result<ChildProcess> {
-        self.print_command(program, args, path)?;
-        ChildProcess::new(program, args, path)
-    }
-    #[inline(always)]
-    fn new_child_make_clean(&mut self, path: &Path) -> Result<ChildProcess> {
-        self.new_child("make", &["clean".as_ref()], path)
-    }
-    #[inline(always)]
-    fn new_child_gradlew_clean(&mut self, path: &Path) -> Result<ChildProcess> {
-        self.new_child("./gradlew", &["clean".as_ref()], path)
-    }
-    #[inline(always)]
-    fn new_child_ninja_clean(&mut self, path: &Path) -> Result<ChildProcess> {
-        self.new_child("ninja", &["clean".as_ref()], path)
-    }
-    #[inline(always)]
-    fn new_child_cargo_clean(&mut self, path: &Path) -> Result<ChildProcess> {
-        self.new_child("cargo", &["clean".as_ref(), "--manifest-path".as_ref(), path.as_ref()], path)
-    }
-    #[inline(always)]
-    fn new_child_git_clean(&mut self, path: &Path) -> Result<ChildProcess> {
-        self.new_child("git", &["gc".as_ref()], path)
+    fn on_event(&mut self, event: Event<'_>) -> Result<()> {
+        if let (Some(journal), Event::Spawn { project, .. }) = (&mut self.journal, &event) {
+            journal.record(project, ProjectState::Pending)?;
+        }
+        match event {
+            Event::Planned { project, action } if self.is_text() => {
+                writeln!(&mut self.stdout, "[{}] {}: {action}", project.kind, project.root.display())
+            }
+            Event::Planned { project, action } => {
+                let object = with_action(self.project_object("planned", project), action);
+                self.emit(object)
+            }
+            Event::Skipped { project, reason } => {
+                self.skipped.push(format!("[{}] {}: {reason}", project.kind, project.root.display()));
+                let object = self.project_object("skipped", project).str("reason", reason);
+                self.emit(object)
+            }
+            Event::Spawn { .. } if self.is_text() && self.verbosity < Verbosity::Verbose => Ok(()),
+            Event::Spawn { project, action } if self.is_text() => {
+                writeln!(&mut self.stdout, "[{path}]: {action}", path = project.marker.display())
+            }
+            Event::Spawn { project, action } => {
+                let object = with_action(self.project_object("spawn", project), action);
+                self.emit(object)
+            }
+            Event::Finish { project, status, stderr, duration, timed_out, freed } => {
+                let failed = status.filter(|status| timed_out || (!status.success() && !is_ignored_failure(stderr)));
+                self.failed += failed.is_some() as usize;
+                if let Some(journal) = &mut self.journal {
+                    journal
+                        .record(project, if failed.is_some() { ProjectState::Failed } else { ProjectState::Done })?;
+                }
+                if self.is_text() {
+                    if let Some(status) = failed {
+                        if timed_out {
+                            let msg = format!("timed out after {}, {status}", format_duration(duration));
+                            self.stderr.log_err(&project.root, Error::new(ErrorKind::TimedOut, msg))?;
+                        } else {
+                            self.stderr.log_child_stderr(&project.root, status, stderr)?;
+                        }
+                    }
+                    if self.verbosity < Verbosity::Normal {
+                        return Ok(());
+                    }
+                    return writeln!(&mut self.stdout, "[{}] {}: freed {freed}", project.kind, project.root.display());
+                }
+                let mut object = self.project_object("finish", project);
+                object = match status {
+                    Some(status) => object.exit_status(status),
+                    None => object.raw("exit_code", "null").raw("signal", "null"),
+                };
+                object = object.bool("success", failed.is_none()).bool("timed_out", timed_out);
+                if status.is_some_and(|status| !status.success()) {
+                    object = object.str("stderr", stderr);
+                }
+                let object = object.duration("duration_secs", duration).usage("freed", freed);
+                self.emit(object)
+            }
+            Event::Moved { .. } if self.is_text() && self.verbosity < Verbosity::Normal => Ok(()),
+            Event::Moved { project, from, to } if self.is_text() => writeln!(
+                &mut self.stdout,
+                "[{}] {}: moved {} to {}",
+                project.kind,
+                project.root.display(),
+                from.display(),
+                to.display()
+            ),
+            Event::Moved { project, from, to } => {
+                let object = self.project_object("moved", project).path("from", from).path("to", to);
+                self.emit(object)
+            }
+            Event::Error { path, error } => self.log_err(path, error),
+            Event::Warning { message } if self.is_text() => self.stderr.log_warning(message),
+            Event::Warning { message } => {
+                let object = self.object("warning").str("message", message);
+                self.emit(object)
+            }
+        }
     }
 }
 
-impl Drop for ChildrenManager {
-    #[inline(always)]
-    fn drop(&mut self) {
-        // Wait on all sub-processes.
-        self.kids.drain(..).for_each(|child| {
-            child.wait_log(&mut self.stderr).expect("Failed to wait on child process while dropping ChildrenManager");
-        });
+/// Adds the `argv` and `workdir` of a command, or the directories an action deletes.
+#[inline(always)]
+fn with_action(object: JsonObject, action: &Action) -> JsonObject {
+    match action {
+        Action::Command { program, args, dir } => {
+            object.strs("argv", iter::once(program).chain(args).map(|arg| arg.to_string_lossy())).path("workdir", dir)
+        }
+        Action::Remove(paths) => object.strs("delete", paths.iter().map(|path| path.to_string_lossy())),
     }
 }
 
 struct StdErrManager {
     stderr: io::StderrLock<'static>,
-    buf: String,
 }
 
 impl StdErrManager {
     #[inline(always)]
     fn new() -> Self {
-        Self { stderr: io::stderr().lock(), buf: String::with_capacity(256) }
+        Self { stderr: io::stderr().lock() }
     }
 
     #[inline(always)]
@@ -196,231 +243,362 @@ impl StdErrManager {
     fn log_os_err(&mut self, err: impl std::error::Error) -> Result<()> {
         writeln!(&mut self.stderr, "Operating System Error: {err}")
     }
+    #[inline(always)]
+    fn log_warning(&mut self, message: &str) -> Result<()> {
+        writeln!(&mut self.stderr, "Warning: {message}")
+    }
 
     #[inline(always)]
-    fn log_child_stderr(
-        &mut self,
-        path: &impl AsRef<Path>,
-        status: ExitStatus,
-        child_stderr: &mut Option<ChildStderr>,
-    ) -> Result<()> {
-        const IGNORE_LIST: &[&str] = &["No rule to make target"];
+    fn log_child_stderr(&mut self, path: &impl AsRef<Path>, status: ExitStatus, child_stderr: &str) -> Result<()> {
+        if is_ignored_failure(child_stderr) {
+            return Ok(()); // Ignore this error
+        }
+        self.log_err(path, Error::other(format!("{status}, stderr: {child_stderr}")))
+    }
+}
 
-        self.buf.clear();
-        if let Some(stderr_handler) = child_stderr
-            && let Err(err) = stderr_handler.read_to_string(&mut self.buf)
-        {
-            return self.log_err(path, err);
+fn main() -> ExitCode {
+    let args = match cli::parse(env::args_os().skip(1)) {
+        Ok(Parsed::Run(args)) => args,
+        Ok(Parsed::Help) => {
+            print!("{USAGE}");
+            return ExitCode::SUCCESS;
         }
-        if IGNORE_LIST.iter().any(|&s| self.buf.contains(s)) {
-            return Ok(()); // Ignore this error
+        Ok(Parsed::Version) => {
+            println!("code-clean {}", env!("CARGO_PKG_VERSION"));
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("error: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(&args) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
         }
-        self.log_err(path, Error::other(format!("{status}, stderr: {}", self.buf)))
     }
 }
 
-fn main() -> Result<()> {
-    let kids_limit = env::args()
-        .position(|a| a == "-j" || a == "--jobs")
-        .and_then(|pos| env::args().nth(pos + 1).map(|v| usize::from_str(&v).unwrap()))
-        .unwrap_or(MAX_KIDS);
-    println!("Using {kids_limit} jobs");
-    let is_log_out = env::var("LOG").map(|v| v == "1" || v == "true").unwrap_or(false);
-    let mut dirs = Vec::with_capacity(512);
-    let mut kids_manager = ChildrenManager::new(kids_limit, is_log_out);
-    dirs.push(current_dir()?);
-    //. Loop over subdirectories, this is a replacement of recursion. (to prevent stack overflow and smashing)
-    while let Some(dir) = dirs.pop() {
-        for entry in try_continue!(&mut kids_manager.stderr, fs::read_dir(&dir), dir) {
-            let entry = try_continue!(&mut kids_manager.stderr, entry, dir);
-            let path = entry.path();
-            let metadata = try_continue!(&mut kids_manager.stderr, entry.metadata(), path);
-            try_continue!(&mut kids_manager.stderr, kids_manager.handle_path(&path), path);
-            // This won't traverse symlinks, as `entry.metadata()` is the same as `symlink_metadata()`.
-            if metadata.is_dir() && !should_ignore(&path) && !is_hidden(&path) {
-                dirs.push(path);
-            }
+fn run(args: &Args) -> Result<ExitCode> {
+    match args.subcommand {
+        Subcommand::Clean => clean(args),
+        Subcommand::Scan => scan(args, projects(args)?),
+        Subcommand::Report => report(args, projects(args)?),
+        Subcommand::Tui => {
+            let _lock = RootLock::acquire(&args.roots)?;
+            tui::run(args, projects(args)?)
         }
+        Subcommand::Purge => purge(args, &quarantine(args)?),
+        Subcommand::Restore => restore(args, &quarantine(args)?),
     }
-    writeln!(kids_manager.stdout, "Waiting for child processes to finish")?;
-    // At the end wait for all currently running sub-processes to finish.
-    drop(kids_manager);
-    println!("Done");
-    Ok(())
 }
 
-struct ChildProcess {
-    child: Child,
-    path: PathBuf,
+/// Scans the roots with the rules configured for the current directory.
+fn projects(args: &Args) -> Result<impl Iterator<Item = ScanResult> + '_> {
+    let rules = RuleSet::load(&current_dir()?)?;
+    Ok(args.roots.iter().flat_map(move |root| Scanner::new(root.clone(), rules.clone())))
 }
 
-impl ChildProcess {
-    #[inline(always)]
-    fn new(program: &str, args: &[&OsStr], path: &Path) -> Result<Self> {
-        assert!(path.is_absolute());
-        let path = path.parent().unwrap();
-        Ok(Self {
-            child: Command::new(program)
-                .args(args)
-                .current_dir(path)
-                .stdout(Stdio::null())
-                .stderr(Stdio::piped())
-                .register_child()
-                .spawn()?,
-            path: path.into(),
-        })
+#[inline(always)]
+fn quarantine(args: &Args) -> Result<Quarantine> {
+    let dir = args.quarantine.clone().or_else(Quarantine::default_dir);
+    dir.map(Quarantine::new).ok_or_else(|| Error::other("can't find a home directory, use '--quarantine <DIR>'"))
+}
+
+/// How the cleaners run, as the arguments tell.
+fn options(args: &Args) -> Options {
+    let disposal = match &args.quarantine {
+        Some(dir) => Disposal::Quarantine(Quarantine::new(dir)),
+        None if args.trash => Disposal::Trash,
+        None => Disposal::Delete,
+    };
+    Options {
+        jobs: args.jobs,
+        dry_run: args.dry_run,
+        older_than: args.older_than,
+        activity: args.activity,
+        disposal,
+        timeout: args.timeout,
+        no_exec: args.no_exec,
+        sandbox: args.sandbox,
+        isolate_network: args.isolate_network,
+        roots: args.roots.clone(),
     }
+}
 
-    #[inline(always)]
-    fn try_wait_log(&mut self, stderr_manager: &mut StdErrManager) -> Result<bool> {
-        self.child
-            .try_wait()
-            .transpose()
-            .map_or_else(|| Ok(false), |res| self.log_res(stderr_manager, res).map(|()| true))
+fn clean(args: &Args) -> Result<ExitCode> {
+    let started = Instant::now();
+    handle_interrupts()?;
+    let normal = args.format == Format::Text && args.verbosity >= Verbosity::Normal;
+    let rules = RuleSet::load(&current_dir()?)?;
+    // A dry run doesn't touch anything, and `--free` picks the projects again every time.
+    let _lock = if args.dry_run { None } else { Some(RootLock::acquire(&args.roots)?) };
+    let journal_path = if args.dry_run || args.free.is_some() { None } else { Journal::default_path(&args.roots) };
+    let mut resume = match &journal_path {
+        Some(path) if args.resume => match Journal::load(path, &rules)? {
+            Some(resume) => resume,
+            None => {
+                if normal {
+                    println!("The last clean of these paths finished, there is nothing to resume");
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+        },
+        None if args.resume => return Err(Error::other("can't find a home directory to keep the journal in")),
+        _ => {
+            let dirs = args.roots.iter().map(|root| PendingDir { path: root.clone(), owned: Vec::new() }).collect();
+            Resume { projects: Vec::new(), dirs, known: HashSet::new() }
+        }
+    };
+    let journal = journal_path.map(|path| Journal::create(&path, &args.roots, &resume.projects)).transpose()?;
+    let mut scanner = Scanner::with_dirs(mem::take(&mut resume.dirs), rules);
+    // The projects the resumed run didn't clean come first.
+    let pending = mem::take(&mut resume.projects);
+    let found = scanner.by_ref().filter(|result| !matches!(result, Ok(project) if resume.is_known(project)));
+    let projects = pending.into_iter().map(Ok).chain(found);
+    if normal {
+        println!("Using {} jobs", args.jobs);
     }
-    #[inline(always)]
-    fn log_output(&mut self, status: ExitStatus, stderr_manager: &mut StdErrManager) -> Result<()> {
-        if status.success() {
-            Ok(())
-        } else {
-            stderr_manager.log_child_stderr(&self.path, status, &mut self.child.stderr)
+    let options = options(args);
+    let mut reporter = Reporter::new(args.verbosity, args.format);
+    reporter.journal = journal;
+    let mut kids_manager = ChildrenManager::new(options, reporter);
+    let budget = match args.free {
+        Some(target) => Some(free_space(args, DiskBudget::new(target, &args.roots)?, &mut kids_manager, projects)?),
+        None => {
+            for project in projects {
+                match project {
+                    Ok(project) => {
+                        kids_manager.handle_project(&project)?;
+                    }
+                    Err(err) => kids_manager.handler_mut().log_err(Some(&err.path), &err.error)?,
+                }
+            }
+            None
         }
+    };
+    // Whatever a signal kept the scan from yielding, to resume from.
+    let (dirs, found) = scanner.stop();
+    if let Some(journal) = &mut kids_manager.handler_mut().journal {
+        journal.record_frontier(&dirs, &found)?;
     }
-    #[inline(always)]
-    fn log_res(&mut self, stderr_manager: &mut StdErrManager, res: Result<ExitStatus>) -> Result<()> {
-        match res {
-            Err(err) => stderr_manager.log_err(&self.path, err),
-            Ok(status) => self.log_output(status, stderr_manager),
+    if normal {
+        writeln!(kids_manager.handler_mut().stdout, "Waiting for child processes to finish")?;
+    }
+    // At the end wait for all currently running sub-processes to finish.
+    kids_manager.wait_all()?;
+    let interrupted = interrupt_count() > 0;
+    let journaled = match kids_manager.handler_mut().journal.take() {
+        Some(journal) => journal.end(interrupted).map(|()| true)?,
+        None => false,
+    };
+    // A second signal killed the cleaners, exit like the signal would have.
+    if interrupt_count() >= 2 {
+        return Ok(ExitCode::from(128 + interrupt_signal().unwrap_or_default() as u8));
+    }
+    let (planned, freed) = (kids_manager.planned(), kids_manager.freed());
+    let gained = budget.as_ref().map(DiskBudget::gained).transpose()?;
+    let reporter = kids_manager.handler_mut();
+    if !reporter.is_text() {
+        let mut summary = reporter
+            .object("summary")
+            .bool("dry_run", args.dry_run)
+            .num("planned", planned)
+            .num("skipped", reporter.skipped.len())
+            .num("failed", reporter.failed)
+            .usage("freed", freed)
+            .duration("duration_secs", started.elapsed());
+        if let (Some(budget), Some(gained)) = (&budget, gained) {
+            summary = summary.num("free_target_bytes", budget.target()).num("free_gained_bytes", gained);
+        }
+        reporter.summary(summary.bool("interrupted", interrupted))?;
+    } else {
+        let skipped = &reporter.skipped;
+        if normal && !skipped.is_empty() {
+            println!("Skipped {} projects:", skipped.len());
+            skipped.iter().for_each(|line| println!("  {line}"));
+        }
+        if args.dry_run {
+            println!("Dry run: {planned} actions would have been performed");
+        } else if normal && (args.trash || args.quarantine.is_some()) {
+            println!("Moved {freed} out of the projects in total");
+        } else if normal {
+            println!("Freed {freed} in total");
+            if let (Some(budget), Some(gained)) = (&budget, gained) {
+                let (target, gained) = (Bytes(budget.target()), Bytes(gained));
+                if target.0 <= gained.0 {
+                    println!("Reached the target of {target}, {gained} more is available on disk");
+                } else {
+                    println!("Only {gained} more is available on disk, short of the target of {target}");
+                }
+            }
+        }
+        if normal && interrupted {
+            println!("Interrupted, the remaining projects were left alone");
+            if journaled {
+                println!("Run again with '--resume' to clean them");
+            }
+        }
+        if normal {
+            println!("Done");
         }
     }
-    #[inline(always)]
-    fn wait_log(mut self, stderr_manager: &mut StdErrManager) -> Result<()> {
-        let res = self.child.wait();
-        self.log_res(stderr_manager, res)
+    if interrupted {
+        return Ok(ExitCode::from(INTERRUPTED));
     }
+    if planned > 0 {
+        return Ok(ExitCode::from(DRY_RUN_PENDING));
+    }
+    Ok(ExitCode::SUCCESS)
 }
 
-trait RegisterChild {
-    fn register_child(&mut self) -> &mut Self;
-}
-
-#[cfg(unix)]
-mod os_wait {
-    use crate::{ChildProcess, RegisterChild};
-    use std::ffi::c_int;
-    use std::io::Result;
-    use std::os::unix::prelude::ExitStatusExt;
-    use std::os::unix::process::CommandExt;
-    use std::process::{Command, ExitStatus, abort};
-    use std::sync::atomic::{AtomicI32, Ordering};
-    #[allow(non_camel_case_types)]
-    type pid_t = i32;
-    unsafe extern "C" {
-        fn waitpid(pid: pid_t, wstatus: *mut c_int, options: c_int) -> pid_t;
-        fn getpgrp() -> pid_t;
-    }
-    fn get_pgid() -> pid_t {
-        static PGID: AtomicI32 = AtomicI32::new(-1);
-        let cur_pgid = PGID.load(Ordering::Relaxed);
-        if cur_pgid != -1 {
-            // Check if we already have a pgid
-            return cur_pgid;
+/// Cleans the largest and stalest projects first, until `budget` is reached or nothing is left to clean.
+fn free_space(
+    args: &Args,
+    budget: DiskBudget,
+    manager: &mut ChildrenManager<Reporter>,
+    projects: impl Iterator<Item = ScanResult>,
+) -> Result<DiskBudget> {
+    let mut candidates = Vec::new();
+    for project in projects {
+        match project {
+            Ok(project) => candidates.push(Candidate::new(project, args.activity)),
+            Err(err) => manager.handler_mut().log_err(Some(&err.path), &err.error)?,
         }
-        // We don't have a pgid yet, so we need to get one.
-        let pgid = unsafe { getpgrp() };
-        // getpgid(), and the BSD-specific getpgrp() return a process group on success.
-        // On error, -1 is returned, and errno is set to indicate the error.
-        if pgid == -1 {
-            eprintln!("{:?}", std::io::Error::last_os_error());
-            abort();
+    }
+    rank(&mut candidates);
+    // Projects with nothing to reclaim can't help reaching the target.
+    let mut candidates = candidates.into_iter().filter(|candidate| candidate.size.allocated > 0);
+    // Estimated space of the cleaners that were started since the last time all of them finished.
+    let mut pending = 0;
+    while interrupt_count() == 0 {
+        // Nothing is freed in a dry run, so it only relies on the estimates.
+        let gained = if args.dry_run { 0 } else { budget.gained()? };
+        if gained >= budget.target() {
+            break;
+        }
+        if gained + pending >= budget.target() {
+            if args.dry_run {
+                break;
+            }
+            // The running cleaners should be enough, see how much they really freed before starting more.
+            manager.wait_all()?;
+            pending = 0;
+            continue;
+        }
+        let Some(candidate) = candidates.next() else { break };
+        if manager.handle_project(&candidate.project)? {
+            pending += candidate.size.allocated;
         }
-        let last_pgid = PGID.swap(pgid, Ordering::Relaxed);
-        // Make sure that if we raced another thread we got the same pgid.
-        assert!(last_pgid == -1 || last_pgid == pgid, "last_pgid: {last_pgid}, pgid: {pgid}");
-        pgid
     }
+    Ok(budget)
+}
 
-    impl RegisterChild for Command {
-        #[inline(always)]
-        fn register_child(&mut self) -> &mut Self {
-            self.process_group(get_pgid())
+fn scan(args: &Args, projects: impl Iterator<Item = ScanResult>) -> Result<ExitCode> {
+    let mut reporter = Reporter::new(args.verbosity, args.format);
+    let mut found = 0;
+    for project in projects {
+        let project = match project {
+            Ok(project) => project,
+            Err(err) => {
+                reporter.log_err(Some(&err.path), &err.error)?;
+                continue;
+            }
+        };
+        found += 1;
+        if reporter.is_text() {
+            writeln!(&mut reporter.stdout, "[{}] {}", project.kind, project.root.display())?;
+        } else {
+            let object = reporter.project_object("project", &project);
+            reporter.emit(object)?;
         }
     }
+    if reporter.is_text() {
+        writeln!(&mut reporter.stdout, "Found {found} projects")?;
+    }
+    let summary = reporter.object("summary").num("projects", found);
+    reporter.summary(summary)?;
+    Ok(ExitCode::SUCCESS)
+}
 
-    /// Returns the exit status and the index of the child process that exited.
-    #[inline(always)]
-    pub(super) fn wait_on_children(processes: &[ChildProcess]) -> Result<(ExitStatus, usize)> {
-        let mut status: c_int = 0;
-        let pid = match unsafe { waitpid(-get_pgid(), &mut status, 0) } {
-            -1 => return Err(std::io::Error::last_os_error()),
-            pid if pid.is_positive() => pid,
-            _ => abort(),
+fn report(args: &Args, projects: impl Iterator<Item = ScanResult>) -> Result<ExitCode> {
+    let mut reporter = Reporter::new(args.verbosity, args.format);
+    let mut found = 0;
+    let mut total = DiskUsage::default();
+    // Outputs shared by several projects in the same directory only count once in the total.
+    let mut measured = HashSet::new();
+    for project in projects {
+        let project = match project {
+            Ok(project) => project,
+            Err(err) => {
+                reporter.log_err(Some(&err.path), &err.error)?;
+                continue;
+            }
         };
-        let pid_u32 = u32::try_from(pid).expect("pid should fit in u32");
-        let index = processes.iter().position(|p| p.child.id() == pid_u32).unwrap_or_else(|| {
-            let pids = processes.iter().map(|p| p.child.id()).collect::<Vec<_>>();
-            panic!("waitpid returned unknown pid: {pid_u32}, known pids: {pids:?}")
-        });
-        Ok((ExitStatus::from_raw(status), index))
+        found += 1;
+        let usage = project.kind.measure(&project.root);
+        let outputs = project.kind.output_paths(&project.root);
+        let new_outputs: Vec<_> = outputs.iter().filter(|&output| measured.insert(output.clone())).collect();
+        total += if new_outputs.len() == outputs.len() { usage } else { SizeWalker::measure(new_outputs) };
+        if reporter.is_text() {
+            writeln!(&mut reporter.stdout, "[{}] {}: {usage}", project.kind, project.root.display())?;
+        } else {
+            let object = reporter.project_object("project", &project).usage("usage", usage);
+            reporter.emit(object)?;
+        }
     }
+    if reporter.is_text() {
+        writeln!(&mut reporter.stdout, "Reclaimable {total} in total")?;
+    }
+    let summary = reporter.object("summary").num("projects", found).usage("reclaimable", total);
+    reporter.summary(summary)?;
+    Ok(ExitCode::SUCCESS)
 }
 
-#[cfg(windows)]
-#[allow(clippy::upper_case_acronyms)]
-mod os_wait {
-    use crate::{ChildProcess, RegisterChild};
-    use std::ffi::{c_int, c_ulong};
-    use std::io::Result;
-    use std::os::windows::{io::AsRawHandle, process::ExitStatusExt, raw::HANDLE};
-    use std::process::{Command, ExitStatus};
-    use std::{cmp, ptr};
-
-    impl RegisterChild for Command {
-        #[inline(always)]
-        fn register_child(&mut self) -> &mut Self {
-            self
+fn purge(args: &Args, quarantine: &Quarantine) -> Result<ExitCode> {
+    let (purged, usage, unreadable) = quarantine.purge(args.older_than)?;
+    warn_unreadable(&unreadable)?;
+    if args.verbosity >= Verbosity::Verbose {
+        for entry in &purged {
+            println!("Purged {}: {}", entry.id, entry.original.display());
         }
     }
-
-    type DWORD = c_ulong;
-    type BOOL = c_int;
-    type LPDWORD = *mut DWORD;
-
-    const MAXIMUM_WAIT_OBJECTS: usize = 64;
-    const WAIT_OBJECT_0: DWORD = 0;
-    const WAIT_FAILED: DWORD = 0xFFFFFFFF;
-    const INFINITE: DWORD = 0xFFFFFFFF;
-    const FALSE: BOOL = 0;
-    unsafe extern "system" {
-        fn WaitForMultipleObjects(
-            n_count: DWORD,
-            lp_handles: *const HANDLE,
-            b_wait_all: BOOL,
-            dw_milliseconds: DWORD,
-        ) -> DWORD;
-        fn GetExitCodeProcess(h_process: HANDLE, lp_exit_code: LPDWORD) -> BOOL;
+    if args.verbosity >= Verbosity::Normal {
+        println!("Purged {} directories, freed {usage}", purged.len());
     }
+    Ok(ExitCode::SUCCESS)
+}
 
-    /// Returns the exit status and the index of the child process that exited.
-    #[inline(always)]
-    pub(super) fn wait_on_children(processes: &[ChildProcess]) -> Result<(ExitStatus, usize)> {
-        // Sadly windows doesn't support waiting on more than 64 processes at once.
-        let mut handles = [ptr::null_mut(); MAXIMUM_WAIT_OBJECTS];
-        let size = cmp::min(processes.len(), MAXIMUM_WAIT_OBJECTS);
-        for (i, p) in processes.iter().take(size).enumerate() {
-            handles[i] = p.child.as_raw_handle();
+/// Restores the quarantined directories, or lists them without any id.
+fn restore(args: &Args, quarantine: &Quarantine) -> Result<ExitCode> {
+    if args.ids.is_empty() {
+        let now = SystemTime::now();
+        let (entries, unreadable) = quarantine.entries()?;
+        warn_unreadable(&unreadable)?;
+        for entry in entries {
+            let age = format_duration(now.duration_since(entry.date).unwrap_or_default());
+            println!("{}  {:>8}  {}", entry.id, format!("{age} ago"), entry.original.display());
         }
-        let index = match unsafe { WaitForMultipleObjects(size as DWORD, handles.as_ptr(), FALSE, INFINITE) } {
-            WAIT_FAILED => return Err(std::io::Error::last_os_error()),
-            ret => (ret - WAIT_OBJECT_0) as usize,
-        };
-        let mut status = 0;
-        let handle = processes[index].child.as_raw_handle();
-        // If the function succeeds, the return value is nonzero.
-        // If the function fails, the return value is zero.
-        if unsafe { GetExitCodeProcess(handle, &mut status) } == 0 {
-            return Err(std::io::Error::last_os_error());
+        return Ok(ExitCode::SUCCESS);
+    }
+    for id in &args.ids {
+        let entry = quarantine.restore(id)?;
+        if args.verbosity >= Verbosity::Normal {
+            println!("Restored {}", entry.original.display());
         }
-        Ok((ExitStatus::from_raw(status), index))
     }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Warns about the quarantined entries skipped because they can't be read.
+#[inline(always)]
+fn warn_unreadable(unreadable: &[ScanError]) -> Result<()> {
+    let mut stderr = StdErrManager::new();
+    for err in unreadable {
+        stderr.log_warning(&format!("skipping a quarantined entry that can't be read, {}", err.error))?;
+    }
+    Ok(())
 }
@@ -0,0 +1,1103 @@
+use crate::busy::BusyCheck;
+use crate::cargo::CargoTarget;
+use crate::drain::Drain;
+use crate::reactor;
+use crate::removal::{Removal, RemovalPool};
+use crate::sandbox::Sandbox;
+use crate::size::DiskUsage;
+use crate::{
+    Action, ActivitySource, Cleaner, Project, Quarantine, RuleKind, activity, format_duration, interrupt, move_to_trash,
+};
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Result};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant, SystemTime};
+
+// We don't want to overwhelm the system with open files
+pub const MAX_KIDS: usize = 512 + 256;
+
+/// How long to wait for a failed cleaner's stderr to close after it exited.
+const STDERR_GRACE: Duration = Duration::from_millis(500);
+
+/// How long a cleaner that ran out of time has to exit after SIGTERM, before it gets SIGKILL.
+pub const KILL_GRACE: Duration = Duration::from_secs(5);
+
+/// How often finished deletions are checked while waiting on cleaners, where they can't interrupt the wait.
+const REMOVAL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Something that happened while cleaning, reported to the [`EventHandler`].
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// An action that would have run, emitted instead of [`Event::Spawn`] in dry-run mode.
+    Planned { project: &'a Project, action: &'a Action },
+    /// A project was left alone, `reason` says why.
+    Skipped { project: &'a Project, reason: &'a str },
+    /// A cleaner is about to start.
+    Spawn { project: &'a Project, action: &'a Action },
+    /// A cleaner finished, `status` is `None` for actions that ran in-process.
+    /// `stderr` holds the child's output if it failed, `duration` is how long the cleaner ran, and `timed_out`
+    /// says it was terminated because it ran out of time.
+    Finish {
+        project: &'a Project,
+        status: Option<ExitStatus>,
+        stderr: &'a str,
+        duration: Duration,
+        timed_out: bool,
+        freed: DiskUsage,
+    },
+    /// A directory was moved to the trash or a quarantine instead of being deleted.
+    Moved { project: &'a Project, from: &'a Path, to: &'a Path },
+    /// Something went wrong while handling `path`, `None` means an operating system error not tied to a path.
+    Error { path: Option<&'a Path>, error: &'a io::Error },
+    /// Cleaning goes on, but not quite as asked, like cleaners running outside the sandbox.
+    Warning { message: &'a str },
+}
+
+/// Receives the [`Event`]s of a [`ChildrenManager`], any closure taking an event is a handler.
+pub trait EventHandler {
+    fn on_event(&mut self, event: Event<'_>) -> Result<()>;
+}
+
+impl<F: FnMut(Event<'_>) -> Result<()>> EventHandler for F {
+    #[inline(always)]
+    fn on_event(&mut self, event: Event<'_>) -> Result<()> {
+        self(event)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Maximum number of cleaners running at the same time.
+    pub jobs: usize,
+    /// Only report what would be done, without running anything.
+    pub dry_run: bool,
+    /// Skip projects that were active within this duration.
+    pub older_than: Option<Duration>,
+    /// How the last activity of a project is determined for `older_than`.
+    pub activity: ActivitySource,
+    /// What happens to the directories of [`Action::Remove`].
+    pub disposal: Disposal,
+    /// How long a cleaner can run before it is terminated, unless its rule has its own [`Rule::timeout`].
+    pub timeout: Option<Duration>,
+    /// Never run anything from the projects, only delete their outputs, see [`Rule::fallback`].
+    pub no_exec: bool,
+    /// Confine the cleaners: a scrubbed environment everywhere, and on Linux writes only below the project and
+    /// the [`Rule::writable`] paths.
+    pub sandbox: bool,
+    /// Also cut the sandboxed cleaners off from the network, on Linux.
+    pub isolate_network: bool,
+    /// The directories the projects were found in. Maven reactors and Gradle builds are only looked for up to the
+    /// one a project is in, without any every project is a build of its own.
+    pub roots: Vec<PathBuf>,
+}
+
+/// What happens to removed directories.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Disposal {
+    /// Deleted right away.
+    #[default]
+    Delete,
+    /// Moved to the freedesktop.org trash, see [`move_to_trash`].
+    Trash,
+    /// Moved into a staging directory they can be restored from.
+    Quarantine(Quarantine),
+}
+
+impl Default for Options {
+    #[inline(always)]
+    fn default() -> Self {
+        Self {
+            jobs: MAX_KIDS,
+            dry_run: false,
+            older_than: None,
+            activity: ActivitySource::default(),
+            disposal: Disposal::default(),
+            timeout: None,
+            no_exec: false,
+            sandbox: true,
+            isolate_network: false,
+            roots: Vec::new(),
+        }
+    }
+}
+
+/// Runs the cleaners of the projects it is given, at most [`Options::jobs`] at a time. Directories are deleted
+/// on a pool of threads, and every project being deleted counts as a running cleaner.
+pub struct ChildrenManager<H: EventHandler> {
+    kids: Vec<ChildProcess>,
+    waiter: os_wait::Waiter,
+    /// Started with the first deletion.
+    removals: Option<RemovalPool>,
+    /// Set up with the first cleaner, if [`Options::sandbox`].
+    sandbox: Option<Sandbox>,
+    options: Options,
+    handler: H,
+    /// Number of actions that were planned (but not executed) in dry-run mode.
+    planned: usize,
+    /// Projects that were active too recently are skipped if they were last active after this. Also `None` when
+    /// [`Options::older_than`] reaches back before the earliest time the system can represent, every project is
+    /// skipped then.
+    cutoff: Option<SystemTime>,
+    /// Total space reclaimed by all the cleaners that finished.
+    freed: DiskUsage,
+    /// The target directories of the Cargo projects handled so far, and the workspace each one belongs to.
+    targets: HashMap<PathBuf, PathBuf>,
+    /// The top directories of the Maven and Gradle builds handled so far.
+    builds: HashSet<PathBuf>,
+    /// Projects being built are skipped.
+    busy: BusyCheck,
+}
+
+impl<H: EventHandler> ChildrenManager<H> {
+    #[inline(always)]
+    pub fn new(options: Options, handler: H) -> Self {
+        Self {
+            kids: Vec::with_capacity(options.jobs),
+            waiter: os_wait::Waiter::new(),
+            removals: None,
+            sandbox: None,
+            cutoff: options.older_than.and_then(|older_than| SystemTime::now().checked_sub(older_than)),
+            options,
+            handler,
+            planned: 0,
+            freed: DiskUsage::default(),
+            targets: HashMap::new(),
+            builds: HashSet::new(),
+            busy: BusyCheck::new(),
+        }
+    }
+
+    #[inline(always)]
+    pub fn handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    /// Number of actions that were planned in dry-run mode.
+    #[inline(always)]
+    pub fn planned(&self) -> usize {
+        self.planned
+    }
+
+    /// Total space reclaimed by the cleaners that finished so far.
+    #[inline(always)]
+    pub fn freed(&self) -> DiskUsage {
+        self.freed
+    }
+
+    /// Cleans `project` with its ecosystem's cleaner, unless it is being built right now.
+    ///
+    /// Returns whether a cleaner was started (or planned in dry-run mode), failures of the cleaner are reported
+    /// to the handler, an error is only returned if the handler fails.
+    #[inline(always)]
+    pub fn handle_project(&mut self, project: &Project) -> Result<bool> {
+        // The running cleaners keep writing while this one is planned.
+        self.waiter.drain(&mut self.kids);
+        let Some(project) = self.claim_target(project)? else { return Ok(false) };
+        let Some(project) = self.claim_build(project) else { return Ok(false) };
+        let project = &*project;
+        if let Some(older_than) = self.options.older_than
+            && self.cutoff.is_none()
+        {
+            let reason = format!("nothing is older than --older-than {}", format_duration(older_than));
+            self.handler.on_event(Event::Skipped { project, reason: &reason })?;
+            return Ok(false);
+        }
+        if let Some(cutoff) = self.cutoff
+            && let Some(time) = activity::recent_activity(project, self.options.activity, cutoff)
+        {
+            let age = SystemTime::now().duration_since(time).unwrap_or_default();
+            let reason = format!(
+                "modified {} ago, within --older-than {}",
+                format_duration(age),
+                format_duration(self.options.older_than.unwrap_or_default())
+            );
+            self.handler.on_event(Event::Skipped { project, reason: &reason })?;
+            return Ok(false);
+        }
+        if let Some(reason) = self.busy.active_build(project) {
+            self.handler.on_event(Event::Skipped { project, reason: &reason })?;
+            return Ok(false);
+        }
+        let planned = if !self.options.no_exec {
+            project.kind.plan(project)
+        } else if project.kind.cleans_without_exec() {
+            project.kind.plan_without_exec(project)
+        } else {
+            let reason = "its cleaner runs a command, and --no-exec only deletes files";
+            self.handler.on_event(Event::Skipped { project, reason })?;
+            return Ok(false);
+        };
+        let action = match planned {
+            Ok(Some(action)) => action,
+            Ok(None) => return Ok(false),
+            Err(error) => {
+                self.handler.on_event(Event::Error { path: Some(&project.marker), error: &error })?;
+                return Ok(false);
+            }
+        };
+        // An in-source CMake build directory is the source tree, only its cleaner can run there.
+        if let Action::Remove(paths) = &action
+            && project.kind.kind == Some(RuleKind::CMake)
+            && paths.contains(&project.root)
+            && project.root.join("CMakeLists.txt").exists()
+        {
+            let reason = "it is an in-source build, deleting its build directory would delete its sources";
+            self.handler.on_event(Event::Skipped { project, reason })?;
+            return Ok(false);
+        }
+        if self.options.dry_run {
+            self.planned += 1;
+            self.handler.on_event(Event::Planned { project, action: &action })?;
+            return Ok(true);
+        }
+        self.handler.on_event(Event::Spawn { project, action: &action })?;
+        let timeout = project.kind.timeout.or(self.options.timeout);
+        match &action {
+            Action::Command { program, args, dir } => {
+                let isolate_network = self.options.isolate_network;
+                let sandbox = if self.options.sandbox {
+                    Some(self.sandbox.get_or_insert_with(|| Sandbox::new(isolate_network)))
+                } else {
+                    None
+                };
+                let spawned = ChildProcess::new(project, program, args, dir, timeout, &mut self.waiter, sandbox);
+                let warnings = self.sandbox.as_mut().map(Sandbox::take_warnings).unwrap_or_default();
+                for message in &warnings {
+                    self.handler.on_event(Event::Warning { message })?;
+                }
+                match spawned {
+                    Ok(kid) => self.push_wait(kid)?,
+                    Err(error) => {
+                        self.handler.on_event(Event::Error { path: Some(&project.marker), error: &error })?;
+                        return Ok(false);
+                    }
+                }
+            }
+            Action::Remove(paths) if self.options.disposal == Disposal::Delete => self.push_removal(project, paths)?,
+            Action::Remove(paths) => self.move_dirs(project, paths)?,
+        }
+        Ok(true)
+    }
+
+    /// Cargo projects are cleaned once per target directory, from the root of their workspace. Returns `None` if
+    /// the target directory was claimed already, may be shared with other projects or is unknown, and is left
+    /// alone.
+    fn claim_target<'a>(&mut self, project: &'a Project) -> Result<Option<Cow<'a, Project>>> {
+        if project.kind.kind != Some(RuleKind::Cargo) {
+            return Ok(Some(Cow::Borrowed(project)));
+        }
+        let target = match CargoTarget::resolve(&project.marker) {
+            Ok(target) => target,
+            Err(problem) => {
+                let message = format!("the target directory of {} is unknown, {problem}", project.root.display());
+                self.handler.on_event(Event::Warning { message: &message })?;
+                let reason = format!("its target directory is unknown, {problem}");
+                self.handler.on_event(Event::Skipped { project, reason: &reason })?;
+                return Ok(None);
+            }
+        };
+        let owner = match self.targets.entry(target.dir.clone()) {
+            // Another member of the same workspace.
+            Entry::Occupied(owner) if *owner.get() == target.root => return Ok(None),
+            Entry::Occupied(owner) => Some(owner.get().clone()),
+            Entry::Vacant(entry) => {
+                entry.insert(target.root.clone());
+                None
+            }
+        };
+        let project = if target.root == project.root {
+            Cow::Borrowed(project)
+        } else {
+            let (kind, marker) = (project.kind.clone(), target.root.join("Cargo.toml"));
+            Cow::Owned(Project { kind, root: target.root.clone(), marker })
+        };
+        let dir = target.dir.display();
+        let reason = match (owner, &target.source) {
+            (Some(owner), _) => format!("target directory {dir} is shared with {}", owner.display()),
+            (None, Some(source)) if target.is_external() => {
+                format!("target directory {dir} from {source} may be shared with other projects")
+            }
+            (None, _) => return Ok(Some(project)),
+        };
+        self.handler.on_event(Event::Skipped { project: &project, reason: &reason })?;
+        Ok(None)
+    }
+
+    /// Maven reactors and Gradle builds with several projects are cleaned once, by running their cleaner in their
+    /// top directory. Returns `None` if the build was claimed already, or is a Gradle build with a wrapper, which
+    /// the rule running the wrapper cleans.
+    ///
+    /// Deleting the outputs of the top directory doesn't delete those of the modules, so with
+    /// [`Options::no_exec`] every module is cleaned on its own.
+    fn claim_build<'a>(&mut self, project: Cow<'a, Project>) -> Option<Cow<'a, Project>> {
+        let wrapped = |dir: &Path| dir.join("gradlew").exists();
+        let top = match project.kind.kind {
+            Some(RuleKind::Gradle) if wrapped(&project.root) => return None,
+            _ if self.options.no_exec => return Some(project),
+            Some(RuleKind::Maven) => reactor::maven_reactor(&project.marker, self.scan_root(&project)),
+            Some(RuleKind::Gradle) => reactor::gradle_settings(&project.marker, self.scan_root(&project)),
+            _ => return Some(project),
+        };
+        let root = top.parent().unwrap_or(&top).to_path_buf();
+        if (project.kind.kind == Some(RuleKind::Gradle) && wrapped(&root)) || !self.builds.insert(root.clone()) {
+            return None;
+        }
+        if root == project.root {
+            return Some(project);
+        }
+        Some(Cow::Owned(Project { kind: project.kind.clone(), root, marker: top }))
+    }
+
+    /// The innermost of [`Options::roots`] that `project` is in, or its own directory.
+    #[inline(always)]
+    fn scan_root<'a>(&'a self, project: &'a Project) -> &'a Path {
+        let roots = self.options.roots.iter().filter(|root| project.root.starts_with(root));
+        roots.max_by_key(|root| root.components().count()).map_or(&project.root, PathBuf::as_path)
+    }
+
+    /// Moves the directories to the trash or the quarantine, which are only renames and happen right away.
+    #[inline(always)]
+    fn move_dirs(&mut self, project: &Project, paths: &[PathBuf]) -> Result<()> {
+        let before = project.kind.measure(&project.root);
+        let started = Instant::now();
+        for path in paths {
+            let moved = match &self.options.disposal {
+                Disposal::Delete => unreachable!("deletions run on the RemovalPool"),
+                Disposal::Trash => move_to_trash(path),
+                Disposal::Quarantine(quarantine) => quarantine.stash(path).map(|entry| entry.path),
+            };
+            match moved {
+                Ok(to) => self.handler.on_event(Event::Moved { project, from: path, to: &to })?,
+                Err(error) => self.handler.on_event(Event::Error { path: Some(path), error: &error })?,
+            }
+        }
+        let duration = started.elapsed();
+        let freed = before.freed(project.kind.measure(&project.root));
+        self.freed += freed;
+        self.handler.on_event(Event::Finish { project, status: None, stderr: "", duration, timed_out: false, freed })
+    }
+
+    #[inline(always)]
+    fn push_wait(&mut self, kid: ChildProcess) -> Result<()> {
+        // IMPORTANT: Add the child FIRST, before calling wait_remove.
+        // Otherwise, waitpid could return this child's PID before we track it.
+        self.kids.push(kid);
+        self.enforce_deadlines()?;
+        self.enforce_jobs()
+    }
+
+    /// Queues the deletion of `paths` on the pool, starting the pool first if needed.
+    #[inline(always)]
+    fn push_removal(&mut self, project: &Project, paths: &[PathBuf]) -> Result<()> {
+        let pool = match &mut self.removals {
+            Some(pool) => pool,
+            None => match RemovalPool::new(self.waiter.waker()) {
+                Ok(pool) => self.removals.insert(pool),
+                Err(error) => return self.handler.on_event(Event::Error { path: None, error: &error }),
+            },
+        };
+        pool.submit(project, paths);
+        self.enforce_jobs()
+    }
+
+    /// Number of cleaners running, deletions included.
+    #[inline(always)]
+    fn running(&self) -> usize {
+        self.kids.len() + self.removals.as_ref().map_or(0, RemovalPool::running)
+    }
+
+    #[inline(always)]
+    fn enforce_jobs(&mut self) -> Result<()> {
+        // Now enforce the limit
+        if self.running() >= self.options.jobs {
+            self.try_wait_remove()?;
+        }
+        // If nothing finished yet, we have to wait for something to finish.
+        if self.running() >= self.options.jobs {
+            self.wait_remove()?;
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn try_wait_remove(&mut self) -> Result<()> {
+        while let Some(removal) = self.removals.as_mut().and_then(RemovalPool::try_finished) {
+            self.finish_removal(removal)?;
+        }
+        let mut i = 0;
+        while i < self.kids.len() {
+            let res = match self.kids[i].child.try_wait() {
+                Ok(None) => {
+                    i += 1;
+                    continue;
+                }
+                Ok(Some(status)) => Ok(status),
+                Err(err) => Err(err),
+            };
+            let kid = self.kids.swap_remove(i);
+            self.finish(kid, res)?;
+        }
+        Ok(())
+    }
+
+    /// Waits for a child to exit or a deletion to finish, terminating the children that run out of time meanwhile,
+    /// and killing all of them after a second interrupt. Returns `false` if waiting on the children failed.
+    #[inline(always)]
+    fn wait_remove(&mut self) -> Result<bool> {
+        loop {
+            if interrupt::interrupt_count() >= 2 {
+                self.kill_all()?;
+            }
+            if let Some(removal) = self.removals.as_mut().and_then(RemovalPool::try_finished) {
+                self.finish_removal(removal)?;
+                return Ok(true);
+            }
+            let mut deadline = self.kids.iter().filter_map(ChildProcess::deadline).min();
+            if self.kids.is_empty() {
+                // Only deletions are running, a signal doesn't interrupt this wait, so it can't wait forever.
+                let deadline = Instant::now() + REMOVAL_POLL_INTERVAL;
+                if let Some(removal) = self.removals.as_mut().and_then(|pool| pool.wait_finished(Some(deadline))) {
+                    self.finish_removal(removal)?;
+                    return Ok(true);
+                }
+                continue;
+            }
+            if let Some(pool) = &self.removals
+                && pool.running() > 0
+                && !pool.wakes()
+            {
+                let poll = Instant::now() + REMOVAL_POLL_INTERVAL;
+                deadline = Some(deadline.map_or(poll, |deadline| deadline.min(poll)));
+            }
+            match self.waiter.wait(&mut self.kids, deadline) {
+                Err(error) => {
+                    self.handler.on_event(Event::Error { path: None, error: &error })?;
+                    return Ok(false);
+                }
+                Ok(Some((status, idx))) => {
+                    let kid = self.kids.swap_remove(idx);
+                    self.finish(kid, Ok(status))?;
+                    return Ok(true);
+                }
+                Ok(None) => self.enforce_deadlines()?,
+            }
+        }
+    }
+
+    /// Sends SIGTERM to the cleaners that ran out of time, and SIGKILL to the ones that outlived [`KILL_GRACE`].
+    fn enforce_deadlines(&mut self) -> Result<()> {
+        let now = Instant::now();
+        for kid in &mut self.kids {
+            if kid.deadline().is_none_or(|deadline| now < deadline) {
+                continue;
+            }
+            let force = kid.terminated.is_some();
+            if let Err(error) = os_wait::terminate(&mut kid.child, force) {
+                self.handler.on_event(Event::Error { path: Some(&kid.project.root), error: &error })?;
+            }
+            kid.terminated = Some(if force { Termination::Killed } else { Termination::Terminated(now) });
+        }
+        Ok(())
+    }
+
+    /// Sends SIGKILL to every cleaner that wasn't killed yet, and stops deleting.
+    fn kill_all(&mut self) -> Result<()> {
+        if let Some(pool) = &self.removals {
+            pool.cancel();
+        }
+        for kid in &mut self.kids {
+            if matches!(kid.terminated, Some(Termination::Killed | Termination::Interrupted)) {
+                continue;
+            }
+            if let Err(error) = os_wait::terminate(&mut kid.child, true) {
+                self.handler.on_event(Event::Error { path: Some(&kid.project.root), error: &error })?;
+            }
+            kid.terminated = Some(Termination::Interrupted);
+        }
+        Ok(())
+    }
+
+    /// Waits on all the running sub-processes and deletions.
+    #[inline(always)]
+    pub fn wait_all(&mut self) -> Result<()> {
+        while self.running() > 0 {
+            if !self.wait_remove()? {
+                // Waiting on all of them at once failed, so fall back to waiting on them one by one.
+                for mut kid in mem::take(&mut self.kids) {
+                    let res = kid.child.wait();
+                    self.finish(kid, res)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reports a finished deletion, and the directories it couldn't delete.
+    #[inline(always)]
+    fn finish_removal(&mut self, removal: Removal) -> Result<()> {
+        let Removal { project, errors, freed, duration } = removal;
+        for (path, error) in &errors {
+            self.handler.on_event(Event::Error { path: Some(path), error })?;
+        }
+        self.freed += freed;
+        let project = &project;
+        self.handler.on_event(Event::Finish { project, status: None, stderr: "", duration, timed_out: false, freed })
+    }
+
+    /// Collects the output of a child that exited and measures how much space its cleaner reclaimed.
+    #[inline(always)]
+    fn finish(&mut self, mut kid: ChildProcess, res: Result<ExitStatus>) -> Result<()> {
+        self.waiter.forget(&kid.child);
+        let duration = kid.started.elapsed();
+        let status = match res {
+            Ok(status) => status,
+            Err(error) => return self.handler.on_event(Event::Error { path: Some(&kid.project.root), error: &error }),
+        };
+        // A successful cleaner's output isn't needed, its reader keeps draining the pipe in the background.
+        let stderr = match kid.stderr.take() {
+            Some(stderr) if !status.success() => stderr.finish(STDERR_GRACE),
+            _ => String::new(),
+        };
+        let freed = kid.before.freed(kid.project.kind.measure(&kid.project.root));
+        self.freed += freed;
+        let timed_out = matches!(kid.terminated, Some(Termination::Terminated(_) | Termination::Killed));
+        let (project, status, stderr) = (&kid.project, Some(status), &stderr);
+        self.handler.on_event(Event::Finish { project, status, stderr, duration, timed_out, freed })
+    }
+}
+
+impl<H: EventHandler> Drop for ChildrenManager<H> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        // Wait on all sub-processes, without the handler while unwinding, it may be what panicked.
+        if std::thread::panicking() {
+            for kid in &mut self.kids {
+                let _ = kid.child.wait();
+            }
+            return;
+        }
+        self.wait_all().expect("Failed to wait on child process while dropping ChildrenManager");
+    }
+}
+
+struct ChildProcess {
+    child: Child,
+    project: Project,
+    /// Size of the project's outputs before the cleaner started.
+    before: DiskUsage,
+    started: Instant,
+    stderr: Option<Drain>,
+    timeout: Option<Duration>,
+    /// Set once the child ran out of time.
+    terminated: Option<Termination>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Termination {
+    /// SIGTERM was sent at this time.
+    Terminated(Instant),
+    /// SIGKILL was sent.
+    Killed,
+    /// SIGKILL was sent after a second interrupt.
+    Interrupted,
+}
+
+impl ChildProcess {
+    #[inline(always)]
+    fn new(
+        project: &Project,
+        program: &OsStr,
+        args: &[OsString],
+        dir: &Path,
+        timeout: Option<Duration>,
+        waiter: &mut os_wait::Waiter,
+        sandbox: Option<&mut Sandbox>,
+    ) -> Result<Self> {
+        assert!(dir.is_absolute());
+        let before = project.kind.measure(&project.root);
+        // A cleaner in its own process group doesn't get the terminal's Ctrl-C, which is handled here, and can't
+        // read from the terminal either, nothing could answer its prompts anyway.
+        let command = || {
+            let mut command = Command::new(program);
+            command
+                .args(args)
+                .current_dir(dir)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .register_child();
+            command
+        };
+        let mut child = match sandbox {
+            Some(sandbox) => sandbox.spawn(command, &project.kind.writable_paths(project))?,
+            None => command().spawn()?,
+        };
+        let stderr = child.stderr.take().expect("stderr is piped");
+        let stderr = match waiter.watch(&child, stderr) {
+            Ok(stderr) => Some(stderr),
+            Err(err) => {
+                // Nobody would read its stderr, so it can't be left running.
+                let _ = child.kill();
+                let _ = child.wait();
+                waiter.forget(&child);
+                return Err(err);
+            }
+        };
+        let (project, started) = (project.clone(), Instant::now());
+        Ok(Self { child, project, before, started, stderr, timeout, terminated: None })
+    }
+
+    /// When the child has to be sent its next signal.
+    #[inline(always)]
+    fn deadline(&self) -> Option<Instant> {
+        match self.terminated {
+            // A timeout too long to be represented never expires.
+            None => self.timeout.and_then(|timeout| self.started.checked_add(timeout)),
+            Some(Termination::Terminated(at)) => Some(at + KILL_GRACE),
+            Some(Termination::Killed | Termination::Interrupted) => None,
+        }
+    }
+}
+
+trait RegisterChild {
+    /// Puts the child in a process group of its own, so it can be terminated along with everything it started.
+    fn register_child(&mut self) -> &mut Self;
+}
+
+#[cfg(unix)]
+mod os_wait {
+    use super::{ChildProcess, RegisterChild};
+    use crate::drain::Drain;
+    use crate::interrupt_count;
+    use crate::removal::Wake;
+    use std::ffi::c_int;
+    use std::io::{Error, ErrorKind, Result};
+    use std::os::unix::prelude::ExitStatusExt;
+    use std::os::unix::process::CommandExt;
+    use std::process::{Child, ChildStderr, Command, ExitStatus};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    #[allow(non_camel_case_types)]
+    type pid_t = i32;
+    const WNOHANG: c_int = 1;
+    const SIGKILL: c_int = 9;
+    const SIGTERM: c_int = 15;
+    /// Longest sleep between two polls of the children.
+    const MAX_POLL_INTERVAL: Duration = Duration::from_millis(20);
+    unsafe extern "C" {
+        fn waitpid(pid: pid_t, wstatus: *mut c_int, options: c_int) -> pid_t;
+        fn kill(pid: pid_t, sig: c_int) -> c_int;
+    }
+
+    impl RegisterChild for Command {
+        #[inline(always)]
+        fn register_child(&mut self) -> &mut Self {
+            // 0 makes the child the leader of a new group.
+            self.process_group(0)
+        }
+    }
+
+    /// Waits on the children, with pidfds on Linux, or by polling each of them with `waitpid` otherwise.
+    pub(super) struct Waiter {
+        #[cfg(target_os = "linux")]
+        pidfds: Option<pidfd::PidFds>,
+    }
+
+    impl Waiter {
+        #[inline(always)]
+        pub(super) fn new() -> Self {
+            Self {
+                #[cfg(target_os = "linux")]
+                pidfds: pidfd::PidFds::new(),
+            }
+        }
+
+        /// Starts watching a child that was just spawned, and returns the reader of its stderr.
+        #[inline(always)]
+        pub(super) fn watch(&mut self, child: &Child, stderr: ChildStderr) -> Result<Drain> {
+            #[cfg(target_os = "linux")]
+            if let Some(pidfds) = &mut self.pidfds {
+                return pidfds.watch(child, stderr).map(Drain::Polled);
+            }
+            let _ = child;
+            Drain::spawn(stderr)
+        }
+
+        /// Stops watching a child that exited.
+        #[inline(always)]
+        pub(super) fn forget(&mut self, child: &Child) {
+            #[cfg(target_os = "linux")]
+            if let Some(pidfds) = &mut self.pidfds {
+                pidfds.forget(child);
+            }
+            let _ = child;
+        }
+
+        /// Reads what the children wrote to stderr since the last [`Waiter::wait`] without blocking, so none of them
+        /// fills its pipe and stalls while nothing waits on them. Readers on threads of their own don't need it.
+        #[inline(always)]
+        pub(super) fn drain(&mut self, processes: &mut [ChildProcess]) {
+            #[cfg(target_os = "linux")]
+            for process in processes {
+                // A closed pipe is taken out of the epoll set by the next wait, where it is still readable.
+                if let Some(Drain::Polled(pipe)) = &mut process.stderr {
+                    pipe.read_available();
+                }
+            }
+            #[cfg(not(target_os = "linux"))]
+            let _ = processes;
+        }
+
+        /// Something other threads can call to end a [`Waiter::wait`] early, if waiting supports it.
+        #[inline(always)]
+        pub(super) fn waker(&self) -> Option<Wake> {
+            #[cfg(target_os = "linux")]
+            if let Some(pidfds) = &self.pidfds {
+                return Some(pidfds.waker());
+            }
+            None
+        }
+
+        /// Returns the exit status and the index of the child process that exited, or `None` once `deadline`
+        /// passed, or a signal or a [`Waiter::waker`] interrupted the wait.
+        #[inline(always)]
+        pub(super) fn wait(
+            &mut self,
+            processes: &mut [ChildProcess],
+            deadline: Option<Instant>,
+        ) -> Result<Option<(ExitStatus, usize)>> {
+            #[cfg(target_os = "linux")]
+            if let Some(pidfds) = &mut self.pidfds {
+                return pidfds.wait(processes, deadline);
+            }
+            wait_any(processes, deadline)
+        }
+    }
+
+    /// The fallback of [`Waiter::wait`]. Polls each child with `waitpid`, other children, like the `stty` the
+    /// terminal UI runs meanwhile, are left to whoever started them.
+    fn wait_any(processes: &[ChildProcess], deadline: Option<Instant>) -> Result<Option<(ExitStatus, usize)>> {
+        let interrupts = interrupt_count();
+        let mut interval = Duration::from_millis(1);
+        loop {
+            for (index, process) in processes.iter().enumerate() {
+                let pid = pid_t::try_from(process.child.id()).expect("pid should fit in pid_t");
+                let mut status: c_int = 0;
+                match unsafe { waitpid(pid, &mut status, WNOHANG) } {
+                    -1 if Error::last_os_error().kind() == ErrorKind::Interrupted => return Ok(None),
+                    -1 => return Err(Error::last_os_error()),
+                    0 => {}
+                    _ => return Ok(Some((ExitStatus::from_raw(status), index))),
+                }
+            }
+            // Sleeping isn't interrupted by signals, they are noticed between the polls.
+            if interrupt_count() != interrupts {
+                return Ok(None);
+            }
+            let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            match remaining {
+                Some(remaining) if remaining.is_zero() => return Ok(None),
+                Some(remaining) => thread::sleep(interval.min(remaining)),
+                None => thread::sleep(interval),
+            }
+            interval = (interval * 2).min(MAX_POLL_INTERVAL);
+        }
+    }
+
+    /// Sends SIGTERM, or SIGKILL if `force`, to the process group the child leads.
+    #[inline(always)]
+    pub(super) fn terminate(child: &mut Child, force: bool) -> Result<()> {
+        let pgid = pid_t::try_from(child.id()).expect("pid should fit in pid_t");
+        match unsafe { kill(-pgid, if force { SIGKILL } else { SIGTERM }) } {
+            // ESRCH, everything already exited.
+            -1 if Error::last_os_error().raw_os_error() == Some(3) => Ok(()),
+            -1 => Err(Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Waiting on exactly our children, and reading their stderr in the same loop.
+    #[cfg(target_os = "linux")]
+    mod pidfd {
+        use super::super::ChildProcess;
+        use crate::drain::{Drain, PolledPipe};
+        use crate::removal::Wake;
+        use std::collections::HashMap;
+        use std::ffi::{c_int, c_long, c_uint};
+        use std::fs::File;
+        use std::io::{Error, ErrorKind, Read, Result, Write};
+        use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+        use std::process::{self, Child, ChildStderr, ExitStatus};
+        use std::sync::Arc;
+        use std::time::Instant;
+
+        const SYS_PIDFD_OPEN: c_long = 434;
+        const EPOLL_CLOEXEC: c_int = 0o2000000;
+        const EPOLL_CTL_ADD: c_int = 1;
+        const EPOLL_CTL_DEL: c_int = 2;
+        const EPOLLIN: u32 = 1;
+        const EFD_CLOEXEC: c_int = 0o2000000;
+        const EFD_NONBLOCK: c_int = 0o4000;
+        /// Set in the token of a stderr pipe, the rest of a token is the child's pid.
+        const STDERR_TOKEN: u64 = 1 << 32;
+        /// The token of the eventfd of [`PidFds::waker`].
+        const WAKE_TOKEN: u64 = u64::MAX;
+        const MAX_EVENTS: usize = 64;
+
+        #[derive(Clone, Copy)]
+        #[cfg_attr(target_arch = "x86_64", repr(C, packed))]
+        #[cfg_attr(not(target_arch = "x86_64"), repr(C))]
+        struct EpollEvent {
+            events: u32,
+            data: u64,
+        }
+        unsafe extern "C" {
+            fn syscall(number: c_long, ...) -> c_long;
+            fn epoll_create1(flags: c_int) -> c_int;
+            fn epoll_ctl(epfd: c_int, op: c_int, fd: c_int, event: *mut EpollEvent) -> c_int;
+            fn epoll_wait(epfd: c_int, events: *mut EpollEvent, max_events: c_int, timeout: c_int) -> c_int;
+            fn eventfd(initval: c_uint, flags: c_int) -> c_int;
+        }
+
+        /// A descriptor that becomes readable once the process `pid` exits, needs Linux 5.3.
+        #[inline(always)]
+        fn pidfd_open(pid: u32) -> Result<OwnedFd> {
+            let pid = c_int::try_from(pid).expect("pid should fit in pid_t");
+            match unsafe { syscall(SYS_PIDFD_OPEN, pid, 0 as c_uint) } {
+                -1 => Err(Error::last_os_error()),
+                fd => Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) }),
+            }
+        }
+
+        /// An epoll set with the pidfd and the stderr pipe of every child, and an eventfd to wake it.
+        pub(in super::super) struct PidFds {
+            epoll: OwnedFd,
+            /// Closing a pidfd also removes it from the epoll set.
+            pidfds: HashMap<u32, OwnedFd>,
+            wake: Arc<File>,
+        }
+
+        impl PidFds {
+            /// `None` if the kernel doesn't support pidfds.
+            pub(in super::super) fn new() -> Option<Self> {
+                pidfd_open(process::id()).ok()?;
+                let epoll = unsafe { epoll_create1(EPOLL_CLOEXEC) };
+                if epoll == -1 {
+                    return None;
+                }
+                let epoll = unsafe { OwnedFd::from_raw_fd(epoll) };
+                let wake = unsafe { eventfd(0, EFD_CLOEXEC | EFD_NONBLOCK) };
+                if wake == -1 {
+                    return None;
+                }
+                let wake = Arc::new(unsafe { File::from_raw_fd(wake) });
+                let pidfds = Self { epoll, pidfds: HashMap::new(), wake };
+                pidfds.control(EPOLL_CTL_ADD, pidfds.wake.as_raw_fd(), WAKE_TOKEN).ok()?;
+                Some(pidfds)
+            }
+
+            /// Makes the eventfd readable, which ends the current or next [`PidFds::wait`].
+            #[inline(always)]
+            pub(in super::super) fn waker(&self) -> Wake {
+                let wake = Arc::clone(&self.wake);
+                // Only fails if the counter would overflow, and then it is readable already.
+                Box::new(move || drop((&*wake).write(&1u64.to_ne_bytes())))
+            }
+
+            #[inline(always)]
+            fn control(&self, op: c_int, fd: RawFd, token: u64) -> Result<()> {
+                let mut event = EpollEvent { events: EPOLLIN, data: token };
+                if unsafe { epoll_ctl(self.epoll.as_raw_fd(), op, fd, &mut event) } == -1 {
+                    return Err(Error::last_os_error());
+                }
+                Ok(())
+            }
+
+            pub(in super::super) fn watch(&mut self, child: &Child, stderr: ChildStderr) -> Result<PolledPipe> {
+                let pid = child.id();
+                let pidfd = pidfd_open(pid)?;
+                let pipe = PolledPipe::new(stderr)?;
+                self.control(EPOLL_CTL_ADD, pipe.as_raw_fd(), u64::from(pid) | STDERR_TOKEN)?;
+                self.control(EPOLL_CTL_ADD, pidfd.as_raw_fd(), u64::from(pid))?;
+                self.pidfds.insert(pid, pidfd);
+                Ok(pipe)
+            }
+
+            #[inline(always)]
+            pub(in super::super) fn forget(&mut self, child: &Child) {
+                self.pidfds.remove(&child.id());
+            }
+
+            pub(in super::super) fn wait(
+                &mut self,
+                processes: &mut [ChildProcess],
+                deadline: Option<Instant>,
+            ) -> Result<Option<(ExitStatus, usize)>> {
+                let mut events = [EpollEvent { events: 0, data: 0 }; MAX_EVENTS];
+                loop {
+                    let timeout = match deadline {
+                        // Rounded up, so the deadline has passed when this times out.
+                        Some(deadline) => {
+                            let remaining = deadline.saturating_duration_since(Instant::now());
+                            c_int::try_from(remaining.as_micros().div_ceil(1000)).unwrap_or(c_int::MAX)
+                        }
+                        None => -1,
+                    };
+                    let fd = self.epoll.as_raw_fd();
+                    let ready = match unsafe { epoll_wait(fd, events.as_mut_ptr(), MAX_EVENTS as c_int, timeout) } {
+                        -1 if Error::last_os_error().kind() == ErrorKind::Interrupted => return Ok(None),
+                        -1 => return Err(Error::last_os_error()),
+                        0 => return Ok(None),
+                        ready => ready as usize,
+                    };
+                    let (mut exited, mut woken) = (None, false);
+                    for event in &events[..ready] {
+                        if event.data == WAKE_TOKEN {
+                            woken = true;
+                            let _ = (&*self.wake).read(&mut [0; 8]);
+                            continue;
+                        }
+                        let (token, pid) = (event.data, event.data as u32);
+                        let Some(index) = processes.iter().position(|p| p.child.id() == pid) else { continue };
+                        if token & STDERR_TOKEN != 0 {
+                            if let Some(Drain::Polled(pipe)) = &mut processes[index].stderr
+                                && pipe.read_available()
+                            {
+                                // A closed pipe stays readable forever.
+                                self.control(EPOLL_CTL_DEL, pipe.as_raw_fd(), token)?;
+                            }
+                        } else if exited.is_none()
+                            && let Some(status) = processes[index].child.try_wait()?
+                        {
+                            exited = Some((status, index));
+                        }
+                    }
+                    // Other children that exited are still ready on the next call.
+                    if exited.is_some() || woken {
+                        return Ok(exited);
+                    }
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+#[allow(clippy::upper_case_acronyms)]
+mod os_wait {
+    use super::{ChildProcess, RegisterChild};
+    use crate::drain::Drain;
+    use crate::removal::Wake;
+    use std::ffi::{c_int, c_ulong};
+    use std::io::Result;
+    use std::os::windows::{io::AsRawHandle, process::ExitStatusExt, raw::HANDLE};
+    use std::process::{Child, ChildStderr, Command, ExitStatus};
+    use std::time::Instant;
+    use std::{cmp, ptr};
+
+    impl RegisterChild for Command {
+        #[inline(always)]
+        fn register_child(&mut self) -> &mut Self {
+            self
+        }
+    }
+
+    type DWORD = c_ulong;
+    type BOOL = c_int;
+    type LPDWORD = *mut DWORD;
+
+    const MAXIMUM_WAIT_OBJECTS: usize = 64;
+    const WAIT_OBJECT_0: DWORD = 0;
+    const WAIT_TIMEOUT: DWORD = 0x102;
+    const WAIT_FAILED: DWORD = 0xFFFFFFFF;
+    const INFINITE: DWORD = 0xFFFFFFFF;
+    const FALSE: BOOL = 0;
+    unsafe extern "system" {
+        fn WaitForMultipleObjects(
+            n_count: DWORD,
+            lp_handles: *const HANDLE,
+            b_wait_all: BOOL,
+            dw_milliseconds: DWORD,
+        ) -> DWORD;
+        fn GetExitCodeProcess(h_process: HANDLE, lp_exit_code: LPDWORD) -> BOOL;
+    }
+
+    /// Waits on the process handles of the children.
+    pub(super) struct Waiter;
+
+    impl Waiter {
+        #[inline(always)]
+        pub(super) fn new() -> Self {
+            Self
+        }
+
+        /// Starts watching a child that was just spawned, and returns the reader of its stderr.
+        #[inline(always)]
+        pub(super) fn watch(&mut self, _child: &Child, stderr: ChildStderr) -> Result<Drain> {
+            Drain::spawn(stderr)
+        }
+
+        /// Stops watching a child that exited.
+        #[inline(always)]
+        pub(super) fn forget(&mut self, _child: &Child) {}
+
+        /// The stderr of every child is read on a thread of its own.
+        #[inline(always)]
+        pub(super) fn drain(&mut self, _processes: &mut [ChildProcess]) {}
+
+        /// Nothing can end a [`Waiter::wait`] early.
+        #[inline(always)]
+        pub(super) fn waker(&self) -> Option<Wake> {
+            None
+        }
+
+        /// Returns the exit status and the index of the child process that exited, or `None` once `deadline`
+        /// passed.
+        #[inline(always)]
+        pub(super) fn wait(
+            &mut self,
+            processes: &mut [ChildProcess],
+            deadline: Option<Instant>,
+        ) -> Result<Option<(ExitStatus, usize)>> {
+            // Sadly windows doesn't support waiting on more than 64 processes at once.
+            let mut handles = [ptr::null_mut(); MAXIMUM_WAIT_OBJECTS];
+            let size = cmp::min(processes.len(), MAXIMUM_WAIT_OBJECTS);
+            for (i, p) in processes.iter().take(size).enumerate() {
+                handles[i] = p.child.as_raw_handle();
+            }
+            let timeout = match deadline {
+                // Rounded up, so the deadline has passed when this times out.
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()).as_micros().div_ceil(1000),
+                None => INFINITE.into(),
+            };
+            let timeout = DWORD::try_from(timeout).unwrap_or(INFINITE - 1);
+            let index = match unsafe { WaitForMultipleObjects(size as DWORD, handles.as_ptr(), FALSE, timeout) } {
+                WAIT_TIMEOUT => return Ok(None),
+                WAIT_FAILED => return Err(std::io::Error::last_os_error()),
+                ret => (ret - WAIT_OBJECT_0) as usize,
+            };
+            let mut status = 0;
+            let handle = processes[index].child.as_raw_handle();
+            // If the function succeeds, the return value is nonzero.
+            // If the function fails, the return value is zero.
+            if unsafe { GetExitCodeProcess(handle, &mut status) } == 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(Some((ExitStatus::from_raw(status), index)))
+        }
+    }
+
+    /// There are no process groups or signals here, the child is killed right away.
+    #[inline(always)]
+    pub(super) fn terminate(child: &mut Child, _force: bool) -> Result<()> {
+        child.kill()
+    }
+}
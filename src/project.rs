@@ -0,0 +1,14 @@
+use crate::Rule;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// A project detected by the [`Scanner`](crate::Scanner).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Project {
+    /// The rule that detected the project.
+    pub kind: Arc<Rule>,
+    /// The project directory, this is where cleaners run.
+    pub root: PathBuf,
+    /// The file (or directory, for git) that identified the project.
+    pub marker: PathBuf,
+}
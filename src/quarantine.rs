@@ -0,0 +1,164 @@
+use crate::toml::{self, Value};
+use crate::{DiskUsage, ScanError, SizeWalker};
+use std::env;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Name of the file describing an entry, next to the moved directory.
+const INFO: &str = "info.toml";
+/// Name of the file marking a quarantine, the scanner doesn't look into a directory that has one.
+pub(crate) const TAG: &str = ".code-clean-quarantine";
+
+/// A staging directory that build outputs are moved into instead of being deleted, so they can be restored.
+///
+/// Every moved directory becomes an entry `<dir>/<id>/` holding the directory itself and an `info.toml` with its
+/// original path. The scanner skips the staging directory, which is tagged with a `.code-clean-quarantine` file.
+/// Entries are renamed in and out, so the staging directory must be on the same filesystem.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Quarantine {
+    dir: PathBuf,
+}
+
+/// A directory moved into a [`Quarantine`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QuarantineEntry {
+    pub id: String,
+    /// Where the directory was moved from, and is restored to.
+    pub original: PathBuf,
+    /// When the directory was moved.
+    pub date: SystemTime,
+    /// Where the directory is now.
+    pub path: PathBuf,
+}
+
+impl Quarantine {
+    #[inline(always)]
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// `$XDG_DATA_HOME/code-clean/quarantine`, or `~/.local/share/code-clean/quarantine`.
+    pub fn default_dir() -> Option<PathBuf> {
+        let data_home =
+            env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()).map(PathBuf::from).or_else(|| {
+                env::var_os("HOME")
+                    .or_else(|| env::var_os("USERPROFILE"))
+                    .map(|home| Path::new(&home).join(".local/share"))
+            })?;
+        Some(data_home.join("code-clean").join("quarantine"))
+    }
+
+    #[inline(always)]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Moves the directory at `path` into a new entry.
+    pub fn stash(&self, path: &Path) -> Result<QuarantineEntry> {
+        let (Some(original), Some(name)) = (path.to_str(), path.file_name()) else {
+            let msg = format!("{path:?} can't be quarantined, it isn't a valid UTF-8 path to a directory");
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        };
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.dir.join(TAG), "")?;
+        let secs = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+        // Several directories are moved within the same second, the first free id wins.
+        let (id, entry_dir) = (0..)
+            .map(|n| format!("{secs}-{n}"))
+            .map(|id| (self.dir.join(&id), id))
+            .find_map(|(dir, id)| match fs::create_dir(&dir) {
+                Ok(()) => Some(Ok((id, dir))),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => None,
+                Err(err) => Some(Err(err)),
+            })
+            .expect("There's always a free id")?;
+        let info = format!("original = {}\ndate = {secs}\n", toml::quote(original));
+        let moved = fs::write(entry_dir.join(INFO), info).and_then(|()| fs::rename(path, entry_dir.join(name)));
+        if let Err(err) = moved {
+            let _ = fs::remove_dir_all(&entry_dir);
+            if err.kind() == ErrorKind::CrossesDevices {
+                let msg =
+                    format!("{} is on another filesystem than the quarantine {}", path.display(), self.dir.display());
+                return Err(Error::new(err.kind(), msg));
+            }
+            return Err(err);
+        }
+        let date = SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        Ok(QuarantineEntry { id, original: path.into(), date, path: entry_dir })
+    }
+
+    /// All the entries, oldest first, and the errors of those that can't be read, like one an interrupted move left
+    /// behind. A missing quarantine directory has no entries.
+    pub fn entries(&self) -> Result<(Vec<QuarantineEntry>, Vec<ScanError>)> {
+        let dir = match fs::read_dir(&self.dir) {
+            Ok(dir) => dir,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok((Vec::new(), Vec::new())),
+            Err(err) => return Err(err),
+        };
+        let (mut entries, mut errors) = (Vec::new(), Vec::new());
+        for entry in dir {
+            let entry = entry?;
+            if let Some(id) = entry.file_name().to_str()
+                && entry.file_type()?.is_dir()
+            {
+                match self.entry(id) {
+                    Ok(entry) => entries.push(entry),
+                    Err(error) => errors.push(ScanError { path: entry.path(), error }),
+                }
+            }
+        }
+        entries.sort_by(|a, b| (a.date, &a.id).cmp(&(b.date, &b.id)));
+        Ok((entries, errors))
+    }
+
+    /// Reads the entry `id`.
+    pub fn entry(&self, id: &str) -> Result<QuarantineEntry> {
+        let path = self.dir.join(id);
+        if id.is_empty() || id.contains(['/', '\\']) || id.starts_with('.') || !path.is_dir() {
+            let msg = format!("no quarantined entry '{id}' in {}", self.dir.display());
+            return Err(Error::new(ErrorKind::NotFound, msg));
+        }
+        let info_path = path.join(INFO);
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidData, format!("{}: {msg}", info_path.display()));
+        let info = fs::read_to_string(&info_path)
+            .map_err(|err| Error::new(err.kind(), format!("{}: {err}", info_path.display())))?;
+        let info = toml::parse(&info).map_err(|err| invalid(err.to_string()))?;
+        let (Some(Value::String(original)), Some(&Value::Integer(secs))) = (info.get("original"), info.get("date"))
+        else {
+            return Err(invalid("expected an `original` path and a `date`".into()));
+        };
+        let date = SystemTime::UNIX_EPOCH + Duration::from_secs(secs.try_into().unwrap_or_default());
+        Ok(QuarantineEntry { id: id.into(), original: original.into(), date, path })
+    }
+
+    /// Moves the entry `id` back to where it came from, unless something else is there now.
+    pub fn restore(&self, id: &str) -> Result<QuarantineEntry> {
+        let entry = self.entry(id)?;
+        if fs::symlink_metadata(&entry.original).is_ok() {
+            let msg = format!("can't restore '{id}', {} already exists", entry.original.display());
+            return Err(Error::new(ErrorKind::AlreadyExists, msg));
+        }
+        if let Some(parent) = entry.original.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let name = entry.original.file_name().ok_or_else(|| Error::other(format!("invalid entry '{id}'")))?;
+        fs::rename(entry.path.join(name), &entry.original)?;
+        fs::remove_dir_all(&entry.path)?;
+        Ok(entry)
+    }
+
+    /// Deletes the entries older than `older_than`, or all of them, and returns them with the space they used. The
+    /// entries that can't be read are left alone, and returned with their errors.
+    pub fn purge(&self, older_than: Option<Duration>) -> Result<(Vec<QuarantineEntry>, DiskUsage, Vec<ScanError>)> {
+        let now = SystemTime::now();
+        let (mut purged, errors) = self.entries()?;
+        purged.retain(|entry| older_than.is_none_or(|age| now.duration_since(entry.date).is_ok_and(|d| d >= age)));
+        let usage = SizeWalker::measure(purged.iter().map(|entry| &entry.path));
+        for entry in &purged {
+            fs::remove_dir_all(&entry.path)?;
+        }
+        Ok((purged, usage, errors))
+    }
+}
@@ -0,0 +1,101 @@
+//! Maven and Gradle builds made of several modules, which are cleaned once from their top directory rather than
+//! once per module.
+
+use crate::cargo::normalize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The POM at the top of the reactor `pom` is a module of, or `pom` itself. Reactors are only looked for up to
+/// `scan_root`, the user didn't ask to clean anything above it.
+///
+/// Every ancestor directory whose POM lists the current top as a `<module>` becomes the new top, modules are
+/// usually subdirectories of their aggregator. Those next to it, like `../core`, aren't found.
+pub(crate) fn maven_reactor(pom: &Path, scan_root: &Path) -> PathBuf {
+    let mut top = pom.to_path_buf();
+    let Some(dir) = pom.parent() else { return top };
+    let mut top_dir = dir.to_path_buf();
+    for ancestor in dir.ancestors().skip(1).take_while(|ancestor| ancestor.starts_with(scan_root)) {
+        let candidate = ancestor.join("pom.xml");
+        let Ok(src) = fs::read_to_string(&candidate) else { continue };
+        let lists_top = modules(&src).any(|module| {
+            // A module is a directory, or the POM file itself when it isn't named `pom.xml`.
+            let module = normalize(&ancestor.join(module));
+            let module_dir = if module.extension().is_some_and(|ext| ext == "xml") {
+                module.parent().map(Path::to_path_buf).unwrap_or_default()
+            } else {
+                module
+            };
+            module_dir == top_dir
+        });
+        if lists_top {
+            top = candidate;
+            top_dir = ancestor.to_path_buf();
+        }
+    }
+    top
+}
+
+/// The settings file of the Gradle build `build_file` is part of, or `build_file` itself when it is a build of its
+/// own. That is the closest settings file in its directory or above, up to `scan_root`, if it includes the project.
+pub(crate) fn gradle_settings(build_file: &Path, scan_root: &Path) -> PathBuf {
+    let Some(dir) = build_file.parent() else { return build_file.to_path_buf() };
+    for ancestor in dir.ancestors().take_while(|ancestor| ancestor.starts_with(scan_root)) {
+        for name in ["settings.gradle", "settings.gradle.kts"] {
+            let settings = ancestor.join(name);
+            let Ok(src) = fs::read_to_string(&settings) else { continue };
+            let project = dir.strip_prefix(ancestor).unwrap_or(dir);
+            // The root project is always part of the build, and including `a:b` includes `a` too. Gradle only
+            // uses the closest settings file, a project it doesn't include is a build of its own.
+            if project.as_os_str().is_empty() || includes(&src).iter().any(|included| included.starts_with(project)) {
+                return settings;
+            }
+            return build_file.to_path_buf();
+        }
+    }
+    build_file.to_path_buf()
+}
+
+/// The directories of the projects a settings file includes, relative to it: `include("a:b")` is `a/b`. Those
+/// moved elsewhere with `projectDir` aren't known.
+fn includes(src: &str) -> Vec<PathBuf> {
+    let mut included = Vec::new();
+    let mut statement: Option<String> = None;
+    for line in src.lines() {
+        let line = line.split_once("//").map_or(line, |(code, _)| code).trim();
+        let rest = match statement.take() {
+            Some(open) => open + line,
+            None => match line.strip_prefix("include") {
+                Some(rest) if rest.starts_with([' ', '\t', '(', '"', '\'']) => rest.to_owned(),
+                _ => continue,
+            },
+        };
+        // Continues on the next line while a parenthesis is open, or after a trailing comma.
+        if (rest.contains('(') && !rest.contains(')')) || rest.ends_with(',') {
+            statement = Some(rest);
+            continue;
+        }
+        let quoted = rest.split(['"', '\'']).skip(1).step_by(2);
+        included.extend(quoted.map(|path| path.trim_start_matches(':').split(':').collect::<PathBuf>()));
+    }
+    included
+}
+
+/// The `<module>` elements of a POM, in `<modules>` or in a profile, but not in comments.
+fn modules(src: &str) -> impl Iterator<Item = &str> {
+    let mut rest = src;
+    std::iter::from_fn(move || {
+        loop {
+            let start = rest.find("<module>")?;
+            // Skips the comments before the next module, it may be inside one.
+            if let Some(comment) = rest.find("<!--").filter(|&comment| comment < start) {
+                let end = rest[comment..].find("-->").map_or(rest.len(), |end| comment + end + 3);
+                rest = &rest[end..];
+                continue;
+            }
+            let value = &rest[start + "<module>".len()..];
+            let end = value.find("</module>")?;
+            rest = &value[end..];
+            return Some(value[..end].trim());
+        }
+    })
+}
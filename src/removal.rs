@@ -0,0 +1,492 @@
+//! Deleting directory trees in-process on a pool of threads, which read and delete the directories of a tree in
+//! parallel instead of one after the other like `fs::remove_dir_all`. That is only on Linux, where directories are
+//! opened and deleted relative to their parent like `fs::remove_dir_all` does, elsewhere every tree is left to it.
+
+use crate::scanner::MAX_THREADS;
+use crate::{DiskUsage, Project};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::num::NonZero;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Condvar, Mutex, OnceLock, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Called by a pool thread after a removal finished.
+pub(crate) type Wake = Box<dyn Fn() + Send + Sync>;
+
+/// The directories of a project that were deleted.
+pub(crate) struct Removal {
+    pub(crate) project: Project,
+    /// The first error of every directory that couldn't be entirely deleted.
+    pub(crate) errors: Vec<(PathBuf, Error)>,
+    pub(crate) freed: DiskUsage,
+    pub(crate) duration: Duration,
+}
+
+/// Threads deleting the directories of projects, every project is one removal.
+pub(crate) struct RemovalPool {
+    shared: Arc<Shared>,
+    finished: Receiver<Removal>,
+    /// Removals submitted and not received from `finished` yet.
+    running: usize,
+    threads: Vec<JoinHandle<()>>,
+}
+
+struct Shared {
+    tasks: Mutex<Tasks>,
+    available: Condvar,
+    finished: Sender<Removal>,
+    wake: Option<Wake>,
+    /// Set to stop deleting, the removals still finish with an error.
+    cancelled: AtomicBool,
+}
+
+#[derive(Default)]
+struct Tasks {
+    queue: VecDeque<Task>,
+    closed: bool,
+}
+
+enum Task {
+    /// Measures the project and starts deleting its directories.
+    Start(Arc<Job>),
+    /// Deletes the files of a directory and queues its subdirectories.
+    Dir(Arc<Node>),
+}
+
+struct Job {
+    project: Project,
+    paths: Vec<PathBuf>,
+    started: Instant,
+    before: OnceLock<DiskUsage>,
+    /// Paths of `paths` that weren't deleted yet.
+    remaining: AtomicUsize,
+    errors: Mutex<Vec<(PathBuf, Error)>>,
+}
+
+/// A directory being deleted.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+struct Node {
+    path: PathBuf,
+    /// The index of the path of the job this directory is in.
+    root: usize,
+    parent: Option<Arc<Node>>,
+    job: Arc<Job>,
+    /// Subdirectories that weren't deleted yet, plus one until this directory was read.
+    remaining: AtomicUsize,
+    /// The directory once it was opened. Its entries are deleted and its subdirectories opened relative to it, so
+    /// a directory swapped for a symlink meanwhile is never followed.
+    #[cfg(target_os = "linux")]
+    dir: OnceLock<std::os::fd::OwnedFd>,
+}
+
+impl Node {
+    #[inline(always)]
+    fn new(path: PathBuf, root: usize, parent: Option<Arc<Node>>, job: Arc<Job>) -> Self {
+        Self {
+            path,
+            root,
+            parent,
+            job,
+            remaining: AtomicUsize::new(1),
+            #[cfg(target_os = "linux")]
+            dir: OnceLock::new(),
+        }
+    }
+}
+
+impl RemovalPool {
+    /// Starts the threads, `wake` is called every time a removal finishes.
+    pub(crate) fn new(wake: Option<Wake>) -> Result<Self> {
+        let (sender, finished) = mpsc::channel();
+        let shared = Arc::new(Shared {
+            tasks: Mutex::new(Tasks::default()),
+            available: Condvar::new(),
+            finished: sender,
+            wake,
+            cancelled: AtomicBool::new(false),
+        });
+        let count = thread::available_parallelism().map_or(1, NonZero::get).min(MAX_THREADS);
+        let mut pool = Self { shared, finished, running: 0, threads: Vec::with_capacity(count) };
+        for _ in 0..count {
+            let shared = Arc::clone(&pool.shared);
+            let thread = thread::Builder::new().name("code-clean-remove".into()).spawn(move || shared.work())?;
+            pool.threads.push(thread);
+        }
+        Ok(pool)
+    }
+
+    /// Number of removals that were submitted and didn't finish yet.
+    #[inline(always)]
+    pub(crate) fn running(&self) -> usize {
+        self.running
+    }
+
+    /// Deletes the directories `paths` of `project`.
+    pub(crate) fn submit(&mut self, project: &Project, paths: &[PathBuf]) {
+        let job = Job {
+            project: project.clone(),
+            paths: paths.to_vec(),
+            started: Instant::now(),
+            before: OnceLock::new(),
+            remaining: AtomicUsize::new(paths.len()),
+            errors: Mutex::new(Vec::new()),
+        };
+        self.running += 1;
+        self.shared.push(Task::Start(Arc::new(job)));
+    }
+
+    /// Returns a removal that finished, without waiting.
+    #[inline(always)]
+    pub(crate) fn try_finished(&mut self) -> Option<Removal> {
+        let removal = self.finished.try_recv().ok()?;
+        self.running -= 1;
+        Some(removal)
+    }
+
+    /// Waits for a removal to finish, until `deadline` if there is one.
+    pub(crate) fn wait_finished(&mut self, deadline: Option<Instant>) -> Option<Removal> {
+        if self.running == 0 {
+            return None;
+        }
+        let removal = match deadline {
+            Some(deadline) => match self.finished.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(removal) => removal,
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => return None,
+            },
+            None => self.finished.recv().ok()?,
+        };
+        self.running -= 1;
+        Some(removal)
+    }
+
+    /// Whether finishing removals call the `wake` they were started with.
+    #[inline(always)]
+    pub(crate) fn wakes(&self) -> bool {
+        self.shared.wake.is_some()
+    }
+
+    /// Stops deleting, the running removals finish right away with an error.
+    #[inline(always)]
+    pub(crate) fn cancel(&self) {
+        self.shared.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Drop for RemovalPool {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.available.notify_all();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Shared {
+    #[inline(always)]
+    fn lock(&self) -> std::sync::MutexGuard<'_, Tasks> {
+        self.tasks.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    #[inline(always)]
+    fn push(&self, task: Task) {
+        self.lock().queue.push_back(task);
+        self.available.notify_one();
+    }
+
+    fn work(&self) {
+        loop {
+            let mut tasks = self.lock();
+            // The newest task first, so a tree is deleted depth first and few directories are queued at once.
+            let task = loop {
+                match tasks.queue.pop_back() {
+                    Some(task) => break task,
+                    None if tasks.closed => return,
+                    None => tasks = self.available.wait(tasks).unwrap_or_else(PoisonError::into_inner),
+                }
+            };
+            drop(tasks);
+            match task {
+                Task::Start(job) => self.start(job),
+                Task::Dir(node) => self.delete_files(node),
+            }
+        }
+    }
+
+    fn start(&self, job: Arc<Job>) {
+        let _ = job.before.set(job.project.kind.measure(&job.project.root));
+        if job.paths.is_empty() {
+            return self.finish(&job);
+        }
+        for (root, path) in job.paths.iter().enumerate() {
+            // Like `fs::remove_dir_all`, a symlink is removed rather than followed.
+            match fs::symlink_metadata(path) {
+                Ok(metadata) if metadata.is_dir() => {
+                    let node = Node::new(path.clone(), root, None, Arc::clone(&job));
+                    self.push(Task::Dir(Arc::new(node)));
+                }
+                Ok(_) => {
+                    if let Err(err) = fs::remove_file(path) {
+                        job.fail(root, path, err);
+                    }
+                    self.path_done(&job);
+                }
+                Err(err) => {
+                    job.fail(root, path, err);
+                    self.path_done(&job);
+                }
+            }
+        }
+    }
+
+    /// Deletes the files of a directory and queues its subdirectories, with `openat` and `unlinkat` relative to the
+    /// directory, like `fs::remove_dir_all` does.
+    #[cfg(target_os = "linux")]
+    fn delete_files(&self, node: Arc<Node>) {
+        if self.cancelled.load(Ordering::SeqCst) {
+            node.job.fail(node.root, &node.path, Error::from(ErrorKind::Interrupted));
+            return self.dir_done(&node);
+        }
+        let name = node.path.file_name().unwrap_or_default();
+        let opened = match &node.parent {
+            Some(parent) => os_unlink::open_at(parent.opened(), name),
+            None => os_unlink::open(&node.path),
+        };
+        let dir = match opened {
+            Ok(dir) => node.dir.get_or_init(|| dir),
+            // Not a directory anymore, like a symlink that replaced it, which is removed rather than followed.
+            Err(err) if os_unlink::is_not_dir(&err) => {
+                let removed = match &node.parent {
+                    Some(parent) => os_unlink::unlink_at(parent.opened(), name, false),
+                    None => fs::remove_file(&node.path),
+                };
+                if let Err(err) = removed {
+                    node.job.fail(node.root, &node.path, err);
+                }
+                return self.dir_done(&node);
+            }
+            Err(err) => {
+                node.job.fail(node.root, &node.path, err);
+                return self.dir_done(&node);
+            }
+        };
+        let entries = match os_unlink::entries(dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                node.job.fail(node.root, &node.path, err);
+                return self.dir_done(&node);
+            }
+        };
+        for (name, is_dir) in entries {
+            let path = node.path.join(&name);
+            // Unlinking a directory fails, which tells what it is when the directory entry doesn't.
+            let unlinked = if is_dir { None } else { Some(os_unlink::unlink_at(dir, &name, false)) };
+            match unlinked {
+                Some(Ok(())) => {}
+                Some(Err(err)) if err.kind() != ErrorKind::IsADirectory => node.job.fail(node.root, &path, err),
+                None | Some(Err(_)) => {
+                    node.remaining.fetch_add(1, Ordering::SeqCst);
+                    let child = Node::new(path, node.root, Some(Arc::clone(&node)), Arc::clone(&node.job));
+                    self.push(Task::Dir(Arc::new(child)));
+                }
+            }
+        }
+        self.dir_done(&node);
+    }
+
+    /// Without `openat`, the whole tree is left to `fs::remove_dir_all`, which doesn't follow a directory swapped
+    /// for a symlink either.
+    #[cfg(not(target_os = "linux"))]
+    fn delete_files(&self, node: Arc<Node>) {
+        let result = match self.cancelled.load(Ordering::SeqCst) {
+            true => Err(Error::from(ErrorKind::Interrupted)),
+            false => fs::remove_dir_all(&node.path),
+        };
+        if let Err(err) = result {
+            node.job.fail(node.root, &node.path, err);
+        }
+        self.path_done(&node.job);
+    }
+
+    /// Called once a directory was read and every time one of its subdirectories was deleted, deletes the
+    /// directory itself after the last of them.
+    #[cfg(target_os = "linux")]
+    fn dir_done(&self, mut node: &Node) {
+        while node.remaining.fetch_sub(1, Ordering::SeqCst) == 1 {
+            // A directory that couldn't be opened was either removed already or can't be emptied.
+            if !self.cancelled.load(Ordering::SeqCst) && node.dir.get().is_some() {
+                let removed = match &node.parent {
+                    Some(parent) => {
+                        os_unlink::unlink_at(parent.opened(), node.path.file_name().unwrap_or_default(), true)
+                    }
+                    None => fs::remove_dir(&node.path),
+                };
+                if let Err(err) = removed {
+                    node.job.fail(node.root, &node.path, err);
+                }
+            }
+            match &node.parent {
+                Some(parent) => node = parent,
+                None => return self.path_done(&node.job),
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn path_done(&self, job: &Job) {
+        if job.remaining.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.finish(job);
+        }
+    }
+
+    fn finish(&self, job: &Job) {
+        let before = job.before.get().copied().unwrap_or_default();
+        let freed = before.freed(job.project.kind.measure(&job.project.root));
+        let errors = std::mem::take(&mut *job.errors.lock().unwrap_or_else(PoisonError::into_inner));
+        let removal = Removal { project: job.project.clone(), errors, freed, duration: job.started.elapsed() };
+        // The pool outlives the receiver only while it is dropped, nobody waits for removals then.
+        let _ = self.finished.send(removal);
+        if let Some(wake) = &self.wake {
+            wake();
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Node {
+    /// The directory, which is open while its subdirectories are deleted.
+    #[inline(always)]
+    fn opened(&self) -> &std::os::fd::OwnedFd {
+        self.dir.get().expect("A directory is opened before its subdirectories are queued")
+    }
+}
+
+impl Job {
+    /// Records the first error of the path `root`, `path` is where in that path it happened.
+    fn fail(&self, root: usize, path: &Path, err: Error) {
+        let mut errors = self.errors.lock().unwrap_or_else(PoisonError::into_inner);
+        let root = &self.paths[root];
+        if errors.iter().any(|(failed, _)| failed == root) {
+            return;
+        }
+        let err = if path == root { err } else { Error::new(err.kind(), format!("{}: {err}", path.display())) };
+        errors.push((root.clone(), err));
+    }
+}
+
+/// Deleting relative to open directories.
+#[cfg(target_os = "linux")]
+mod os_unlink {
+    use std::ffi::{CStr, CString, OsStr, OsString, c_char, c_int, c_void};
+    use std::fs::OpenOptions;
+    use std::io::{Error, ErrorKind, Result};
+    use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::path::Path;
+
+    // These two differ between architectures.
+    #[cfg(any(target_arch = "arm", target_arch = "aarch64", target_arch = "powerpc", target_arch = "powerpc64"))]
+    const O_DIRECTORY: c_int = 0o40000;
+    #[cfg(any(target_arch = "arm", target_arch = "aarch64", target_arch = "powerpc", target_arch = "powerpc64"))]
+    const O_NOFOLLOW: c_int = 0o100000;
+    #[cfg(not(any(target_arch = "arm", target_arch = "aarch64", target_arch = "powerpc", target_arch = "powerpc64")))]
+    const O_DIRECTORY: c_int = 0o200000;
+    #[cfg(not(any(target_arch = "arm", target_arch = "aarch64", target_arch = "powerpc", target_arch = "powerpc64")))]
+    const O_NOFOLLOW: c_int = 0o400000;
+    const O_CLOEXEC: c_int = 0o2000000;
+    const AT_REMOVEDIR: c_int = 0x200;
+    const DT_DIR: u8 = 4;
+    const ENOTDIR: i32 = 20;
+    const ELOOP: i32 = 40;
+
+    /// `struct dirent64`, which is also the `struct dirent` of musl.
+    #[repr(C)]
+    struct Dirent {
+        d_ino: u64,
+        d_off: i64,
+        d_reclen: u16,
+        d_type: u8,
+        d_name: [c_char; 256],
+    }
+    unsafe extern "C" {
+        fn openat(dirfd: c_int, path: *const c_char, flags: c_int, ...) -> c_int;
+        fn unlinkat(dirfd: c_int, path: *const c_char, flags: c_int) -> c_int;
+        fn fdopendir(fd: c_int) -> *mut c_void;
+        #[cfg_attr(target_env = "gnu", link_name = "readdir64")]
+        fn readdir(dir: *mut c_void) -> *const Dirent;
+        fn closedir(dir: *mut c_void) -> c_int;
+        fn close(fd: c_int) -> c_int;
+        fn __errno_location() -> *mut c_int;
+    }
+
+    #[inline(always)]
+    fn c_name(name: &OsStr) -> Result<CString> {
+        CString::new(name.as_bytes()).map_err(|err| Error::new(ErrorKind::InvalidInput, err))
+    }
+
+    /// Whether opening a directory failed because it isn't one, or is a symlink.
+    #[inline(always)]
+    pub(super) fn is_not_dir(err: &Error) -> bool {
+        matches!(err.raw_os_error(), Some(ENOTDIR | ELOOP))
+    }
+
+    /// Opens the directory at `path`, unless it is a symlink.
+    #[inline(always)]
+    pub(super) fn open(path: &Path) -> Result<OwnedFd> {
+        Ok(OpenOptions::new().read(true).custom_flags(O_DIRECTORY | O_NOFOLLOW).open(path)?.into())
+    }
+
+    /// Opens the subdirectory `name` of `dir`, unless it is a symlink.
+    pub(super) fn open_at(dir: &OwnedFd, name: &OsStr) -> Result<OwnedFd> {
+        let name = c_name(name)?;
+        match unsafe { openat(dir.as_raw_fd(), name.as_ptr(), O_DIRECTORY | O_NOFOLLOW | O_CLOEXEC) } {
+            -1 => Err(Error::last_os_error()),
+            fd => Ok(unsafe { OwnedFd::from_raw_fd(fd) }),
+        }
+    }
+
+    /// Deletes `name` from `dir`, an empty directory if `is_dir`, and otherwise anything else but a directory.
+    pub(super) fn unlink_at(dir: &OwnedFd, name: &OsStr, is_dir: bool) -> Result<()> {
+        let name = c_name(name)?;
+        match unsafe { unlinkat(dir.as_raw_fd(), name.as_ptr(), if is_dir { AT_REMOVEDIR } else { 0 }) } {
+            -1 => Err(Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+
+    /// The names in `dir` but `.` and `..`, with whether they are directories, which is `false` when the directory
+    /// entry doesn't tell.
+    pub(super) fn entries(dir: &OwnedFd) -> Result<Vec<(OsString, bool)>> {
+        // The stream owns its descriptor, `dir` stays open for the subdirectories.
+        let fd = dir.try_clone()?.into_raw_fd();
+        let stream = unsafe { fdopendir(fd) };
+        if stream.is_null() {
+            let err = Error::last_os_error();
+            unsafe { close(fd) };
+            return Err(err);
+        }
+        let mut entries = Vec::new();
+        let result = loop {
+            unsafe { *__errno_location() = 0 };
+            let entry = unsafe { readdir(stream) };
+            if entry.is_null() {
+                let errno = unsafe { *__errno_location() };
+                break if errno == 0 { Ok(entries) } else { Err(Error::from_raw_os_error(errno)) };
+            }
+            // The record ends with the name, it is usually shorter than the whole struct.
+            let (name, file_type) = unsafe { (CStr::from_ptr((&raw const (*entry).d_name).cast()), (*entry).d_type) };
+            let name = name.to_bytes();
+            if name != b"." && name != b".." {
+                entries.push((OsString::from_vec(name.to_vec()), file_type == DT_DIR));
+            }
+        };
+        unsafe { closedir(stream) };
+        result
+    }
+}
@@ -0,0 +1,584 @@
+use crate::toml::{self, Table, Value};
+use crate::{Action, Cleaner, DiskUsage, Project, SizeWalker, parse_duration};
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+const BUILTIN_RULES: &str = include_str!("builtin.toml");
+
+/// The file at the top of every Python virtual environment. Environments are only ever deleted whole, by a rule of
+/// their own: nothing below one is scanned, and `**` doesn't descend into them.
+pub(crate) const VENV_MARKER: &str = "pyvenv.cfg";
+
+/// How a [`Rule`] cleans a project.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuleAction {
+    /// Run `argv` inside `workdir`, `{marker}` and `{root}` in `argv` are replaced by the marker and project paths.
+    Command { argv: Vec<String>, workdir: PathBuf },
+    /// Delete these directories, symlinks and missing directories are skipped. They can be globs, see
+    /// [`Rule::outputs`].
+    Delete(Vec<PathBuf>),
+}
+
+/// What a rule deletes instead of running its command, when projects can't be trusted to run code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Fallback {
+    /// Delete these directories, like [`RuleAction::Delete`].
+    Delete(Vec<PathBuf>),
+    /// Delete the outputs listed in the `.ninja_log` next to the marker.
+    NinjaLog,
+}
+
+/// The build systems cleaned with more care than a command or a deletion. Set with `kind` in a rule, so renamed
+/// and overridden rules keep it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuleKind {
+    /// Cleaned once per target directory from the workspace root, and skipped while Cargo holds its build lock.
+    Cargo,
+    /// Cleaned once per reactor from its top POM.
+    Maven,
+    /// A Gradle build without a wrapper, cleaned once from its settings file. Builds with a wrapper are left to
+    /// the rule running it.
+    Gradle,
+    /// The build directory is never deleted whole if it is the source directory too.
+    CMake,
+}
+
+impl RuleKind {
+    const NAMES: &[(&str, RuleKind)] = &[
+        ("cargo", RuleKind::Cargo),
+        ("maven", RuleKind::Maven),
+        ("gradle", RuleKind::Gradle),
+        ("cmake", RuleKind::CMake),
+    ];
+
+    #[inline(always)]
+    fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES.iter().find(|&&(known, _)| known == name).map(|&(_, kind)| kind)
+    }
+}
+
+/// A detection rule, all paths are relative to the directory of the marker.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rule {
+    pub name: String,
+    /// Glob matched against file names, supports `*`, `?` and `{a,b}` alternatives.
+    pub marker: String,
+    pub action: RuleAction,
+    /// Build output directories, measured to report reclaimed space. The scanner doesn't descend into them once
+    /// the rule matched, `.` keeps it out of the whole project.
+    ///
+    /// Every component of an output can be a glob like the marker, and `**` stands for any number of directories
+    /// below the project, except hidden ones, virtual environments and other projects of the rule.
+    pub outputs: Vec<PathBuf>,
+    pub enabled: bool,
+    /// How long its command can run, overrides [`Options::timeout`](crate::Options::timeout).
+    pub timeout: Option<Duration>,
+    /// Used instead of the command with [`Options::no_exec`](crate::Options::no_exec), rules without one are
+    /// skipped then.
+    pub fallback: Option<Fallback>,
+    /// Where its command may write besides the project, `~/` is the home directory. Only enforced on Linux, see
+    /// [`Options::sandbox`](crate::Options::sandbox).
+    pub writable: Vec<PathBuf>,
+    /// Also looks for the marker directly inside hidden directories, which aren't scanned otherwise.
+    pub hidden: bool,
+    /// Names of the rules whose projects are left out where this one matches in the same directory, like the
+    /// `build.ninja` a CMake build directory has next to its cache.
+    pub supersedes: Vec<String>,
+    /// The build system the rule cleans, when it needs more care than running the command or deleting the outputs.
+    pub kind: Option<RuleKind>,
+}
+
+impl Rule {
+    #[inline(always)]
+    pub fn matches(&self, file_name: &str) -> bool {
+        self.enabled && glob_match(self.marker.as_bytes(), file_name.as_bytes())
+    }
+
+    /// Whether the rule can clean a project without running anything, see [`Rule::plan_without_exec`].
+    #[inline(always)]
+    pub fn cleans_without_exec(&self) -> bool {
+        matches!(self.action, RuleAction::Delete(_)) || self.fallback.is_some()
+    }
+
+    /// Like [`Cleaner::plan`], but deletes the fallback outputs instead of planning a command.
+    pub fn plan_without_exec(&self, project: &Project) -> Result<Option<Action>> {
+        match (&self.action, &self.fallback) {
+            (RuleAction::Delete(dirs), _) | (RuleAction::Command { .. }, Some(Fallback::Delete(dirs))) => {
+                plan_delete(self, dirs, project)
+            }
+            (RuleAction::Command { .. }, Some(Fallback::NinjaLog)) => plan_ninja_log(project),
+            (RuleAction::Command { .. }, None) => {
+                Err(Error::new(ErrorKind::Unsupported, format!("rule `{}` has no `fallback`", self.name)))
+            }
+        }
+    }
+
+    /// The directories the command may write below when cleaning `project`, the project first.
+    pub fn writable_paths(&self, project: &Project) -> Vec<PathBuf> {
+        let home = env::var_os("HOME").filter(|home| !home.is_empty()).map(PathBuf::from);
+        let extra = self.writable.iter().filter_map(|path| match path.strip_prefix("~") {
+            Ok(rest) => home.as_ref().map(|home| home.join(rest)),
+            Err(_) => Some(project.root.join(path)),
+        });
+        std::iter::once(project.root.clone()).chain(extra).collect()
+    }
+
+    /// Measures the disk usage of the build outputs of a project rooted at `project_dir`.
+    #[inline(always)]
+    pub fn measure(&self, project_dir: &Path) -> DiskUsage {
+        SizeWalker::measure(self.output_paths(project_dir))
+    }
+
+    /// The build outputs of a project rooted at `project_dir`, with their globs expanded. Those of a rule with the
+    /// `ninja-log` fallback are the files in its `.ninja_log`, a Ninja build directory can be the source directory.
+    #[inline(always)]
+    pub fn output_paths(&self, project_dir: &Path) -> Vec<PathBuf> {
+        if self.fallback == Some(Fallback::NinjaLog) {
+            return ninja_log_outputs(project_dir).unwrap_or_default();
+        }
+        self.expand(&self.outputs, project_dir)
+    }
+
+    /// The paths `patterns` stand for in `dir`. Those without globs are kept whether they exist or not, globs only
+    /// expand to paths that exist.
+    fn expand(&self, patterns: &[PathBuf], dir: &Path) -> Vec<PathBuf> {
+        let mut paths = Vec::with_capacity(patterns.len());
+        for pattern in patterns {
+            let components: Vec<&OsStr> = pattern.iter().filter(|&component| component != ".").collect();
+            if components.iter().any(|component| component.to_str().is_some_and(is_glob)) {
+                self.expand_into(dir.to_path_buf(), &components, &mut paths);
+            } else {
+                paths.push(dir.join(pattern).components().collect());
+            }
+        }
+        paths
+    }
+
+    /// Expands `pattern` in `dir`, only descending into real directories: a symlink can only be the last component.
+    fn expand_into(&self, dir: PathBuf, pattern: &[&OsStr], paths: &mut Vec<PathBuf>) {
+        let Some((&first, rest)) = pattern.split_first() else { return paths.push(dir) };
+        match first.to_str().filter(|&component| is_glob(component)) {
+            Some("**") => self.expand_globstar(dir, rest, paths),
+            Some(glob) => {
+                for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+                    let descend = rest.is_empty() || entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+                    if descend
+                        && entry.file_name().to_str().is_some_and(|name| glob_match(glob.as_bytes(), name.as_bytes()))
+                    {
+                        self.expand_into(entry.path(), rest, paths);
+                    }
+                }
+            }
+            None => {
+                let path = dir.join(first);
+                let metadata = fs::symlink_metadata(&path);
+                if metadata.is_ok_and(|metadata| rest.is_empty() || metadata.is_dir()) {
+                    self.expand_into(path, rest, paths);
+                }
+            }
+        }
+    }
+
+    /// Expands `rest` in `top` and in every directory below it, except hidden directories, symlinks, virtual
+    /// environments and other projects of the rule, which are cleaned on their own.
+    fn expand_globstar(&self, top: PathBuf, rest: &[&OsStr], paths: &mut Vec<PathBuf>) {
+        let mut dirs = vec![top.clone()];
+        while let Some(dir) = dirs.pop() {
+            let entries: Vec<_> = fs::read_dir(&dir).into_iter().flatten().flatten().collect();
+            let names = || entries.iter().filter_map(|entry| entry.file_name().into_string().ok());
+            if dir != top && names().any(|name| name == VENV_MARKER || self.matches(&name)) {
+                continue;
+            }
+            for entry in &entries {
+                let hidden = entry.file_name().to_str().is_some_and(|name| name.starts_with('.'));
+                if !hidden && entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                    dirs.push(entry.path());
+                }
+            }
+            self.expand_into(dir, rest, paths);
+        }
+    }
+
+    /// Builds a rule out of a `[[rule]]` table, fields that are missing are taken from `base`.
+    fn from_table(table: &Table, base: Option<&Rule>) -> std::result::Result<Self, String> {
+        let name = get_string(table, "name")?.ok_or("a rule must have a `name`")?;
+        let marker = get_string(table, "marker")?.or_else(|| base.map(|rule| rule.marker.clone()));
+        let marker = marker.ok_or_else(|| format!("rule `{name}` must have a `marker`"))?;
+        let enabled = get_bool(table, "enabled")?.or(base.map(|rule| rule.enabled)).unwrap_or(true);
+        let delete = get_strings(table, "delete")?;
+        let command = get_strings(table, "command")?;
+        let workdir =
+            get_string(table, "workdir")?.map(|workdir| project_path(&name, "workdir", workdir)).transpose()?;
+        let action = match (command, delete) {
+            (Some(_), Some(_)) => return Err(format!("rule `{name}` can't have both a `command` and `delete`")),
+            (Some(argv), None) if argv.is_empty() => return Err(format!("rule `{name}` has an empty `command`")),
+            (Some(argv), None) => RuleAction::Command { argv, workdir: workdir.unwrap_or_else(|| ".".into()) },
+            (None, Some(dirs)) => RuleAction::Delete(project_paths(&name, "delete", dirs)?),
+            (None, None) => match (base.map(|rule| &rule.action), workdir) {
+                (Some(RuleAction::Command { argv, .. }), Some(workdir)) => {
+                    RuleAction::Command { argv: argv.clone(), workdir }
+                }
+                (Some(action), _) => action.clone(),
+                (None, _) => return Err(format!("rule `{name}` must have either a `command` or `delete`")),
+            },
+        };
+        let outputs = match get_strings(table, "outputs")? {
+            Some(outputs) => project_paths(&name, "outputs", outputs)?,
+            None => match (&action, base) {
+                (RuleAction::Delete(dirs), _) => dirs.clone(),
+                (_, Some(base)) => base.outputs.clone(),
+                (_, None) => Vec::new(),
+            },
+        };
+        let timeout = match get_string(table, "timeout")? {
+            Some(timeout) => Some(parse_duration(&timeout).map_err(|err| format!("rule `{name}`: {err}"))?),
+            None => base.and_then(|rule| rule.timeout),
+        };
+        let fallback = match table.get("fallback") {
+            None => base.and_then(|rule| rule.fallback.clone()),
+            Some(Value::String(kind)) if kind == "ninja-log" => Some(Fallback::NinjaLog),
+            Some(Value::String(kind)) => {
+                return Err(format!("rule `{name}` has an unknown `fallback` \"{kind}\", expected \"ninja-log\""));
+            }
+            Some(_) => match get_strings(table, "fallback")? {
+                Some(dirs) => Some(Fallback::Delete(project_paths(&name, "fallback", dirs)?)),
+                None => None,
+            },
+        };
+        let writable = match get_strings(table, "writable")? {
+            // Paths starting with `~` are in the home directory, the others must be inside the project.
+            Some(paths) => paths
+                .into_iter()
+                .map(|path| {
+                    if Path::new(&path).starts_with("~") {
+                        Ok(PathBuf::from(path))
+                    } else {
+                        project_path(&name, "writable", path)
+                    }
+                })
+                .collect::<std::result::Result<_, _>>()?,
+            None => base.map(|rule| rule.writable.clone()).unwrap_or_default(),
+        };
+        let hidden = get_bool(table, "hidden")?.or(base.map(|rule| rule.hidden)).unwrap_or(false);
+        let supersedes = match get_strings(table, "supersedes")? {
+            Some(names) => names,
+            None => base.map(|rule| rule.supersedes.clone()).unwrap_or_default(),
+        };
+        let kind = match get_string(table, "kind")? {
+            Some(kind) => Some(RuleKind::from_name(&kind).ok_or_else(|| {
+                let names: Vec<_> = RuleKind::NAMES.iter().map(|(name, _)| format!("\"{name}\"")).collect();
+                format!("rule `{name}` has an unknown `kind` \"{kind}\", expected one of {}", names.join(", "))
+            })?),
+            None => base.and_then(|rule| rule.kind),
+        };
+        const FIELDS: &[&str] = &[
+            "name",
+            "marker",
+            "command",
+            "workdir",
+            "delete",
+            "outputs",
+            "enabled",
+            "timeout",
+            "fallback",
+            "writable",
+            "hidden",
+            "supersedes",
+            "kind",
+        ];
+        for key in table.keys() {
+            if !FIELDS.contains(&key.as_str()) {
+                return Err(format!("unknown field `{key}` in rule `{name}`"));
+            }
+        }
+        Ok(Self { name, marker, action, outputs, enabled, timeout, fallback, writable, hidden, supersedes, kind })
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+impl Cleaner for Rule {
+    fn plan(&self, project: &Project) -> Result<Option<Action>> {
+        match &self.action {
+            RuleAction::Command { argv, workdir } => {
+                let mut argv = argv.iter().map(|arg| substitute(arg, project));
+                let program = argv.next().expect("Rules always have a program");
+                Ok(Some(Action::Command { program, args: argv.collect(), dir: project.root.join(workdir) }))
+            }
+            RuleAction::Delete(dirs) => plan_delete(self, dirs, project),
+        }
+    }
+}
+
+/// Deletes the directories `dirs` of `project` that exist, as `rule` expands them.
+fn plan_delete(rule: &Rule, dirs: &[PathBuf], project: &Project) -> Result<Option<Action>> {
+    let mut paths = Vec::with_capacity(dirs.len());
+    for path in rule.expand(dirs, &project.root) {
+        if !path.strip_prefix(&project.root).is_ok_and(|relative| real_parents(&project.root, relative)) {
+            continue;
+        }
+        // use symlink_metadata to make sure it's a directory and not follow the symlink
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => paths.push(path),
+            Ok(_) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+    }
+    // A glob can match inside another directory that is deleted whole, like `**/__pycache__` in `build`.
+    let all = paths.clone();
+    paths.retain(|path| !all.iter().any(|outer| outer != path && path.starts_with(outer)));
+    Ok((!paths.is_empty()).then_some(Action::Remove(paths)))
+}
+
+/// Deletes the files ninja built in `project`, according to its `.ninja_log`. Outputs outside the project are
+/// left alone, the log comes from the project as much as a `Makefile` does.
+fn plan_ninja_log(project: &Project) -> Result<Option<Action>> {
+    let paths = ninja_log_outputs(&project.root)?;
+    Ok((!paths.is_empty()).then_some(Action::Remove(paths)))
+}
+
+/// The files listed in the `.ninja_log` of `dir` that exist, none without a log.
+fn ninja_log_outputs(dir: &Path) -> Result<Vec<PathBuf>> {
+    let log = match fs::read(dir.join(".ninja_log")) {
+        Ok(log) => log,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let log = String::from_utf8_lossy(&log);
+    let mut lines = log.lines();
+    if !lines.next().is_some_and(|header| header.starts_with("# ninja log v")) {
+        let msg = format!("{}: not a ninja log", dir.join(".ninja_log").display());
+        return Err(Error::new(ErrorKind::InvalidData, msg));
+    }
+    // Every line is `start, end, mtime, output, hash` separated by tabs, an output is listed once per build.
+    let mut outputs: Vec<_> = lines
+        .filter_map(|line| line.split('\t').nth(3))
+        .map(Path::new)
+        .filter(|output| output.components().all(|component| matches!(component, Component::Normal(_))))
+        .filter(|output| real_parents(dir, output))
+        .collect();
+    outputs.sort_unstable();
+    outputs.dedup();
+    let mut paths = Vec::new();
+    for path in outputs.into_iter().map(|output| dir.join(output)) {
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if !metadata.is_dir() => paths.push(path),
+            Ok(_) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(paths)
+}
+
+/// Whether the directories between `dir` and its descendant `relative` are all real directories. A symlink among
+/// them could lead a deletion out of `dir`.
+fn real_parents(dir: &Path, relative: &Path) -> bool {
+    let parents = relative.parent().into_iter().flat_map(Path::ancestors);
+    parents
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .all(|parent| fs::symlink_metadata(dir.join(parent)).is_ok_and(|metadata| metadata.is_dir()))
+}
+
+/// The rules used to detect projects, the built-in ones merged with the configuration files.
+#[derive(Clone, Debug)]
+pub struct RuleSet {
+    rules: Vec<Arc<Rule>>,
+}
+
+impl RuleSet {
+    /// The built-in Cargo, Make, Ninja, Gradle, git, npm, Python and virtual environment rules.
+    pub fn builtin() -> Self {
+        let mut rules = Self { rules: Vec::new() };
+        rules.merge_str(BUILTIN_RULES, "builtin.toml").expect("The built-in rules are valid");
+        rules
+    }
+
+    /// The built-in rules, merged with `~/.config/code-clean/config.toml` and then `<dir>/.code-clean.toml`.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let mut rules = Self::builtin();
+        if let Some(config_dir) = user_config_dir() {
+            rules.merge_file(&config_dir.join("code-clean").join("config.toml"))?;
+        }
+        rules.merge_file(&dir.join(".code-clean.toml"))?;
+        Ok(rules)
+    }
+
+    /// Merges the rules of a configuration file, a missing file is ignored.
+    pub fn merge_file(&mut self, path: &Path) -> Result<()> {
+        match fs::read_to_string(path) {
+            Ok(src) => self.merge_str(&src, &path.display().to_string()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(Error::new(err.kind(), format!("{}: {err}", path.display()))),
+        }
+    }
+
+    /// Merges the rules of a configuration, a rule with the name of an existing rule overrides its fields.
+    pub fn merge_str(&mut self, src: &str, origin: &str) -> Result<()> {
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidData, format!("{origin}: {msg}"));
+        let mut document = toml::parse(src).map_err(|err| invalid(err.to_string()))?;
+        let tables = match document.remove("rule") {
+            None => Vec::new(),
+            Some(Value::Array(tables)) => tables,
+            Some(value) => {
+                return Err(invalid(format!("`rule` must be an array of tables, found {}", value.type_name())));
+            }
+        };
+        if let Some(key) = document.keys().next() {
+            return Err(invalid(format!("unknown field `{key}`")));
+        }
+        for table in tables {
+            let Value::Table(table) = table else { return Err(invalid("`rule` must be an array of tables".into())) };
+            let name = get_string(&table, "name").map_err(invalid)?;
+            let existing = self.rules.iter().position(|rule| Some(&rule.name) == name.as_ref());
+            let rule = Rule::from_table(&table, existing.map(|i| &*self.rules[i])).map_err(invalid)?;
+            match existing {
+                Some(i) => self.rules[i] = Arc::new(rule),
+                None => self.rules.push(Arc::new(rule)),
+            }
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn rules(&self) -> &[Arc<Rule>] {
+        &self.rules
+    }
+
+    /// The enabled rules whose marker matches `file_name`.
+    #[inline(always)]
+    pub fn matching<'a>(&'a self, file_name: &'a str) -> impl DoubleEndedIterator<Item = &'a Arc<Rule>> {
+        self.rules.iter().filter(move |rule| rule.matches(file_name))
+    }
+}
+
+#[inline(always)]
+fn user_config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()).map(PathBuf::from).or_else(|| {
+        env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")).map(|home| Path::new(&home).join(".config"))
+    })
+}
+
+/// Replaces the `{marker}` and `{root}` placeholders in a command argument.
+#[inline(always)]
+fn substitute(arg: &str, project: &Project) -> OsString {
+    match arg {
+        // Keep non UTF-8 paths intact when the whole argument is a placeholder.
+        "{marker}" => project.marker.clone().into(),
+        "{root}" => project.root.clone().into(),
+        _ => arg
+            .replace("{marker}", &project.marker.to_string_lossy())
+            .replace("{root}", &project.root.to_string_lossy())
+            .into(),
+    }
+}
+
+/// `output` relative to the project directory without `.` components, or `None` if it isn't inside the project.
+/// An empty path is the project directory itself.
+#[inline(always)]
+pub(crate) fn normalize_output(output: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in output.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalized)
+}
+
+#[inline(always)]
+fn is_glob(component: &str) -> bool {
+    component.contains(['*', '?', '{'])
+}
+
+/// Matches `name` against a glob `pattern` supporting `*`, `?` and `{a,b}` alternatives, which don't nest.
+pub(crate) fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    if let Some(open) = pattern.iter().position(|&c| c == b'{')
+        && let Some(close) = pattern[open..].iter().position(|&c| c == b'}').map(|len| open + len)
+    {
+        let (prefix, suffix) = (&pattern[..open], &pattern[close + 1..]);
+        let mut alternatives = pattern[open + 1..close].split(|&c| c == b',');
+        return alternatives.any(|alternative| glob_match(&[prefix, alternative, suffix].concat(), name));
+    }
+    let (mut p, mut n) = (0, 0);
+    // Where to resume after the last `*`, if the rest didn't match.
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    backtrack = Some((star, matched + 1));
+                    p = star + 1;
+                    n = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// The paths of the field `key` of rule `name`, which must all stay inside the project.
+#[inline(always)]
+fn project_paths(name: &str, key: &str, paths: Vec<String>) -> std::result::Result<Vec<PathBuf>, String> {
+    paths.into_iter().map(|path| project_path(name, key, path)).collect()
+}
+
+/// A path of the field `key` of rule `name`, which must stay inside the project.
+fn project_path(name: &str, key: &str, path: String) -> std::result::Result<PathBuf, String> {
+    match normalize_output(Path::new(&path)) {
+        Some(_) => Ok(PathBuf::from(path)),
+        None => Err(format!("rule `{name}` has \"{path}\" in `{key}`, which must be inside the project")),
+    }
+}
+
+#[inline(always)]
+fn get_string(table: &Table, key: &str) -> std::result::Result<Option<String>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(value) => Err(format!("`{key}` must be a string, found {}", value.type_name())),
+    }
+}
+
+#[inline(always)]
+fn get_bool(table: &Table, key: &str) -> std::result::Result<Option<bool>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Boolean(b)) => Ok(Some(*b)),
+        Some(value) => Err(format!("`{key}` must be a boolean, found {}", value.type_name())),
+    }
+}
+
+#[inline(always)]
+fn get_strings(table: &Table, key: &str) -> std::result::Result<Option<Vec<String>>, String> {
+    let Some(value) = table.get(key) else { return Ok(None) };
+    let err = || format!("`{key}` must be an array of strings, found {}", value.type_name());
+    let Value::Array(values) = value else { return Err(err()) };
+    values
+        .iter()
+        .map(|value| if let Value::String(s) = value { Ok(s.clone()) } else { Err(err()) })
+        .collect::<std::result::Result<_, _>>()
+        .map(Some)
+}
@@ -0,0 +1,288 @@
+//! Confining the cleaners, which run code from the projects they clean.
+//!
+//! Every cleaner gets a scrubbed environment. On Linux, Landlock only lets it write below its project, the
+//! [`Rule::writable`](crate::Rule::writable) paths of its rule and the temporary directory, and it can be cut off
+//! from the network in a namespace of its own. What the kernel doesn't support is left out, and reported once.
+
+use std::env;
+use std::ffi::OsString;
+use std::io::Result;
+use std::path::PathBuf;
+use std::process::{Child, Command};
+
+/// The variables a cleaner keeps, every other one is removed: they often hold credentials.
+const KEPT_VARS: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "SHELL",
+    "LANG",
+    "LANGUAGE",
+    "TZ",
+    "TERM",
+    "TMPDIR",
+    "CARGO_HOME",
+    "RUSTUP_HOME",
+    "RUSTUP_TOOLCHAIN",
+    "CARGO_TARGET_DIR",
+    "CARGO_BUILD_TARGET_DIR",
+    "JAVA_HOME",
+    "GRADLE_USER_HOME",
+    "ANDROID_HOME",
+    "ANDROID_SDK_ROOT",
+];
+
+/// Spawns the cleaners confined, see the [module documentation](self).
+pub(crate) struct Sandbox {
+    isolate_network: bool,
+    #[cfg(target_os = "linux")]
+    landlock: Option<os_sandbox::Landlock>,
+    /// What couldn't be confined, until it is reported.
+    warnings: Vec<String>,
+}
+
+impl Sandbox {
+    /// Probes what the kernel supports, cutting the cleaners off from the network if `isolate_network`.
+    pub(crate) fn new(isolate_network: bool) -> Self {
+        #[allow(unused_mut)]
+        let mut warnings = Vec::new();
+        #[cfg(target_os = "linux")]
+        let landlock = os_sandbox::Landlock::new()
+            .inspect_err(|err| warnings.push(format!("Landlock is unavailable ({err}), cleaners can write anywhere")))
+            .ok();
+        #[cfg(not(target_os = "linux"))]
+        if isolate_network {
+            warnings.push("cutting cleaners off from the network is only supported on Linux".to_owned());
+        }
+        Self {
+            isolate_network: cfg!(target_os = "linux") && isolate_network,
+            #[cfg(target_os = "linux")]
+            landlock,
+            warnings,
+        }
+    }
+
+    /// Takes what couldn't be confined since the last call.
+    #[inline(always)]
+    pub(crate) fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Spawns the command `build` returns, which may only write below `writable`.
+    pub(crate) fn spawn(&mut self, build: impl Fn() -> Command, writable: &[PathBuf]) -> Result<Child> {
+        let mut command = build();
+        self.confine(&mut command, writable, self.isolate_network)?;
+        match command.spawn() {
+            Err(err) if self.isolate_network => {
+                // Nothing ran yet if entering the namespace failed, so try again without it. If that fails too,
+                // the namespace wasn't the problem.
+                let mut command = build();
+                self.confine(&mut command, writable, false)?;
+                let child = command.spawn()?;
+                self.isolate_network = false;
+                self.warnings.push(format!("can't cut cleaners off from the network ({err}), they keep it"));
+                Ok(child)
+            }
+            spawned => spawned,
+        }
+    }
+
+    fn confine(&self, command: &mut Command, writable: &[PathBuf], isolate_network: bool) -> Result<()> {
+        let kept: Vec<(OsString, OsString)> = env::vars_os()
+            .filter(|(name, _)| name.to_str().is_some_and(|name| KEPT_VARS.contains(&name) || name.starts_with("LC_")))
+            .collect();
+        command.env_clear().envs(kept);
+        #[cfg(target_os = "linux")]
+        {
+            // Scratch space, which plenty of tools need even to clean.
+            let writable: Vec<PathBuf> = writable.iter().cloned().chain([env::temp_dir()]).collect();
+            os_sandbox::confine(command, self.landlock.as_ref(), &writable, isolate_network)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = (writable, isolate_network);
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod os_sandbox {
+    use std::ffi::{CString, c_char, c_int, c_long, c_uint, c_ulong, c_void};
+    use std::fs::OpenOptions;
+    use std::io::{Error, ErrorKind, Result};
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::process::CommandExt;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    const SYS_LANDLOCK_CREATE_RULESET: c_long = 444;
+    const SYS_LANDLOCK_ADD_RULE: c_long = 445;
+    const SYS_LANDLOCK_RESTRICT_SELF: c_long = 446;
+    const LANDLOCK_CREATE_RULESET_VERSION: c_uint = 1;
+    const LANDLOCK_RULE_PATH_BENEATH: c_int = 1;
+    const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+    const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+    const ACCESS_FS_MAKE_CHAR: u64 = 1 << 6;
+    const ACCESS_FS_MAKE_DIR: u64 = 1 << 7;
+    const ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+    const ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+    const ACCESS_FS_MAKE_FIFO: u64 = 1 << 10;
+    const ACCESS_FS_MAKE_BLOCK: u64 = 1 << 11;
+    const ACCESS_FS_MAKE_SYM: u64 = 1 << 12;
+    /// Since ABI 2.
+    const ACCESS_FS_REFER: u64 = 1 << 13;
+    /// Since ABI 3.
+    const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+    const PR_SET_NO_NEW_PRIVS: c_int = 38;
+    const CLONE_NEWUSER: c_int = 0x10000000;
+    const CLONE_NEWNET: c_int = 0x40000000;
+    const O_PATH: c_int = 0o10000000;
+    const O_WRONLY: c_int = 1;
+    const O_CLOEXEC: c_int = 0o2000000;
+    /// Devices anything may write to, outside the writable directories.
+    const DEVICES: &[&str] = &["/dev/null", "/dev/zero", "/dev/full", "/dev/tty"];
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+    unsafe extern "C" {
+        fn syscall(number: c_long, ...) -> c_long;
+        fn prctl(option: c_int, arg2: c_ulong, arg3: c_ulong, arg4: c_ulong, arg5: c_ulong) -> c_int;
+        fn unshare(flags: c_int) -> c_int;
+        fn open(path: *const c_char, flags: c_int, ...) -> c_int;
+        fn write(fd: c_int, buf: *const c_void, count: usize) -> isize;
+        fn close(fd: c_int) -> c_int;
+        fn getuid() -> c_uint;
+        fn getgid() -> c_uint;
+    }
+
+    /// The write accesses Landlock can restrict on this kernel.
+    pub(super) struct Landlock {
+        handled: u64,
+    }
+
+    impl Landlock {
+        pub(super) fn new() -> Result<Self> {
+            let abi = unsafe {
+                syscall(
+                    SYS_LANDLOCK_CREATE_RULESET,
+                    std::ptr::null::<RulesetAttr>(),
+                    0usize,
+                    LANDLOCK_CREATE_RULESET_VERSION,
+                )
+            };
+            if abi < 1 {
+                return Err(Error::last_os_error());
+            }
+            let mut handled = ACCESS_FS_WRITE_FILE
+                | ACCESS_FS_REMOVE_DIR
+                | ACCESS_FS_REMOVE_FILE
+                | ACCESS_FS_MAKE_CHAR
+                | ACCESS_FS_MAKE_DIR
+                | ACCESS_FS_MAKE_REG
+                | ACCESS_FS_MAKE_SOCK
+                | ACCESS_FS_MAKE_FIFO
+                | ACCESS_FS_MAKE_BLOCK
+                | ACCESS_FS_MAKE_SYM;
+            if abi >= 2 {
+                handled |= ACCESS_FS_REFER;
+            }
+            if abi >= 3 {
+                handled |= ACCESS_FS_TRUNCATE;
+            }
+            Ok(Self { handled })
+        }
+
+        /// A ruleset allowing writes below `writable`, and to a few devices.
+        fn ruleset(&self, writable: &[PathBuf]) -> Result<OwnedFd> {
+            let attr = RulesetAttr { handled_access_fs: self.handled };
+            let fd = unsafe { syscall(SYS_LANDLOCK_CREATE_RULESET, &attr, size_of::<RulesetAttr>(), 0 as c_uint) };
+            if fd == -1 {
+                return Err(Error::last_os_error());
+            }
+            let ruleset = unsafe { OwnedFd::from_raw_fd(fd as RawFd) };
+            let files = ACCESS_FS_WRITE_FILE | (self.handled & ACCESS_FS_TRUNCATE);
+            let devices = DEVICES.iter().map(|device| (Path::new(device), files));
+            for (path, access) in writable.iter().map(|path| (path.as_path(), self.handled)).chain(devices) {
+                let parent = match OpenOptions::new().read(true).custom_flags(O_PATH | O_CLOEXEC).open(path) {
+                    Ok(parent) => parent,
+                    // Nothing to allow, like a cache that wasn't created yet.
+                    Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                    Err(err) => return Err(Error::new(err.kind(), format!("{}: {err}", path.display()))),
+                };
+                let rule = PathBeneathAttr { allowed_access: access, parent_fd: parent.as_raw_fd() };
+                let added = unsafe {
+                    syscall(SYS_LANDLOCK_ADD_RULE, ruleset.as_raw_fd(), LANDLOCK_RULE_PATH_BENEATH, &rule, 0 as c_uint)
+                };
+                if added == -1 {
+                    return Err(Error::last_os_error());
+                }
+            }
+            Ok(ruleset)
+        }
+    }
+
+    /// Makes the child enter the sandbox right before it executes the cleaner.
+    pub(super) fn confine(
+        command: &mut Command,
+        landlock: Option<&Landlock>,
+        writable: &[PathBuf],
+        isolate_network: bool,
+    ) -> Result<()> {
+        let ruleset = landlock.map(|landlock| landlock.ruleset(writable)).transpose()?;
+        // Everything the child needs is prepared here, it can't allocate between fork and exec.
+        let (uid, gid) = unsafe { (getuid(), getgid()) };
+        let maps = [
+            (c"/proc/self/setgroups", CString::from(c"deny")),
+            (c"/proc/self/uid_map", CString::new(format!("{uid} {uid} 1")).expect("no NUL in a number")),
+            (c"/proc/self/gid_map", CString::new(format!("{gid} {gid} 1")).expect("no NUL in a number")),
+        ];
+        let enter = move || {
+            if isolate_network && unsafe { unshare(CLONE_NEWNET) } == -1 {
+                // Without CAP_SYS_ADMIN, a user namespace grants it, mapping our own ids only.
+                if unsafe { unshare(CLONE_NEWUSER | CLONE_NEWNET) } == -1 {
+                    return Err(Error::last_os_error());
+                }
+                for (path, content) in &maps {
+                    write_file(path.as_ptr(), content)?;
+                }
+            }
+            if let Some(ruleset) = &ruleset {
+                // Landlock requires it, and it also keeps setuid programs from running with more privileges.
+                if unsafe { prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } == -1
+                    || unsafe { syscall(SYS_LANDLOCK_RESTRICT_SELF, ruleset.as_raw_fd(), 0 as c_uint) } == -1
+                {
+                    return Err(Error::last_os_error());
+                }
+            }
+            Ok(())
+        };
+        // SAFETY: `enter` only makes system calls, it doesn't allocate or take locks.
+        unsafe { command.pre_exec(enter) };
+        Ok(())
+    }
+
+    /// Writes `content` to the file at `path`, between fork and exec.
+    #[inline(always)]
+    fn write_file(path: *const c_char, content: &CString) -> Result<()> {
+        let fd = unsafe { open(path, O_WRONLY | O_CLOEXEC) };
+        if fd == -1 {
+            return Err(Error::last_os_error());
+        }
+        let bytes = content.as_bytes();
+        let written = unsafe { write(fd, bytes.as_ptr().cast(), bytes.len()) };
+        unsafe { close(fd) };
+        if written == -1 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+}
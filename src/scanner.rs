@@ -0,0 +1,415 @@
+use crate::quarantine::TAG as QUARANTINE_TAG;
+use crate::rule::{VENV_MARKER, glob_match, normalize_output};
+use crate::{Fallback, Project, Rule, RuleSet, interrupt_count};
+use std::collections::VecDeque;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::mem;
+use std::num::NonZero;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+
+type ScanResult = Result<Project, ScanError>;
+/// A walk that started, with its results and its threads.
+type Started = (Arc<Walk>, Receiver<ScanResult>, Vec<JoinHandle<()>>);
+
+/// Directories are read, and deleted, by at most this many threads by default, more only add contention on the disk.
+pub(crate) const MAX_THREADS: usize = 8;
+
+#[inline(always)]
+fn should_ignore(path: &Path) -> bool {
+    // Installed dependencies, whatever rule matched around them, their Makefiles aren't ours to run.
+    const IGNORE_LIST: &[&str] = &["node_modules"];
+    IGNORE_LIST.iter().any(|&ignore| path.ends_with(ignore))
+}
+
+#[inline(always)]
+fn is_hidden(path: &Path) -> bool {
+    path.file_name().and_then(OsStr::to_str).map(|s| s.starts_with('.')).unwrap_or(false)
+}
+
+/// An error encountered while traversing `path`, the scan continues past it.
+#[derive(Debug)]
+pub struct ScanError {
+    pub path: PathBuf,
+    pub error: io::Error,
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} => {}", self.path, self.error)
+    }
+}
+
+impl std::error::Error for ScanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Walks a directory tree on several threads and yields every [`Project`] in it, in no particular order.
+///
+/// Symlinks are never followed, and hidden and ignored directories are not descended into, nor are the
+/// [`Rule::outputs`] of the projects found, their cleaners are about to delete them, nor Python virtual
+/// environments. Hidden directories are only looked into for the markers of [`Rule::hidden`] rules, and outputs
+/// for those of rules that own their whole directory, like a CMake build in the `build` directory of a Makefile.
+/// Nothing in a [`Quarantine`](crate::Quarantine) is found. The walk ends early once a signal was received, see
+/// [`handle_interrupts`](crate::handle_interrupts), and [`Scanner::stop`] tells where it ended.
+///
+/// [`Rule::outputs`]: crate::Rule::outputs
+/// [`Rule::hidden`]: crate::Rule::hidden
+pub struct Scanner {
+    /// Where the walk starts, until it does.
+    dirs: Vec<PendingDir>,
+    rules: RuleSet,
+    threads: usize,
+    /// Set once the walk started.
+    walk: Option<Started>,
+}
+
+/// A directory the walk didn't read yet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PendingDir {
+    pub path: PathBuf,
+    /// Outputs of the projects above it that are inside it, relative to it. They are skipped too.
+    pub owned: Vec<PathBuf>,
+}
+
+impl Scanner {
+    #[inline(always)]
+    pub fn new(root: impl Into<PathBuf>, rules: RuleSet) -> Self {
+        Self::with_dirs(vec![PendingDir { path: root.into(), owned: Vec::new() }], rules)
+    }
+
+    /// Walks the trees below `dirs`, like those [`Scanner::stop`] returned to continue an earlier walk.
+    #[inline(always)]
+    pub fn with_dirs(dirs: Vec<PendingDir>, rules: RuleSet) -> Self {
+        let threads = thread::available_parallelism().map_or(1, NonZero::get).min(MAX_THREADS);
+        Self { dirs, rules, threads, walk: None }
+    }
+
+    /// Reads directories on `threads` threads, at least one.
+    #[inline(always)]
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Stops the walk, and returns the directories it didn't read with the projects it found but didn't yield
+    /// yet. Together they are everything the walk had left to yield.
+    pub fn stop(&mut self) -> (Vec<PendingDir>, Vec<Project>) {
+        let Some((walk, receiver, threads)) = self.walk.take() else { return (mem::take(&mut self.dirs), Vec::new()) };
+        walk.stop();
+        // A thread finishes the directory it is reading first, queueing its subdirectories.
+        for thread in threads {
+            let _ = thread.join();
+        }
+        let dirs = walk.queues.iter().flat_map(|queue| mem::take(&mut *lock(queue))).collect();
+        (dirs, receiver.try_iter().filter_map(Result::ok).collect())
+    }
+
+    fn start(&mut self, dirs: Vec<PendingDir>) -> io::Result<()> {
+        let walk = Arc::new(Walk {
+            rules: self.rules.clone(),
+            hidden_rules: self.rules.rules().iter().filter(|rule| rule.enabled && rule.hidden).cloned().collect(),
+            build_dir_rules: (self.rules.rules().iter())
+                .filter(|rule| {
+                    rule.enabled && rule.outputs.iter().any(|output| normalize_output(output) == Some(PathBuf::new()))
+                })
+                .cloned()
+                .collect(),
+            queues: (0..self.threads).map(|_| Mutex::new(VecDeque::new())).collect(),
+            pending: AtomicUsize::new(dirs.len()),
+            stopped: AtomicBool::new(false),
+            idle: Mutex::new(0),
+            wakeup: Condvar::new(),
+        });
+        lock(&walk.queues[0]).extend(dirs);
+        let (sender, receiver) = mpsc::channel();
+        let mut threads = Vec::with_capacity(self.threads);
+        for worker in 0..self.threads {
+            let (thread_walk, sender) = (Arc::clone(&walk), sender.clone());
+            let spawned =
+                thread::Builder::new().name("code-clean-scan".into()).spawn(move || thread_walk.work(worker, &sender));
+            match spawned {
+                Ok(thread) => threads.push(thread),
+                Err(err) => {
+                    walk.stop();
+                    return Err(err);
+                }
+            }
+        }
+        self.walk = Some((walk, receiver, threads));
+        Ok(())
+    }
+}
+
+impl Iterator for Scanner {
+    type Item = ScanResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if interrupt_count() > 0 {
+            return None;
+        }
+        if self.walk.is_none() {
+            let dirs = mem::take(&mut self.dirs);
+            let path = dirs.first()?.path.clone();
+            if let Err(error) = self.start(dirs) {
+                return Some(Err(ScanError { path, error }));
+            }
+        }
+        let (_, receiver, _) = self.walk.as_ref()?;
+        // Every thread dropped its sender once the walk is over.
+        receiver.recv().ok()
+    }
+}
+
+impl Drop for Scanner {
+    #[inline(always)]
+    fn drop(&mut self) {
+        if let Some((walk, _, _)) = &self.walk {
+            walk.stop();
+        }
+    }
+}
+
+#[inline(always)]
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// What `owned` leaves to skip inside the subdirectory `name`, `None` if the subdirectory is skipped itself.
+#[inline(always)]
+fn owned_below(owned: &[PathBuf], name: &OsStr) -> Option<Vec<PathBuf>> {
+    let mut below = Vec::new();
+    owned.iter().all(|output| carry(output, name, &mut below)).then_some(below)
+}
+
+/// Adds what `output` leaves to skip inside the subdirectory `name` to `below`, false if it is the subdirectory.
+fn carry(output: &Path, name: &OsStr, below: &mut Vec<PathBuf>) -> bool {
+    let mut components = output.iter();
+    let Some(first) = components.next() else { return false };
+    let rest = components.as_path();
+    if first == "**" {
+        // Stands for no directory as well, so `rest` may already start at `name`.
+        below.push(output.to_path_buf());
+        return carry(rest, name, below);
+    }
+    if !glob_match(first.as_encoded_bytes(), name.as_encoded_bytes()) {
+        return true;
+    }
+    if rest.as_os_str().is_empty() {
+        return false;
+    }
+    below.push(rest.to_path_buf());
+    true
+}
+
+/// The state shared by the threads of a walk.
+struct Walk {
+    rules: RuleSet,
+    /// The enabled rules with [`Rule::hidden`] set.
+    hidden_rules: Vec<Arc<Rule>>,
+    /// The enabled rules with `.` in their outputs.
+    build_dir_rules: Vec<Arc<Rule>>,
+    /// The directories left to read, one queue per thread. Every thread pushes and pops at the back of its own
+    /// queue, and steals from the front of the others' when it runs out, taking the shallowest directories.
+    queues: Vec<Mutex<VecDeque<PendingDir>>>,
+    /// Directories queued or being read, the walk is over once there are none.
+    pending: AtomicUsize,
+    /// Set when nobody wants the results anymore.
+    stopped: AtomicBool,
+    /// How many threads wait on `wakeup`. Held by a thread that found no directory to read while it checks again.
+    idle: Mutex<usize>,
+    /// Notified when directories are queued, and when the walk is over or stopped.
+    wakeup: Condvar,
+}
+
+impl Walk {
+    fn work(&self, worker: usize, results: &Sender<ScanResult>) {
+        while !self.stopped.load(Ordering::Relaxed) && interrupt_count() == 0 {
+            let Some(dir) = self.pop(worker) else {
+                if !self.wait_for_work() {
+                    return;
+                }
+                continue;
+            };
+            if self.read(worker, dir, results).is_err() {
+                self.stop();
+            }
+            if self.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+                self.wake();
+            }
+        }
+    }
+
+    /// Sleeps until another thread queues directories, which it is probably reading now, or the walk is stopped.
+    /// Returns `false` once the walk is over.
+    fn wait_for_work(&self) -> bool {
+        let mut idle = lock(&self.idle);
+        if self.pending.load(Ordering::SeqCst) == 0 {
+            return false;
+        }
+        // Checked while holding `idle`, the threads that change them take it to wake the others afterwards.
+        if self.stopped.load(Ordering::Relaxed) || self.queues.iter().any(|queue| !lock(queue).is_empty()) {
+            return true;
+        }
+        *idle += 1;
+        let mut idle = self.wakeup.wait(idle).unwrap_or_else(PoisonError::into_inner);
+        *idle -= 1;
+        true
+    }
+
+    /// Wakes the threads waiting for directories to read.
+    #[inline(always)]
+    fn wake(&self) {
+        if *lock(&self.idle) > 0 {
+            self.wakeup.notify_all();
+        }
+    }
+
+    #[inline(always)]
+    fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        self.wake();
+    }
+
+    #[inline(always)]
+    fn pop(&self, worker: usize) -> Option<PendingDir> {
+        if let Some(dir) = lock(&self.queues[worker]).pop_back() {
+            return Some(dir);
+        }
+        let count = self.queues.len();
+        (1..count).find_map(|offset| lock(&self.queues[(worker + offset) % count]).pop_front())
+    }
+
+    /// Sends the projects in `dir` and queues its subdirectories but the outputs of these projects, fails once
+    /// the results aren't received anymore.
+    fn read(
+        &self,
+        worker: usize,
+        dir: PendingDir,
+        results: &Sender<ScanResult>,
+    ) -> Result<(), mpsc::SendError<ScanResult>> {
+        let PendingDir { path: dir, mut owned } = dir;
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(error) => return results.send(Err(ScanError { path: dir, error })),
+        };
+        let mut subdirs = Vec::new();
+        let mut hidden = Vec::new();
+        let mut venv = false;
+        let mut quarantine = false;
+        // A project is found once per rule, even when several of the files its marker matches are there.
+        let mut found: Vec<Project> = Vec::new();
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(error) => {
+                    results.send(Err(ScanError { path: dir.clone(), error }))?;
+                    continue;
+                }
+            };
+            let path = entry.path();
+            // The type comes from the directory entry without another stat where the platform has it, and is
+            // the type of a symlink itself rather than of its target, so symlinks are never traversed.
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(error) => {
+                    results.send(Err(ScanError { path, error }))?;
+                    continue;
+                }
+            };
+            if file_type.is_dir() && !should_ignore(&path) {
+                if !is_hidden(&path) {
+                    subdirs.push(path.clone());
+                } else if !self.hidden_rules.is_empty() {
+                    hidden.push(path.clone());
+                }
+            }
+            let Some(file_name) = path.file_name().and_then(OsStr::to_str) else { continue };
+            venv |= file_name == VENV_MARKER;
+            quarantine |= file_name == QUARANTINE_TAG;
+            for rule in self.rules.matching(file_name) {
+                if !found.iter().any(|project| Arc::ptr_eq(&project.kind, rule)) {
+                    found.push(Project { kind: rule.clone(), root: dir.clone(), marker: path.clone() });
+                }
+            }
+        }
+        // Only known once every entry was read, like the outputs, the marker can come after them.
+        if quarantine {
+            return Ok(());
+        }
+        for project in without_superseded(found) {
+            owned.extend(project.kind.outputs.iter().filter_map(|output| normalize_output(output)));
+            // A Ninja build directory can be the source directory too, it only owns what its log lists.
+            if project.kind.fallback == Some(Fallback::NinjaLog) {
+                let logged = project.kind.output_paths(&dir);
+                owned.extend(logged.iter().filter_map(|path| path.strip_prefix(&dir).ok()).map(Path::to_path_buf));
+            }
+            results.send(Ok(project))?;
+        }
+        if venv || owned.iter().any(|output| output.as_os_str().is_empty()) {
+            return Ok(());
+        }
+        for path in hidden {
+            if owned_below(&owned, path.file_name().unwrap_or_default()).is_some() {
+                self.peek(path, &self.hidden_rules, results)?;
+            }
+        }
+        let mut pending = Vec::with_capacity(subdirs.len());
+        for path in subdirs {
+            let Some(name) = path.file_name() else { continue };
+            match owned_below(&owned, name) {
+                Some(owned) => pending.push(PendingDir { path, owned }),
+                None => self.peek(path, &self.build_dir_rules, results)?,
+            }
+        }
+        if pending.is_empty() {
+            return Ok(());
+        }
+        self.pending.fetch_add(pending.len(), Ordering::SeqCst);
+        lock(&self.queues[worker]).extend(pending);
+        self.wake();
+        Ok(())
+    }
+
+    /// Sends the projects of `rules` whose marker is directly inside `dir`, a directory that isn't read any further.
+    /// It is never reported when it can't be read, like the other directories that aren't scanned.
+    fn peek(
+        &self,
+        dir: PathBuf,
+        rules: &[Arc<Rule>],
+        results: &Sender<ScanResult>,
+    ) -> Result<(), mpsc::SendError<ScanResult>> {
+        if rules.is_empty() {
+            return Ok(());
+        }
+        let Ok(entries) = fs::read_dir(&dir) else { return Ok(()) };
+        let mut found: Vec<Project> = Vec::new();
+        for entry in entries.flatten() {
+            let Ok(file_name) = entry.file_name().into_string() else { continue };
+            for rule in rules.iter().filter(|rule| rule.matches(&file_name)) {
+                if !found.iter().any(|project| Arc::ptr_eq(&project.kind, rule)) {
+                    found.push(Project { kind: rule.clone(), root: dir.clone(), marker: entry.path() });
+                }
+            }
+        }
+        for project in without_superseded(found) {
+            results.send(Ok(project))?;
+        }
+        Ok(())
+    }
+}
+
+/// The projects `found` in a directory, but those of the rules another one there supersedes.
+#[inline(always)]
+fn without_superseded(found: Vec<Project>) -> impl Iterator<Item = Project> {
+    let superseded: Vec<String> = found.iter().flat_map(|project| project.kind.supersedes.clone()).collect();
+    found.into_iter().filter(move |project| !superseded.contains(&project.kind.name))
+}
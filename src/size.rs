@@ -0,0 +1,144 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::ops::AddAssign;
+use std::path::{Path, PathBuf};
+
+/// Disk space used by a set of files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DiskUsage {
+    /// Sum of the file lengths.
+    pub apparent: u64,
+    /// Space actually allocated on disk (block count), this differs from `apparent` for sparse/compressed files.
+    pub allocated: u64,
+}
+
+impl DiskUsage {
+    /// How much space was freed going from `self` to `after`.
+    #[inline(always)]
+    pub fn freed(self, after: DiskUsage) -> DiskUsage {
+        DiskUsage {
+            apparent: self.apparent.saturating_sub(after.apparent),
+            allocated: self.allocated.saturating_sub(after.allocated),
+        }
+    }
+}
+
+impl AddAssign for DiskUsage {
+    #[inline(always)]
+    fn add_assign(&mut self, rhs: Self) {
+        self.apparent += rhs.apparent;
+        self.allocated += rhs.allocated;
+    }
+}
+
+impl fmt::Display for DiskUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (allocated {})", Bytes(self.apparent), Bytes(self.allocated))
+    }
+}
+
+/// Human readable byte count using binary units.
+pub struct Bytes(pub u64);
+
+impl fmt::Display for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+        if self.0 < 1024 {
+            return write!(f, "{} B", self.0);
+        }
+        let mut value = self.0 as f64;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+        write!(f, "{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Parses a size like `50G`, `512M` or `1.5T` in binary units, a bare number is in bytes.
+/// A trailing `B` or `iB` is accepted, so `50GiB` and `50GB` are the same as `50G`.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let number = s.trim_end_matches("iB").trim_end_matches('B');
+    let (number, multiplier) = match number.char_indices().last() {
+        Some((i, unit)) if unit.is_ascii_alphabetic() => {
+            let power = match unit.to_ascii_uppercase() {
+                'K' => 1,
+                'M' => 2,
+                'G' => 3,
+                'T' => 4,
+                'P' => 5,
+                _ => return Err(format!("unknown unit '{unit}' in '{s}', expected one of K, M, G, T, P")),
+            };
+            (&number[..i], 1024u64.pow(power))
+        }
+        _ => (number, 1),
+    };
+    let amount: f64 = number.parse().map_err(|_| format!("invalid size '{s}'"))?;
+    let bytes = amount * multiplier as f64;
+    if !(0.0..=u64::MAX as f64).contains(&bytes) {
+        return Err(format!("size '{s}' is out of range"));
+    }
+    Ok(bytes as u64)
+}
+
+/// Walks directory trees and sums their disk usage, counting every hard linked inode only once.
+#[derive(Default)]
+pub struct SizeWalker {
+    #[cfg_attr(not(unix), allow(dead_code))]
+    seen: HashSet<(u64, u64)>,
+    usage: DiskUsage,
+}
+
+impl SizeWalker {
+    /// Measures the combined disk usage of `roots`, missing roots count as empty.
+    pub fn measure<P: AsRef<Path>>(roots: impl IntoIterator<Item = P>) -> DiskUsage {
+        let mut walker = Self::default();
+        for root in roots {
+            walker.walk(root.as_ref());
+        }
+        walker.usage
+    }
+
+    fn walk(&mut self, root: &Path) {
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        // Symlinks are counted as themselves and never followed.
+        let Ok(metadata) = fs::symlink_metadata(root) else { return };
+        self.add(&metadata);
+        if metadata.is_dir() {
+            dirs.push(root.into());
+        }
+        // Errors are ignored on purpose, entries can disappear while a cleaner is running.
+        while let Some(dir) = dirs.pop() {
+            let Ok(entries) = fs::read_dir(&dir) else { continue };
+            for entry in entries.flatten() {
+                let Ok(metadata) = entry.metadata() else { continue };
+                self.add(&metadata);
+                if metadata.is_dir() {
+                    dirs.push(entry.path());
+                }
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[inline(always)]
+    fn add(&mut self, metadata: &fs::Metadata) {
+        use std::os::unix::fs::MetadataExt;
+        if metadata.nlink() > 1 && !metadata.is_dir() && !self.seen.insert((metadata.dev(), metadata.ino())) {
+            return;
+        }
+        self.usage.apparent += metadata.size();
+        // `st_blocks` is always in 512 byte units.
+        self.usage.allocated += metadata.blocks() * 512;
+    }
+
+    #[cfg(not(unix))]
+    #[inline(always)]
+    fn add(&mut self, metadata: &fs::Metadata) {
+        // There's no stable inode number or block count here, so approximate both by the length.
+        self.usage.apparent += metadata.len();
+        self.usage.allocated += metadata.len();
+    }
+}
@@ -0,0 +1,387 @@
+//! A small parser for the subset of TOML used by the configuration files.
+//!
+//! Supported: comments, bare/quoted/dotted keys, `[table]` and `[[array.of.tables]]` headers, basic and literal
+//! strings, integers, booleans, arrays (which may span lines) and inline tables.
+//! Floats, dates and multi-line strings are rejected with an error.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+pub(crate) type Table = BTreeMap<String, Value>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+impl Value {
+    #[inline(always)]
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Boolean(_) => "boolean",
+            Value::Array(_) => "array",
+            Value::Table(_) => "table",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ParseError {
+    pub(crate) line: usize,
+    pub(crate) msg: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+pub(crate) fn parse(src: &str) -> Result<Table, ParseError> {
+    Parser { src, pos: 0, line: 1 }.document()
+}
+
+/// Writes `s` as a basic string that [`parse`] reads back unchanged.
+pub(crate) fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser<'_> {
+    #[inline(always)]
+    fn err<T>(&self, msg: impl Into<String>) -> Result<T, ParseError> {
+        Err(ParseError { line: self.line, msg: msg.into() })
+    }
+
+    #[inline(always)]
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    #[inline(always)]
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    #[inline(always)]
+    fn eat(&mut self, expected: char) -> bool {
+        let found = self.peek() == Some(expected);
+        if found {
+            self.bump();
+        }
+        found
+    }
+
+    #[inline(always)]
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.bump();
+                Ok(())
+            }
+            Some(c) => self.err(format!("expected `{expected}`, found `{c}`")),
+            None => self.err(format!("expected `{expected}`, found end of file")),
+        }
+    }
+
+    /// Skips spaces and tabs.
+    #[inline(always)]
+    fn skip_ws(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.bump();
+        }
+    }
+
+    #[inline(always)]
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.bump();
+            }
+        }
+    }
+
+    /// Skips whitespace, comments and newlines.
+    #[inline(always)]
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_ws();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') => {
+                    self.bump();
+                }
+                Some('\r') if self.src[self.pos..].starts_with("\r\n") => {
+                    self.bump();
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// Expects the end of a line after a statement.
+    #[inline(always)]
+    fn end_of_line(&mut self) -> Result<(), ParseError> {
+        self.skip_ws();
+        self.skip_comment();
+        self.eat('\r');
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.bump();
+                Ok(())
+            }
+            Some(c) => self.err(format!("expected a new line, found `{c}`")),
+        }
+    }
+
+    fn document(mut self) -> Result<Table, ParseError> {
+        let mut root = Table::new();
+        // The table that key/value pairs are currently inserted into.
+        let mut current: Vec<String> = Vec::new();
+        loop {
+            self.skip_blank();
+            match self.peek() {
+                None => return Ok(root),
+                Some('[') => {
+                    self.bump();
+                    let is_array = self.eat('[');
+                    self.skip_ws();
+                    let path = self.key()?;
+                    self.skip_ws();
+                    self.expect(']')?;
+                    if is_array {
+                        self.expect(']')?;
+                    }
+                    self.end_of_line()?;
+                    self.open_table(&mut root, &path, is_array)?;
+                    current = path;
+                }
+                Some(_) => {
+                    let (key, value) = self.key_value()?;
+                    let table = self.current_table(&mut root, &current)?;
+                    self.insert(table, &key, value)?;
+                    self.end_of_line()?;
+                }
+            }
+        }
+    }
+
+    /// Creates the table (or appends a table to the array) named by a header.
+    fn open_table(&self, root: &mut Table, path: &[String], is_array: bool) -> Result<(), ParseError> {
+        let (last, parents) = path.split_last().expect("keys are never empty");
+        let parent = self.current_table(root, parents)?;
+        match (parent.entry(last.clone()).or_insert_with(|| empty(is_array)), is_array) {
+            (Value::Array(tables), true) => tables.push(Value::Table(Table::new())),
+            (Value::Table(_), false) => {}
+            _ => return self.err(format!("`{}` is defined twice", path.join("."))),
+        }
+        Ok(())
+    }
+
+    /// Walks `path` from the root, descending into the last table of arrays of tables.
+    fn current_table<'t>(&self, root: &'t mut Table, path: &[String]) -> Result<&'t mut Table, ParseError> {
+        let mut table = root;
+        for key in path {
+            table = match table.entry(key.clone()).or_insert_with(|| Value::Table(Table::new())) {
+                Value::Table(table) => table,
+                Value::Array(tables) => match tables.last_mut() {
+                    Some(Value::Table(table)) => table,
+                    _ => return self.err(format!("`{key}` is not a table")),
+                },
+                _ => return self.err(format!("`{key}` is not a table")),
+            };
+        }
+        Ok(table)
+    }
+
+    #[inline(always)]
+    fn insert(&self, table: &mut Table, key: &[String], value: Value) -> Result<(), ParseError> {
+        let (last, parents) = key.split_last().expect("keys are never empty");
+        let table = self.current_table(table, parents)?;
+        if table.insert(last.clone(), value).is_some() {
+            return self.err(format!("`{}` is defined twice", key.join(".")));
+        }
+        Ok(())
+    }
+
+    fn key_value(&mut self) -> Result<(Vec<String>, Value), ParseError> {
+        let key = self.key()?;
+        self.skip_ws();
+        self.expect('=')?;
+        self.skip_ws();
+        Ok((key, self.value()?))
+    }
+
+    /// Parses a possibly dotted key.
+    fn key(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut parts = Vec::new();
+        loop {
+            let part = match self.peek() {
+                Some('"') => self.basic_string()?,
+                Some('\'') => self.literal_string()?,
+                Some(c) if is_bare(c) => {
+                    let start = self.pos;
+                    while self.peek().is_some_and(is_bare) {
+                        self.bump();
+                    }
+                    self.src[start..self.pos].to_string()
+                }
+                Some(c) => return self.err(format!("expected a key, found `{c}`")),
+                None => return self.err("expected a key, found end of file"),
+            };
+            parts.push(part);
+            self.skip_ws();
+            if !self.eat('.') {
+                return Ok(parts);
+            }
+            self.skip_ws();
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        match self.peek() {
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            Some('{') => self.inline_table(),
+            Some(c) if c.is_ascii_alphanumeric() || c == '+' || c == '-' => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '_' | '.' | ':'))
+                {
+                    self.bump();
+                }
+                let word = &self.src[start..self.pos];
+                match word {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    _ => match word.replace('_', "").parse() {
+                        Ok(int) => Ok(Value::Integer(int)),
+                        Err(_) => self.err(format!("unsupported value `{word}`")),
+                    },
+                }
+            }
+            Some(c) => self.err(format!("expected a value, found `{c}`")),
+            None => self.err("expected a value, found end of file"),
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, ParseError> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        loop {
+            self.skip_blank();
+            if self.eat(']') {
+                return Ok(Value::Array(values));
+            }
+            values.push(self.value()?);
+            self.skip_blank();
+            if !self.eat(',') {
+                self.skip_blank();
+                self.expect(']')?;
+                return Ok(Value::Array(values));
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value, ParseError> {
+        self.expect('{')?;
+        let mut table = Table::new();
+        self.skip_ws();
+        if self.eat('}') {
+            return Ok(Value::Table(table));
+        }
+        loop {
+            self.skip_ws();
+            let (key, value) = self.key_value()?;
+            self.insert(&mut table, &key, value)?;
+            self.skip_ws();
+            if !self.eat(',') {
+                self.expect('}')?;
+                return Ok(Value::Table(table));
+            }
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, ParseError> {
+        self.expect('"')?;
+        if self.src[self.pos..].starts_with("\"\"") {
+            return self.err("multi-line strings are not supported");
+        }
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                None | Some('\n') => return self.err("unterminated string"),
+                Some('"') => return Ok(s),
+                Some('\\') => match self.bump() {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('r') => s.push('\r'),
+                    Some(u @ ('u' | 'U')) => {
+                        let len = if u == 'u' { 4 } else { 8 };
+                        let hex = self.src.get(self.pos..self.pos + len).unwrap_or_default();
+                        match u32::from_str_radix(hex, 16).ok().and_then(char::from_u32) {
+                            Some(c) => s.push(c),
+                            None => return self.err(format!("invalid unicode escape `\\{u}{hex}`")),
+                        }
+                        self.pos += len;
+                    }
+                    Some(c) => return self.err(format!("invalid escape `\\{c}`")),
+                    None => return self.err("unterminated string"),
+                },
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, ParseError> {
+        self.expect('\'')?;
+        let start = self.pos;
+        loop {
+            match self.bump() {
+                None | Some('\n') => return self.err("unterminated string"),
+                Some('\'') => return Ok(self.src[start..self.pos - 1].to_string()),
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+#[inline(always)]
+fn is_bare(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+#[inline(always)]
+fn empty(is_array: bool) -> Value {
+    if is_array { Value::Array(Vec::new()) } else { Value::Table(Table::new()) }
+}
@@ -0,0 +1,216 @@
+//! Moving directories to the trash, following the freedesktop.org Trash specification:
+//! <https://specifications.freedesktop.org/trash-spec/latest/>
+//!
+//! Directories on the same filesystem as the home trash (`$XDG_DATA_HOME/Trash`) go there, others go to the
+//! trash at the top of their mount point: `$topdir/.Trash/$uid` if an administrator created `$topdir/.Trash`
+//! with the sticky bit, `$topdir/.Trash-$uid` otherwise.
+
+use std::io::Result;
+use std::path::{Path, PathBuf};
+
+/// Moves the directory at `path` to the trash, and returns where it is now.
+#[inline(always)]
+pub fn move_to_trash(path: &Path) -> Result<PathBuf> {
+    os_trash::move_to_trash(path)
+}
+
+#[cfg(unix)]
+mod os_trash {
+    use std::env;
+    use std::ffi::{c_int, c_long};
+    use std::fmt::Write;
+    use std::fs::{self, DirBuilder, OpenOptions};
+    use std::io::{self, Error, ErrorKind, Result};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+    use std::path::{self, Path, PathBuf};
+    use std::time::SystemTime;
+
+    #[allow(non_camel_case_types)]
+    type time_t = c_long;
+    /// `struct tm`, with the glibc/musl/BSD extensions at the end.
+    #[repr(C)]
+    #[derive(Default)]
+    struct Tm {
+        tm_sec: c_int,
+        tm_min: c_int,
+        tm_hour: c_int,
+        tm_mday: c_int,
+        tm_mon: c_int,
+        tm_year: c_int,
+        tm_wday: c_int,
+        tm_yday: c_int,
+        tm_isdst: c_int,
+        tm_gmtoff: c_long,
+        tm_zone: usize,
+    }
+    unsafe extern "C" {
+        fn getuid() -> u32;
+        fn localtime_r(time: *const time_t, result: *mut Tm) -> *mut Tm;
+    }
+
+    pub(super) fn move_to_trash(path: &Path) -> Result<PathBuf> {
+        let path = path::absolute(path)?;
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("{} can't be trashed", path.display())));
+        };
+        let device = fs::symlink_metadata(parent)?.dev();
+        let uid = unsafe { getuid() };
+        let (trash, topdir) = match home_trash() {
+            Some(home) if device_of(&home)? == device => (home, None),
+            _ => {
+                let topdir = mount_point(&path, device)?;
+                (topdir_trash(&topdir, uid)?, Some(topdir))
+            }
+        };
+        let mut builder = DirBuilder::new();
+        builder.recursive(true).mode(0o700);
+        builder.create(trash.join("files"))?;
+        builder.create(trash.join("info"))?;
+
+        // Trash directories on other mounts use paths relative to the mount point, so removable media work
+        // wherever they are mounted.
+        let original = match &topdir {
+            Some(topdir) => path.strip_prefix(topdir).unwrap_or(&path),
+            None => &path,
+        };
+        let info = format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            percent_encode(original.as_os_str().as_bytes()),
+            deletion_date()
+        );
+        // Creating the `.trashinfo` file reserves the name, the spec requires it to be created first.
+        for n in 1u32.. {
+            let mut file_name = name.to_os_string();
+            if n > 1 {
+                file_name.push(format!(".{n}"));
+            }
+            let mut info_name = file_name.clone();
+            info_name.push(".trashinfo");
+            let info_path = trash.join("info").join(info_name);
+            let mut file = match OpenOptions::new().write(true).create_new(true).mode(0o600).open(&info_path) {
+                Ok(file) => file,
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            };
+            let destination = trash.join("files").join(file_name);
+            let moved = io::Write::write_all(&mut file, info.as_bytes()).and_then(|()| {
+                if fs::symlink_metadata(&destination).is_ok() {
+                    return Err(Error::from(ErrorKind::AlreadyExists));
+                }
+                fs::rename(&path, &destination)
+            });
+            match moved {
+                Ok(()) => return Ok(destination),
+                Err(err) => {
+                    let _ = fs::remove_file(&info_path);
+                    if err.kind() != ErrorKind::AlreadyExists {
+                        return Err(err);
+                    }
+                }
+            }
+        }
+        unreachable!("There's always a free name in the trash")
+    }
+
+    /// `$XDG_DATA_HOME/Trash`, or `~/.local/share/Trash`.
+    #[inline(always)]
+    fn home_trash() -> Option<PathBuf> {
+        let data_home = env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()).map(PathBuf::from);
+        let data_home = data_home.or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))?;
+        Some(data_home.join("Trash"))
+    }
+
+    /// The device of `path`, or of its closest existing ancestor.
+    #[inline(always)]
+    fn device_of(path: &Path) -> Result<u64> {
+        let existing = path.ancestors().find(|path| fs::symlink_metadata(path).is_ok()).unwrap_or(path);
+        Ok(fs::symlink_metadata(existing)?.dev())
+    }
+
+    /// The topmost ancestor of `path` that is still on `device`.
+    #[inline(always)]
+    fn mount_point(path: &Path, device: u64) -> Result<PathBuf> {
+        let mut topdir = path.parent().unwrap_or(path);
+        while let Some(parent) = topdir.parent() {
+            if fs::symlink_metadata(parent)?.dev() != device {
+                break;
+            }
+            topdir = parent;
+        }
+        Ok(topdir.into())
+    }
+
+    #[inline(always)]
+    fn topdir_trash(topdir: &Path, uid: u32) -> Result<PathBuf> {
+        const STICKY: u32 = 0o1000;
+        // The shared `.Trash` must be a real directory with the sticky bit, otherwise it is ignored.
+        let shared = topdir.join(".Trash");
+        if let Ok(metadata) = fs::symlink_metadata(&shared)
+            && metadata.is_dir()
+            && metadata.permissions().mode() & STICKY != 0
+        {
+            let trash = shared.join(uid.to_string());
+            match DirBuilder::new().mode(0o700).create(&trash) {
+                Ok(()) => return Ok(trash),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists && owned_dir(&trash, uid) => return Ok(trash),
+                Err(_) => {}
+            }
+        }
+        let trash = topdir.join(format!(".Trash-{uid}"));
+        match DirBuilder::new().mode(0o700).create(&trash) {
+            Ok(()) => Ok(trash),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists && owned_dir(&trash, uid) => Ok(trash),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                Err(Error::new(ErrorKind::PermissionDenied, format!("{} isn't a usable trash", trash.display())))
+            }
+            Err(err) => Err(Error::new(err.kind(), format!("can't create the trash {}: {err}", trash.display()))),
+        }
+    }
+
+    /// A directory (and not a symlink to one) owned by `uid`.
+    #[inline(always)]
+    fn owned_dir(path: &Path, uid: u32) -> bool {
+        fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir() && metadata.uid() == uid)
+    }
+
+    /// Escapes a path like a URL path, as the spec requires for `Path=`.
+    fn percent_encode(bytes: &[u8]) -> String {
+        let mut encoded = String::with_capacity(bytes.len());
+        for &b in bytes {
+            if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+                encoded.push(b as char);
+            } else {
+                write!(encoded, "%{b:02X}").expect("Writing to a String never fails");
+            }
+        }
+        encoded
+    }
+
+    /// The current local time as `YYYY-MM-DDThh:mm:ss`.
+    #[inline(always)]
+    fn deletion_date() -> String {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs() as time_t;
+        let mut tm = Tm::default();
+        unsafe { localtime_r(&now, &mut tm) };
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            tm.tm_year + 1900,
+            tm.tm_mon + 1,
+            tm.tm_mday,
+            tm.tm_hour,
+            tm.tm_min,
+            tm.tm_sec
+        )
+    }
+}
+
+#[cfg(not(unix))]
+mod os_trash {
+    use std::io::{Error, ErrorKind, Result};
+    use std::path::{Path, PathBuf};
+
+    pub(super) fn move_to_trash(_path: &Path) -> Result<PathBuf> {
+        Err(Error::new(ErrorKind::Unsupported, "moving to the trash is only supported on unix"))
+    }
+}
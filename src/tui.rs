@@ -0,0 +1,577 @@
+//! `code-clean tui`: scan first, then pick the projects to clean from a list.
+
+use crate::cli::Args;
+use crate::{ScanResult, is_ignored_failure, options};
+use code_clean::{
+    ActivitySource, Bytes, ChildrenManager, DiskUsage, Event, EventHandler, Options, Project, format_duration,
+    handle_interrupts, interrupt_count, last_activity,
+};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io::{self, Error, ErrorKind, IsTerminal, Read, Result, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+/// How often the main loop checks for signals while nothing happens.
+const INTERRUPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The rule and marker of a project, which tell its row apart from the others.
+type RowKey = (String, PathBuf);
+
+#[inline(always)]
+fn row_key(project: &Project) -> RowKey {
+    (project.kind.name.clone(), project.marker.clone())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Status {
+    Idle,
+    Queued,
+    Running,
+    Done(DiskUsage),
+    Skipped(String),
+    Failed(String),
+}
+
+struct Row {
+    project: Project,
+    size: DiskUsage,
+    modified: Option<SystemTime>,
+    selected: bool,
+    status: Status,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SortKey {
+    Size,
+    Modified,
+    Ecosystem,
+    Path,
+}
+
+impl SortKey {
+    #[inline(always)]
+    fn next(self) -> Self {
+        match self {
+            SortKey::Size => SortKey::Modified,
+            SortKey::Modified => SortKey::Ecosystem,
+            SortKey::Ecosystem => SortKey::Path,
+            SortKey::Path => SortKey::Size,
+        }
+    }
+
+    #[inline(always)]
+    fn name(self) -> &'static str {
+        match self {
+            SortKey::Size => "size",
+            SortKey::Modified => "modified",
+            SortKey::Ecosystem => "ecosystem",
+            SortKey::Path => "path",
+        }
+    }
+}
+
+enum Key {
+    Up,
+    Down,
+    Home,
+    End,
+    Toggle,
+    ToggleAll,
+    Sort,
+    Reverse,
+    Clean,
+    Quit,
+    Other,
+}
+
+/// What the main loop waits for, keys from the terminal and updates from the cleaning worker.
+enum Message {
+    Key(Result<Key>),
+    Status(RowKey, Status),
+    Freed(DiskUsage),
+    Error(Option<PathBuf>, String),
+    Warning(String),
+    /// The worker is done, the cleaners it started finished.
+    Cleaned(Result<()>),
+}
+
+struct App {
+    rows: Vec<Row>,
+    cursor: usize,
+    /// Index of the first row on the screen.
+    offset: usize,
+    sort: SortKey,
+    reverse: bool,
+    cleaning: bool,
+    /// Cleans the selected projects, see [`App::clean`].
+    worker: Option<JoinHandle<()>>,
+    /// Signals sent to the worker, the first one stops starting cleaners and the second one kills them.
+    interrupts: usize,
+    freed: DiskUsage,
+    /// Errors that aren't tied to a row, printed after leaving the terminal UI.
+    errors: Vec<String>,
+    warnings: Vec<String>,
+    term: Terminal,
+    sender: Sender<Message>,
+}
+
+pub(crate) fn run(args: &Args, projects: impl Iterator<Item = ScanResult>) -> Result<ExitCode> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err(Error::other("'tui' needs an interactive terminal"));
+    }
+    println!("Scanning...");
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for project in projects {
+        match project {
+            Ok(project) => rows.push(Row {
+                size: project.kind.measure(&project.root),
+                modified: last_activity(&project, ActivitySource::Mtime),
+                project,
+                selected: false,
+                status: Status::Idle,
+            }),
+            Err(err) => errors.push(err.to_string()),
+        }
+    }
+    // Stopping the worker uses them too, like `clean` does.
+    handle_interrupts()?;
+    let term = Terminal::enter()?;
+    let (sender, messages) = mpsc::channel();
+    read_keys(sender.clone())?;
+    let mut app = App {
+        rows,
+        cursor: 0,
+        offset: 0,
+        sort: SortKey::Size,
+        reverse: false,
+        cleaning: false,
+        worker: None,
+        interrupts: 0,
+        freed: DiskUsage::default(),
+        errors,
+        warnings: Vec::new(),
+        term,
+        sender,
+    };
+    app.sort();
+    app.render()?;
+    loop {
+        let key = match messages.recv_timeout(INTERRUPT_POLL_INTERVAL) {
+            Ok(Message::Key(key)) => key?,
+            Ok(message) => {
+                if app.update(message)? {
+                    break;
+                }
+                app.render()?;
+                continue;
+            }
+            // Ctrl-C is read as a key, but SIGTERM still quits.
+            Err(RecvTimeoutError::Timeout) if interrupt_count() > app.interrupts && app.worker.is_none() => break,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => unreachable!("the app holds a sender"),
+        };
+        app.term.refresh_size();
+        match key {
+            Key::Up => app.cursor = app.cursor.saturating_sub(1),
+            Key::Down => app.cursor = (app.cursor + 1).min(app.rows.len().saturating_sub(1)),
+            Key::Home => app.cursor = 0,
+            Key::End => app.cursor = app.rows.len().saturating_sub(1),
+            Key::Toggle if app.can_select() => {
+                if let Some(row) = app.rows.get_mut(app.cursor) {
+                    row.selected = !row.selected;
+                    app.cursor = (app.cursor + 1).min(app.rows.len() - 1);
+                }
+            }
+            Key::ToggleAll if app.can_select() => {
+                let select = !app.rows.iter().all(|row| row.selected);
+                app.rows.iter_mut().for_each(|row| row.selected = select);
+            }
+            Key::Sort => {
+                app.sort = app.sort.next();
+                app.sort();
+            }
+            Key::Reverse => {
+                app.reverse = !app.reverse;
+                app.sort();
+            }
+            Key::Clean if app.can_select() && app.rows.iter().any(|row| row.selected) => app.clean(options(args))?,
+            Key::Quit if app.worker.is_some() => app.stop(),
+            Key::Quit => break,
+            _ => {}
+        }
+        app.render()?;
+    }
+    let App { freed, errors, warnings, term, .. } = app;
+    // Leave the alternate screen before printing the summary.
+    drop(term);
+    for warning in &warnings {
+        eprintln!("Warning: {warning}");
+    }
+    for error in &errors {
+        eprintln!("Error in: {error}");
+    }
+    println!("Freed {freed} in total");
+    Ok(ExitCode::SUCCESS)
+}
+
+impl App {
+    /// Projects can only be picked before cleaning started.
+    #[inline(always)]
+    fn can_select(&self) -> bool {
+        !self.cleaning
+    }
+
+    fn sort(&mut self) {
+        match self.sort {
+            // Largest and oldest first.
+            SortKey::Size => self.rows.sort_by_key(|row| Reverse(row.size.apparent)),
+            SortKey::Modified => self.rows.sort_by_key(|row| row.modified),
+            SortKey::Ecosystem => self.rows.sort_by(|a, b| a.project.kind.name.cmp(&b.project.kind.name)),
+            SortKey::Path => self.rows.sort_by(|a, b| a.project.root.cmp(&b.project.root)),
+        }
+        if self.reverse {
+            self.rows.reverse();
+        }
+        self.cursor = 0;
+    }
+
+    /// Cleans the selected projects on a worker thread, which sends the updates of their rows to the main loop.
+    fn clean(&mut self, options: Options) -> Result<()> {
+        self.cleaning = true;
+        let selected: Vec<Project> =
+            self.rows.iter().filter(|row| row.selected).map(|row| row.project.clone()).collect();
+        self.rows.iter_mut().filter(|row| row.selected).for_each(|row| row.status = Status::Queued);
+        let updates = Updates { sender: self.sender.clone(), handling: None, aliases: HashMap::new() };
+        let sender = self.sender.clone();
+        let worker = thread::Builder::new().name("clean".into()).spawn(move || {
+            let result = clean(options, updates, &selected);
+            let _ = sender.send(Message::Cleaned(result));
+        })?;
+        self.worker = Some(worker);
+        Ok(())
+    }
+
+    /// Interrupts the worker like a signal interrupts `clean`: the first time it stops starting cleaners, the second
+    /// time it kills the running ones. Quits once they finished.
+    fn stop(&mut self) {
+        if self.interrupts >= 2 || interrupt_count() >= 2 {
+            return;
+        }
+        if let Some(worker) = &self.worker {
+            self.interrupts += 1;
+            os_thread::interrupt(worker);
+        }
+    }
+
+    /// Applies an update of the worker, returns `true` if it finished after being interrupted.
+    fn update(&mut self, message: Message) -> Result<bool> {
+        match message {
+            Message::Key(_) => {}
+            Message::Status(key, status) => {
+                if let Some(row) = self.rows.iter_mut().find(|row| row_key(&row.project) == key) {
+                    row.status = status;
+                }
+            }
+            Message::Freed(freed) => self.freed += freed,
+            Message::Error(Some(path), error) => {
+                // Attribute the error to the innermost project containing the path.
+                let row = self
+                    .rows
+                    .iter_mut()
+                    .filter(|row| matches!(row.status, Status::Queued | Status::Running))
+                    .filter(|row| path.starts_with(&row.project.root))
+                    .max_by_key(|row| row.project.root.as_os_str().len());
+                match row {
+                    Some(row) => row.status = Status::Failed(error),
+                    None => self.errors.push(format!("{path:?} => {error}")),
+                }
+            }
+            Message::Error(None, error) => self.errors.push(error),
+            Message::Warning(message) => self.warnings.push(message),
+            Message::Cleaned(result) => {
+                if let Some(worker) = self.worker.take() {
+                    let _ = worker.join();
+                }
+                result?;
+                if interrupt_count() > 0 {
+                    return Ok(true);
+                }
+                // Anything still queued didn't need cleaning.
+                for row in self.rows.iter_mut().filter(|row| row.status == Status::Queued) {
+                    row.status = Status::Done(DiskUsage::default());
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    fn render(&mut self) -> Result<()> {
+        let (height, width) = self.term.size;
+        let visible = height.saturating_sub(3).max(1);
+        if self.cursor < self.offset {
+            self.offset = self.cursor;
+        } else if self.cursor >= self.offset + visible {
+            self.offset = self.cursor + 1 - visible;
+        }
+        let selected = self.rows.iter().filter(|row| row.selected);
+        let selected_size = selected.clone().map(|row| row.size.apparent).sum();
+        let order = if self.reverse { "reversed" } else { "" };
+        let mut lines = vec![
+            format!(
+                "code-clean: {} projects, {} selected ({}), freed {}   sort: {} {order}",
+                self.rows.len(),
+                selected.count(),
+                Bytes(selected_size),
+                Bytes(self.freed.apparent),
+                self.sort.name()
+            ),
+            format!("      {:<10} {:>10} {:>9}  {:<9} PATH", "ECOSYSTEM", "SIZE", "MODIFIED", "STATUS"),
+        ];
+        let now = SystemTime::now();
+        for (i, row) in self.rows.iter().enumerate().skip(self.offset).take(visible) {
+            let cursor = if i == self.cursor { '>' } else { ' ' };
+            let check = if row.selected { 'x' } else { ' ' };
+            let modified = match row.modified.and_then(|time| now.duration_since(time).ok()) {
+                Some(age) => format!("{} ago", format_duration(age)),
+                None => "-".into(),
+            };
+            let status = match &row.status {
+                Status::Idle => String::new(),
+                Status::Queued => "queued".into(),
+                Status::Running => "running".into(),
+                Status::Done(freed) => format!("done, freed {}", Bytes(freed.apparent)),
+                Status::Skipped(reason) => format!("skipped: {reason}"),
+                Status::Failed(reason) => format!("failed: {reason}"),
+            };
+            lines.push(format!(
+                "{cursor} [{check}] {:<10} {:>10} {modified:>9}  {status:<9} {}",
+                row.project.kind.name,
+                Bytes(row.size.apparent).to_string(),
+                row.project.root.display()
+            ));
+        }
+        let help = if self.interrupts > 0 && self.worker.is_some() {
+            "stopping, waiting for the running cleaners  q kill them"
+        } else if self.cleaning {
+            "up/down move  s sort  r reverse  q quit"
+        } else {
+            "up/down move  space select  a all  s sort  r reverse  enter clean  q quit"
+        };
+        self.term.draw(&lines, help, width)
+    }
+}
+
+/// Runs the selected projects through the [`ChildrenManager`], until it is interrupted.
+fn clean(options: Options, updates: Updates, selected: &[Project]) -> Result<()> {
+    let mut manager = ChildrenManager::new(options, updates);
+    for project in selected {
+        if interrupt_count() > 0 {
+            break;
+        }
+        manager.handler_mut().handling = Some(row_key(project));
+        manager.handle_project(project)?;
+    }
+    manager.handler_mut().handling = None;
+    manager.wait_all()
+}
+
+/// Turns the events of the worker into updates of the rows of the projects they are about.
+struct Updates {
+    sender: Sender<Message>,
+    /// The selected project the worker is handling.
+    handling: Option<RowKey>,
+    /// The projects cleaned instead of selected ones, like the workspace of a Cargo package, and the rows of the
+    /// selected ones.
+    aliases: HashMap<RowKey, RowKey>,
+}
+
+impl Updates {
+    /// The row of a project that is being started or skipped, which is the one being handled.
+    #[inline(always)]
+    fn handled_row(&mut self, project: &Project) -> RowKey {
+        let key = row_key(project);
+        match &self.handling {
+            Some(handling) if *handling != key => {
+                self.aliases.insert(key, handling.clone());
+                handling.clone()
+            }
+            _ => key,
+        }
+    }
+
+    /// The row of a project that was started before.
+    #[inline(always)]
+    fn row(&self, project: &Project) -> RowKey {
+        let key = row_key(project);
+        self.aliases.get(&key).cloned().unwrap_or(key)
+    }
+}
+
+impl EventHandler for Updates {
+    fn on_event(&mut self, event: Event<'_>) -> Result<()> {
+        let message = match event {
+            Event::Spawn { project, .. } => Message::Status(self.handled_row(project), Status::Running),
+            Event::Finish { project, status, stderr, timed_out, freed, .. } => {
+                let _ = self.sender.send(Message::Freed(freed));
+                let failed = status.filter(|status| !status.success() && !is_ignored_failure(stderr));
+                let status = match failed {
+                    _ if timed_out => Status::Failed("timed out".into()),
+                    Some(status) => Status::Failed(stderr.lines().next().unwrap_or(&status.to_string()).into()),
+                    None => Status::Done(freed),
+                };
+                Message::Status(self.row(project), status)
+            }
+            Event::Error { path, error } => Message::Error(path.map(Into::into), error.to_string()),
+            Event::Warning { message } => Message::Warning(message.into()),
+            Event::Skipped { project, reason } => {
+                Message::Status(self.handled_row(project), Status::Skipped(reason.into()))
+            }
+            Event::Planned { .. } | Event::Moved { .. } => return Ok(()),
+        };
+        // The main loop only stops receiving once the worker finished.
+        let _ = self.sender.send(message);
+        Ok(())
+    }
+}
+
+/// Reads keys on a thread of their own, so the main loop gets them while cleaning too.
+fn read_keys(sender: Sender<Message>) -> Result<()> {
+    thread::Builder::new().name("keys".into()).spawn(move || {
+        loop {
+            let key = match read_key() {
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                key => key,
+            };
+            // Nothing can be read after the end of the input or an error.
+            let last = !matches!(key, Ok(Some(_)));
+            if sender.send(Message::Key(key.map(|key| key.unwrap_or(Key::Quit)))).is_err() || last {
+                break;
+            }
+        }
+    })?;
+    Ok(())
+}
+
+/// The next key pressed, `None` at the end of the input.
+fn read_key() -> Result<Option<Key>> {
+    let mut buf = [0u8; 8];
+    let n = io::stdin().lock().read(&mut buf)?;
+    Ok(Some(match &buf[..n] {
+        [] => return Ok(None),
+        b"q" | b"\x03" => Key::Quit,
+        b"\x1b[A" | b"k" => Key::Up,
+        b"\x1b[B" | b"j" => Key::Down,
+        b"\x1b[H" | b"g" => Key::Home,
+        b"\x1b[F" | b"G" => Key::End,
+        b" " => Key::Toggle,
+        b"a" => Key::ToggleAll,
+        b"s" => Key::Sort,
+        b"r" => Key::Reverse,
+        b"\r" | b"\n" => Key::Clean,
+        _ => Key::Other,
+    }))
+}
+
+/// Puts the terminal in raw mode on the alternate screen, and restores it when dropped.
+struct Terminal {
+    stdout: io::Stdout,
+    /// Rows and columns, refreshed after every key press, see [`Terminal::refresh_size`].
+    size: (usize, usize),
+    #[cfg_attr(not(unix), allow(dead_code))]
+    saved: String,
+}
+
+impl Terminal {
+    #[cfg(unix)]
+    fn enter() -> Result<Self> {
+        let saved = stty(&["-g"])?;
+        // No line buffering, no echo, and Ctrl-C is read as a key so the terminal always gets restored.
+        stty(&["-icanon", "-echo", "-isig", "min", "1", "time", "0"])?;
+        let mut term = Self { stdout: io::stdout(), size: (24, 80), saved: saved.trim().into() };
+        term.refresh_size();
+        // Alternate screen, hide the cursor.
+        write!(term.stdout, "\x1b[?1049h\x1b[?25l")?;
+        Ok(term)
+    }
+
+    #[cfg(not(unix))]
+    fn enter() -> Result<Self> {
+        Err(Error::new(io::ErrorKind::Unsupported, "'tui' is only supported on unix"))
+    }
+
+    #[inline(always)]
+    fn refresh_size(&mut self) {
+        #[cfg(unix)]
+        if let Ok(size) = stty(&["size"])
+            && let Some((rows, cols)) = size.trim().split_once(' ')
+            && let (Ok(rows @ 1..), Ok(cols @ 1..)) = (rows.parse(), cols.parse())
+        {
+            self.size = (rows, cols);
+        }
+    }
+
+    fn draw(&mut self, lines: &[String], footer: &str, width: usize) -> Result<()> {
+        let mut out = self.stdout.lock();
+        write!(out, "\x1b[H\x1b[2J")?;
+        for line in lines {
+            let line: String = line.chars().take(width).collect();
+            write!(out, "{line}\r\n")?;
+        }
+        write!(out, "\r\n{}", footer.chars().take(width).collect::<String>())?;
+        out.flush()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        // Show the cursor, leave the alternate screen.
+        let _ = write!(self.stdout, "\x1b[?25h\x1b[?1049l");
+        let _ = self.stdout.flush();
+        #[cfg(unix)]
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+/// Runs `stty` on the controlling terminal, which it finds through its stdin.
+#[cfg(unix)]
+fn stty(args: &[&str]) -> Result<String> {
+    use std::process::{Command, Stdio};
+    let output = Command::new("stty").args(args).stdin(Stdio::inherit()).stderr(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        return Err(Error::other(format!("stty {} failed: {}", args.join(" "), output.status)));
+    }
+    String::from_utf8(output.stdout).map_err(Error::other)
+}
+
+/// Sending SIGINT to the worker, whose waits it interrupts, see [`code_clean::handle_interrupts`].
+#[cfg(unix)]
+mod os_thread {
+    use std::ffi::c_int;
+    use std::os::unix::thread::{JoinHandleExt, RawPthread};
+    use std::thread::JoinHandle;
+
+    const SIGINT: c_int = 2;
+    unsafe extern "C" {
+        fn pthread_kill(thread: RawPthread, sig: c_int) -> c_int;
+    }
+
+    #[inline(always)]
+    pub(super) fn interrupt(thread: &JoinHandle<()>) {
+        // Only fails if the thread exited already, then there is nothing to interrupt.
+        unsafe { pthread_kill(thread.as_pthread_t(), SIGINT) };
+    }
+}
+
+#[cfg(not(unix))]
+mod os_thread {
+    use std::thread::JoinHandle;
+
+    /// The terminal UI only runs on unix.
+    #[inline(always)]
+    pub(super) fn interrupt(_thread: &JoinHandle<()>) {}
+}
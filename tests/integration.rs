@@ -78,7 +78,7 @@ fn test_directory_traversal_and_detection() {
     let binary = env!("CARGO_BIN_EXE_code-clean");
     let output = Command::new(binary)
         .current_dir(root)
-        .env("LOG", "1")
+        .arg("-v")
         .arg("-j")
         .arg("4") // Limit parallelism for predictable output
         .stdout(Stdio::piped())
@@ -244,7 +244,7 @@ fn test_node_modules_variations() {
     let binary = env!("CARGO_BIN_EXE_code-clean");
     let output = Command::new(binary)
         .current_dir(root)
-        .env("LOG", "1")
+        .arg("-v")
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .output()
@@ -262,7 +262,7 @@ fn test_node_modules_variations() {
 }
 
 #[test]
-fn test_log_environment_variable() {
+fn test_verbosity_flags() {
     let temp = TempDir::new();
     let root = temp.path();
 
@@ -270,52 +270,1723 @@ fn test_log_environment_variable() {
     create_project(root, ".", &["Cargo.toml"]);
 
     let binary = env!("CARGO_BIN_EXE_code-clean");
+    let run = |flag: Option<&str>| {
+        let mut command = Command::new(binary);
+        command.current_dir(root).stdout(Stdio::piped()).stderr(Stdio::piped());
+        command.args(flag);
+        let output = command.output().expect("Failed to run code-clean");
+        (String::from_utf8_lossy(&output.stdout).into_owned(), String::from_utf8_lossy(&output.stderr).into_owned())
+    };
+
+    // -v and --verbose show commands
+    for flag in ["-v", "--verbose"] {
+        let (stdout, _) = run(Some(flag));
+        assert!(stdout.contains("cargo clean"), "{flag} should show commands: {stdout}");
+    }
+
+    // Without a flag - should NOT show commands
+    let (stdout, _) = run(None);
+    assert!(!stdout.contains("cargo clean"), "Without -v should not show command details");
+    assert!(stdout.contains("Using"), "Should still show jobs info");
+    assert!(stdout.contains("Done"), "Should still show Done");
+
+    // -q prints nothing but errors
+    let (stdout, stderr) = run(Some("-q"));
+    assert!(stdout.is_empty(), "-q should not print anything to stdout: {stdout}");
+    assert!(stderr.contains("Error in"), "-q should still print errors: {stderr}");
+}
+
+#[test]
+fn test_cli_arguments() {
+    let temp = TempDir::new();
+    let root = temp.path();
+    create_project(root, "one", &["Cargo.toml"]);
+    create_project(root, "two", &["Makefile"]);
+    create_project(root, "three", &["package.json"]);
+    create_project(root, "three/node_modules/dep", &["index.js"]);
+
+    let binary = env!("CARGO_BIN_EXE_code-clean");
+    let run = |args: &[&str]| {
+        Command::new(binary)
+            .current_dir(root)
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Failed to run code-clean")
+    };
+
+    // Invalid input is a clean error, not a panic.
+    for (args, message) in [
+        (&["-j", "0"][..], "invalid value '0' for '-j'"),
+        (&["--jobs", "abc"], "invalid value 'abc' for '--jobs'"),
+        (&["-j"], "'-j' requires a value"),
+        (&["--bogus"], "unexpected argument '--bogus'"),
+        (&["-v", "-q"], "can't be used together"),
+        (&["scan", "--dry-run"], "'--dry-run' can only be used with 'clean'"),
+        (&["does-not-exist"], "'does-not-exist' is not a directory"),
+    ] {
+        let output = run(args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(1), "{args:?} should fail: {stderr}");
+        assert!(stderr.contains(message), "{args:?} should explain the error: {stderr}");
+        assert!(stderr.contains("--help"), "{args:?} should point to --help: {stderr}");
+        assert!(!stderr.contains("panicked"), "{args:?} should not panic: {stderr}");
+    }
+
+    let output = run(&["--help"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Usage: code-clean"));
+    let output = run(&["--version"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), format!("code-clean {}", env!("CARGO_PKG_VERSION")));
+
+    // `scan` lists the projects of every root, and doesn't touch anything.
+    let output = run(&["scan", "one", "three"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(&format!("[cargo] {}", root.join("one").display())), "{stdout}");
+    assert!(stdout.contains(&format!("[npm] {}", root.join("three").display())), "{stdout}");
+    assert!(!stdout.contains("[make]"), "Only the given roots should be scanned: {stdout}");
+    assert!(stdout.contains("Found 2 projects"), "{stdout}");
+    assert!(root.join("three/node_modules").exists());
+    // Two spellings of the same directory are the same root.
+    let output = run(&["scan", "one/../three", "three"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(&format!("[npm] {}", root.join("three").display())), "{stdout}");
+    assert!(stdout.contains("Found 1 projects"), "{stdout}");
+    // And a root inside another one is walked with it, once.
+    let output = run(&["scan", "three", "."]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.matches(&format!("[npm] {}", root.join("three").display())).count(), 1, "{stdout}");
+    assert!(stdout.contains("Found 3 projects"), "{stdout}");
+
+    // `report` shows the reclaimable space.
+    let output = run(&["report", "-j", "3"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(&format!("[npm] {}: ", root.join("three").display())), "{stdout}");
+    assert!(stdout.contains("Reclaimable "), "{stdout}");
+    assert!(root.join("three/node_modules").exists());
+
+    // `clean` with an explicit root.
+    let output = run(&["clean", "--jobs=2", "three"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Using 2 jobs"));
+    assert!(!root.join("three/node_modules").exists());
+}
+
+#[test]
+fn test_dry_run() {
+    let temp = TempDir::new();
+    let root = temp.path();
+
+    create_project(root, "rust", &["Cargo.toml"]);
+    create_project(root, "c", &["Makefile"]);
+    create_project(root, "web", &["package.json"]);
+    let nm = root.join("web/node_modules");
+    fs::create_dir_all(&nm).unwrap();
+    File::create(nm.join("pkg.js")).unwrap();
 
-    // Test LOG=1 shows commands
+    let binary = env!("CARGO_BIN_EXE_code-clean");
     let output = Command::new(binary)
         .current_dir(root)
-        .env("LOG", "1")
+        .arg("--dry-run")
         .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
         .output()
         .expect("Failed to run code-clean");
 
     let stdout = String::from_utf8_lossy(&output.stdout);
-    assert!(stdout.contains("cargo") && stdout.contains("clean"), "LOG=1 should show commands");
+    println!("=== STDOUT ===\n{stdout}");
+
+    // Something would have been done, so the exit status reports it.
+    assert_eq!(output.status.code(), Some(2), "Dry run with pending actions should exit with 2");
 
-    // Test LOG=true also works
+    let cargo_line = format!(
+        "[cargo] {}: cargo clean --manifest-path {}",
+        root.join("rust").display(),
+        root.join("rust/Cargo.toml").display()
+    );
+    assert!(stdout.contains(&cargo_line), "Should plan cargo clean: {stdout}");
+    let make_line = format!("[make] {}: make clean", root.join("c").display());
+    assert!(stdout.contains(&make_line), "Should plan make clean: {stdout}");
+    let npm_line = format!("[npm] {}: rm -rf {}", root.join("web").display(), nm.display());
+    assert!(stdout.contains(&npm_line), "Should plan node_modules removal: {stdout}");
+    assert!(stdout.contains("3 actions"), "Should report the number of planned actions: {stdout}");
+
+    // Nothing was actually deleted.
+    assert!(nm.join("pkg.js").exists(), "Dry run must not delete node_modules");
+
+    // An empty tree has nothing to do.
+    let empty = TempDir::new();
     let output = Command::new(binary)
+        .current_dir(empty.path())
+        .arg("--dry-run")
+        .stdout(Stdio::piped())
+        .output()
+        .expect("Failed to run code-clean");
+    assert_eq!(output.status.code(), Some(0), "Dry run without pending actions should exit with 0");
+}
+
+#[test]
+fn test_cargo_target_dirs() {
+    let temp = TempDir::new();
+    let root = temp.path();
+    // A workspace is cleaned once, from its root.
+    fs::create_dir_all(root.join("ws/a")).unwrap();
+    fs::create_dir_all(root.join("ws/b")).unwrap();
+    fs::write(root.join("ws/Cargo.toml"), "[workspace]\nmembers = [\"a\", \"b\"]\n").unwrap();
+    fs::write(root.join("ws/a/Cargo.toml"), "[package]\nname = \"a\"\n").unwrap();
+    fs::write(root.join("ws/b/Cargo.toml"), "[package]\nname = \"b\"\n").unwrap();
+    // Two projects building into the same directory, which isn't cleaned.
+    create_project(root, "shared/one", &["Cargo.toml"]);
+    create_project(root, "shared/two", &["Cargo.toml"]);
+    fs::create_dir_all(root.join("shared/.cargo")).unwrap();
+    fs::write(root.join("shared/.cargo/config.toml"), "[build]\ntarget-dir = \"target\"\n").unwrap();
+    // A target directory that can't be parsed may be anywhere.
+    create_project(root, "broken", &["Cargo.toml"]);
+    fs::create_dir_all(root.join("broken/.cargo")).unwrap();
+    fs::write(root.join("broken/.cargo/config.toml"), "[build]\ntarget-dir = \"\"\"\n/\n\"\"\"\n").unwrap();
+    // Cargo reads more TOML than the configuration parser, the target directory is found anyway.
+    create_project(root, "dated", &["Cargo.toml"]);
+    fs::create_dir_all(root.join("dated/.cargo")).unwrap();
+    let config = "[env]\nSINCE = 1979-05-27T07:32:00Z\nRATIO = 0.5\n\n[build] # outputs\ntarget-dir = 'out'\n";
+    fs::write(root.join("dated/.cargo/config.toml"), config).unwrap();
+
+    let run = |target_dir: Option<&Path>| {
+        let binary = env!("CARGO_BIN_EXE_code-clean");
+        let mut command = Command::new(binary);
+        command.arg("--dry-run").current_dir(root).env("CARGO_HOME", root.join("cargo-home"));
+        command.env_remove("CARGO_TARGET_DIR").env_remove("CARGO_BUILD_TARGET_DIR");
+        if let Some(target_dir) = target_dir {
+            command.env("CARGO_TARGET_DIR", target_dir);
+        }
+        let output = command.output().expect("Failed to run code-clean");
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        println!("=== STDOUT ===\n{stdout}");
+        stdout
+    };
+
+    let stdout = run(None);
+    let ws_line = format!(
+        "[cargo] {}: cargo clean --manifest-path {}",
+        root.join("ws").display(),
+        root.join("ws/Cargo.toml").display()
+    );
+    assert_eq!(stdout.matches(&ws_line).count(), 1, "The workspace should be cleaned once: {stdout}");
+    assert!(!stdout.contains("ws/a/Cargo.toml") && !stdout.contains("ws/b/Cargo.toml"), "{stdout}");
+    let dated_line = format!(
+        "[cargo] {}: cargo clean --manifest-path {}",
+        root.join("dated").display(),
+        root.join("dated/Cargo.toml").display()
+    );
+    assert!(stdout.contains(&dated_line), "Unsupported TOML shouldn't hide the target directory: {stdout}");
+    assert!(stdout.contains("2 actions"), "Only the workspace and dated should be cleaned: {stdout}");
+    // Which of them comes first depends on the scan, the other one is shared with it.
+    let config = format!("from {} may be shared with other projects", root.join("shared/.cargo/config.toml").display());
+    assert!(stdout.contains(&config), "Shared target directories should be left alone: {stdout}");
+    for name in ["one", "two"] {
+        let target = root.join("shared/target");
+        let skipped =
+            format!("[cargo] {}: target directory {} ", root.join("shared").join(name).display(), target.display());
+        assert!(stdout.contains(&skipped), "Shared target directories should be left alone: {stdout}");
+    }
+    let broken = root.join("broken");
+    let skipped = format!(
+        "[cargo] {}: its target directory is unknown, {} can't be parsed",
+        broken.display(),
+        broken.join(".cargo/config.toml").display()
+    );
+    assert!(stdout.contains(&skipped), "Unknown target directories should be left alone: {stdout}");
+
+    // Everything builds into CARGO_TARGET_DIR.
+    let stdout = run(Some(&root.join("everything")));
+    assert!(stdout.contains("0 actions"), "Nothing should be cleaned: {stdout}");
+    assert!(stdout.contains("from CARGO_TARGET_DIR may be shared with other projects"), "{stdout}");
+    for project in ["ws", "shared/one", "shared/two"] {
+        let target = root.join("everything");
+        let skipped = format!("[cargo] {}: target directory {} ", root.join(project).display(), target.display());
+        assert!(stdout.contains(&skipped), "{project} should be skipped: {stdout}");
+    }
+
+    // Copies of the rule clean workspaces once too.
+    let config = "[[rule]]\nname = \"cargo\"\nenabled = false\n\n[[rule]]\nname = \"rust\"\nmarker = \"Cargo.toml\"\n\
+                  kind = \"cargo\"\ncommand = [\"cargo\", \"clean\", \"--offline\"]\n";
+    fs::write(root.join(".code-clean.toml"), config).unwrap();
+    let stdout = run(None);
+    let ws_line = format!("[rust] {}: cargo clean --offline", root.join("ws").display());
+    assert_eq!(stdout.matches(&ws_line).count(), 1, "The workspace should be cleaned once: {stdout}");
+    assert!(stdout.contains("2 actions"), "Only the workspace and dated should be cleaned: {stdout}");
+}
+
+#[test]
+fn test_skips_active_builds() {
+    let temp = TempDir::new();
+    let root = temp.path();
+    create_project(root, "idle/target/debug", &[".cargo-lock"]);
+    fs::write(root.join("idle/Cargo.toml"), "[package]\nname = \"idle\"\n").unwrap();
+    create_project(root, "cargo/target/debug", &[".cargo-lock"]);
+    fs::write(root.join("cargo/Cargo.toml"), "[package]\nname = \"cargo\"\n").unwrap();
+    create_project(root, "gradle", &["gradlew"]);
+    create_project(root, "gradle/.gradle/8.5/fileHashes", &["fileHashes.lock"]);
+    create_project(root, "ninja", &["build.ninja", ".ninja_lock"]);
+    create_project(root, "npm/node_modules", &[]);
+    create_project(root, "npm", &["package.json"]);
+
+    // Held the way the builds hold them, until the files are closed.
+    let cargo_lock = File::open(root.join("cargo/target/debug/.cargo-lock")).unwrap();
+    cargo_lock.lock().unwrap();
+    let gradle_lock = File::open(root.join("gradle/.gradle/8.5/fileHashes/fileHashes.lock")).unwrap();
+    gradle_lock.lock().unwrap();
+    let mut sleeper = Command::new("sleep").arg("30").current_dir(root.join("npm/node_modules")).spawn().unwrap();
+
+    let binary = env!("CARGO_BIN_EXE_code-clean");
+    let output = Command::new(binary)
+        .arg("--dry-run")
         .current_dir(root)
-        .env("LOG", "true")
+        .env("CARGO_HOME", root.join("cargo-home"))
+        .env_remove("CARGO_TARGET_DIR")
+        .env_remove("CARGO_BUILD_TARGET_DIR")
+        .output()
+        .expect("Failed to run code-clean");
+    sleeper.kill().unwrap();
+    sleeper.wait().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    println!("=== STDOUT ===\n{stdout}");
+
+    let skipped = |project: &str, reason: String| {
+        let line = format!("[{project}] {}: {reason}", root.join(project).display());
+        assert!(stdout.contains(&line), "{project} should be skipped: {stdout}");
+    };
+    let cargo_lock = root.join("cargo/target/debug/.cargo-lock");
+    skipped("cargo", format!("{} is locked by a running build", cargo_lock.display()));
+    let gradle_lock = root.join("gradle/.gradle/8.5/fileHashes/fileHashes.lock");
+    skipped("gradle", format!("{} is locked by a running build", gradle_lock.display()));
+    skipped("ninja", format!("{} shows a running build", root.join("ninja/.ninja_lock").display()));
+    if cfg!(target_os = "linux") {
+        skipped("npm", format!("process {} (sleep) is running in ", sleeper.id()));
+        assert!(stdout.contains("1 actions"), "Only the idle project should be cleaned: {stdout}");
+    }
+    assert!(stdout.contains(&format!("[cargo] {}: cargo clean", root.join("idle").display())), "{stdout}");
+}
+
+#[test]
+fn test_no_exec() {
+    let temp = TempDir::new();
+    let root = temp.path();
+    // Every cleaner that runs leaves a file behind.
+    let ran = root.join("ran");
+    fs::create_dir_all(root.join("make")).unwrap();
+    fs::write(root.join("make/Makefile"), format!("clean:\n\ttouch {}\n", ran.display())).unwrap();
+    create_project(root, "gradle/build/classes", &["Main.class"]);
+    create_project(root, "gradle/.gradle", &["cache"]);
+    fs::write(root.join("gradle/gradlew"), format!("#!/bin/sh\ntouch {}\n", ran.display())).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(root.join("gradle/gradlew"), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    create_project(root, "rust/target/debug", &["app"]);
+    create_project(root, "rust", &["Cargo.toml"]);
+    // Only the outputs of the ninja log inside the build directory are deleted.
+    create_project(root, "ninja/obj", &["main.o"]);
+    create_project(root, "ninja", &["build.ninja", "app", "main.c"]);
+    create_project(root, "kept", &["lib.a"]);
+    let log = "# ninja log v5\n1\t5\t0\tobj/main.o\tabc\n5\t9\t0\tapp\tdef\n1\t9\t0\t../kept/lib.a\t123\n";
+    fs::write(root.join("ninja/.ninja_log"), log).unwrap();
+    fs::write(root.join("ninja/app"), vec![b'x'; 2048]).unwrap();
+    fs::write(root.join("ninja/main.c"), vec![b'x'; 1024 * 1024]).unwrap();
+
+    let binary = env!("CARGO_BIN_EXE_code-clean");
+    // Only the outputs of the log are measured, not the sources next to them.
+    let output = Command::new(binary).arg("report").current_dir(root).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let measured = format!("[ninja] {}: 2.0 KiB (allocated ", root.join("ninja").display());
+    assert!(stdout.contains(&measured), "{stdout}");
+
+    let output = Command::new(binary)
+        .arg("--no-exec")
+        .current_dir(root)
+        .env("CARGO_HOME", root.join("cargo-home"))
+        .env_remove("CARGO_TARGET_DIR")
+        .env_remove("CARGO_BUILD_TARGET_DIR")
         .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
         .output()
         .expect("Failed to run code-clean");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    println!("=== STDOUT ===\n{stdout}\n=== STDERR ===\n{stderr}");
+    assert!(output.status.success());
 
+    assert!(!ran.exists(), "No cleaner should run");
+    assert!(!root.join("gradle/build").exists() && !root.join("gradle/.gradle").exists());
+    assert!(!root.join("rust/target").exists());
+    assert!(!root.join("ninja/obj/main.o").exists() && !root.join("ninja/app").exists());
+    assert!(root.join("ninja/main.c").exists() && root.join("ninja/build.ninja").exists());
+    assert!(root.join("kept/lib.a").exists(), "Outputs outside the project should be left alone");
+    let skipped =
+        format!("[make] {}: its cleaner runs a command, and --no-exec only deletes files", root.join("make").display());
+    assert!(stdout.contains(&skipped), "Make has no filesystem fallback: {stdout}");
+
+    let output = Command::new(binary).args(["--no-exec", "--older-than", "1d", "--activity", "git"]).output().unwrap();
+    assert!(!output.status.success(), "git can't run with --no-exec");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_no_exec_stays_inside_ninja_builds() {
+    let temp = TempDir::new();
+    let root = temp.path();
+    let victim = TempDir::new();
+    fs::write(victim.path().join("important"), "keep").unwrap();
+    create_project(root, "ninja/obj", &["main.o"]);
+    create_project(root, "ninja", &["build.ninja"]);
+    std::os::unix::fs::symlink(victim.path(), root.join("ninja/link")).unwrap();
+    let log = "# ninja log v5\n1\t5\t0\tobj/main.o\tabc\n5\t9\t0\tlink/important\tdef\n";
+    fs::write(root.join("ninja/.ninja_log"), log).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_code-clean")).arg("--no-exec").current_dir(root).output().unwrap();
     let stdout = String::from_utf8_lossy(&output.stdout);
-    assert!(stdout.contains("cargo") && stdout.contains("clean"), "LOG=true should show commands");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    println!("=== STDOUT ===\n{stdout}\n=== STDERR ===\n{stderr}");
+    assert!(output.status.success());
+    assert!(!root.join("ninja/obj/main.o").exists());
+    assert!(victim.path().join("important").exists(), "Outputs behind a symlink should be left alone");
+}
 
-    // Test without LOG env var - should NOT show commands
+#[test]
+fn test_reports_freed_space() {
+    let temp = TempDir::new();
+    let root = temp.path();
+
+    create_project(root, "web", &["package.json"]);
+    let nm = root.join("web/node_modules");
+    fs::create_dir_all(&nm).unwrap();
+    fs::write(nm.join("big.js"), vec![b'x'; 1024 * 1024]).unwrap();
+    // A hard link must only be counted once.
+    #[cfg(unix)]
+    fs::hard_link(nm.join("big.js"), nm.join("link.js")).unwrap();
+
+    let binary = env!("CARGO_BIN_EXE_code-clean");
     let output = Command::new(binary)
         .current_dir(root)
-        .env_remove("LOG")
         .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
         .output()
         .expect("Failed to run code-clean");
+    assert!(output.status.success());
 
     let stdout = String::from_utf8_lossy(&output.stdout);
-    assert!(!stdout.contains("cargo clean"), "Without LOG should not show command details");
-    assert!(stdout.contains("Using"), "Should still show jobs info");
-    assert!(stdout.contains("Done"), "Should still show Done");
+    println!("=== STDOUT ===\n{stdout}");
+    let project_line = format!("[npm] {}: freed 1.0 MiB (allocated ", root.join("web").display());
+    assert!(stdout.contains(&project_line), "Should report per project delta: {stdout}");
+    assert!(stdout.contains("Freed 1.0 MiB (allocated "), "Should report the grand total: {stdout}");
+    assert!(!nm.exists());
+}
+
+#[test]
+fn test_library_scanner_and_cleaners() {
+    use code_clean::{Action, ChildrenManager, Event, Options, RuleSet, Scanner};
+
+    let temp = TempDir::new();
+    let root = temp.path();
+    create_project(root, "rust", &["Cargo.toml"]);
+    create_project(root, ".hidden", &["Makefile"]);
+    create_project(root, "web", &["package.json"]);
+    fs::create_dir_all(root.join("web/node_modules/dep")).unwrap();
+
+    let mut projects: Vec<_> = Scanner::new(root, RuleSet::builtin()).collect::<Result<_, _>>().unwrap();
+    projects.sort_by(|a, b| a.marker.cmp(&b.marker));
+    let found: Vec<_> = projects.iter().map(|p| (p.kind.name.as_str(), p.root.clone(), p.marker.clone())).collect();
+    let expected = [
+        ("cargo", root.join("rust"), root.join("rust/Cargo.toml")),
+        ("npm", root.join("web"), root.join("web/package.json")),
+    ];
+    assert_eq!(found, expected);
+
+    let mut planned = Vec::new();
+    let mut manager = ChildrenManager::new(Options { dry_run: true, ..Options::default() }, |event: Event<'_>| {
+        if let Event::Planned { action, .. } = event {
+            planned.push(action.clone());
+        }
+        Ok(())
+    });
+    for project in &projects {
+        manager.handle_project(project).unwrap();
+    }
+    assert_eq!(manager.planned(), 2);
+    drop(manager);
+    assert_eq!(planned[1], Action::Remove(vec![root.join("web/node_modules")]));
+    assert_eq!(
+        planned[0].to_string(),
+        format!("cargo clean --manifest-path {}", root.join("rust/Cargo.toml").display())
+    );
+}
+
+#[test]
+fn test_scanner_skips_outputs() {
+    use code_clean::{RuleSet, Scanner};
+
+    let temp = TempDir::new();
+    let root = temp.path();
+    // Vendored sources in an output would be cleaned along with it.
+    create_project(root, "rust", &["Cargo.toml"]);
+    create_project(root, "rust/target/package/vendored", &["Cargo.toml"]);
+    create_project(root, "web/node_modules/dep", &["package.json"]);
+    create_project(root, "web", &["package.json"]);
+    // Installed dependencies are never scanned, whether a rule matched around them or not.
+    create_project(root, "tools/node_modules/dep", &["Makefile", "package.json"]);
+    // A build directory of its own inside an output is still found, but not the build files it generated.
+    create_project(root, "engine", &["Cargo.toml"]);
+    create_project(root, "engine/target", &["CMakeCache.txt", "Makefile"]);
+    create_project(root, "engine/target/src", &["Makefile"]);
+    // Ninja only owns the outputs in its log, and Make no directory, the projects below them are found.
+    create_project(root, "repo", &["build.ninja"]);
+    create_project(root, "repo/sub/crate", &["Cargo.toml"]);
+    create_project(root, "repo/web", &["package.json"]);
+    fs::write(root.join("repo/.ninja_log"), "# ninja log v5\n1\t5\t0\tweb/app.js\tabc\n").unwrap();
+    create_project(root, "proj", &["Makefile"]);
+    create_project(root, "proj/build/sub", &["package.json"]);
+    // Outputs deeper than one directory only prune that directory.
+    create_project(root, "gen", &["gen.txt"]);
+    create_project(root, "gen/out/objects/nested", &["Makefile"]);
+    create_project(root, "gen/out/src", &["Makefile"]);
+    // A directory named like an output of a project that isn't there is scanned.
+    create_project(root, "plain/target", &["Makefile"]);
+
+    let mut rules = RuleSet::builtin();
+    rules
+        .merge_str(
+            "[[rule]]\nname = \"gen\"\nmarker = \"gen.txt\"\ncommand = [\"true\"]\noutputs = [\"./out/objects\"]\n",
+            "test",
+        )
+        .unwrap();
+    let mut markers: Vec<_> = Scanner::new(root, rules).map(|project| project.unwrap().marker).collect();
+    markers.sort();
+    let expected: Vec<_> = [
+        "engine/Cargo.toml",
+        "engine/target/CMakeCache.txt",
+        "gen/gen.txt",
+        "gen/out/src/Makefile",
+        "plain/target/Makefile",
+        "proj/Makefile",
+        "proj/build/sub/package.json",
+        "repo/build.ninja",
+        "repo/sub/crate/Cargo.toml",
+        "repo/web/package.json",
+        "rust/Cargo.toml",
+        "web/package.json",
+    ]
+    .iter()
+    .map(|marker| root.join(marker))
+    .collect();
+    assert_eq!(markers, expected);
+}
+
+#[test]
+fn test_scanner_stop_and_resume() {
+    use code_clean::{RuleSet, Scanner};
+
+    let temp = TempDir::new();
+    let root = temp.path();
+    for i in 0..40 {
+        create_project(root, &format!("{}/{}/{i}", i % 3, i % 7), &["Makefile"]);
+    }
+    create_project(root, "web", &["package.json"]);
+    create_project(root, "web/node_modules/dep", &["package.json"]);
+
+    let rules = RuleSet::builtin();
+    let mut expected: Vec<_> = Scanner::new(root, rules.clone()).map(|project| project.unwrap().marker).collect();
+    expected.sort();
+    assert_eq!(expected.len(), 41);
+
+    // Stopped before it starts, right after it starts, or anywhere in between, the rest is still found once.
+    for taken in [0, 1, 7, 20, 41] {
+        let mut scanner = Scanner::new(root, rules.clone()).threads(2);
+        let mut markers: Vec<_> = scanner.by_ref().take(taken).map(|project| project.unwrap().marker).collect();
+        let (dirs, found) = scanner.stop();
+        markers.extend(found.into_iter().map(|project| project.marker));
+        markers.extend(Scanner::with_dirs(dirs, rules.clone()).map(|project| project.unwrap().marker));
+        markers.sort();
+        assert_eq!(markers, expected, "Stopped after {taken} projects");
+    }
+}
+
+#[test]
+fn test_config_rules() {
+    let temp = TempDir::new();
+    let root = temp.path();
+    // Keep the user's own configuration out of the test.
+    let config_home = TempDir::new();
+
+    create_project(root, "rust", &["Cargo.toml"]);
+    create_project(root, "c", &["Makefile"]);
+    create_project(root, "custom", &["justfile"]);
+    create_project(root, "custom/out", &["artifact.o"]);
+    create_project(root, "shell", &["build.sh"]);
+    fs::write(
+        root.join(".code-clean.toml"),
+        r#"
+# Disable a built-in rule
+[[rule]]
+name = "make"
+enabled = false
+
+# Override only the command of a built-in rule
+[[rule]]
+name = "cargo"
+command = ["cargo", "clean", "--offline", "--manifest-path", "{marker}"]
+
+[[rule]]
+name = "just"
+marker = "justfile"
+delete = ["out"]
+
+[[rule]]
+name = "shell"
+marker = "build.*"
+command = ["sh", "{marker}", "clean"]
+workdir = "."
+"#,
+    )
+    .unwrap();
+
+    let binary = env!("CARGO_BIN_EXE_code-clean");
+    let output = Command::new(binary)
+        .current_dir(root)
+        .env("XDG_CONFIG_HOME", config_home.path())
+        .arg("--dry-run")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .expect("Failed to run code-clean");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    println!("=== STDOUT ===\n{stdout}\n=== STDERR ===\n{stderr}");
+    assert_eq!(output.status.code(), Some(2));
+
+    assert!(!stdout.contains("make clean"), "Disabled rules should not run: {stdout}");
+    let cargo_line = format!("cargo clean --offline --manifest-path {}", root.join("rust/Cargo.toml").display());
+    assert!(stdout.contains(&cargo_line), "Overridden command should be used: {stdout}");
+    let just_line = format!("[just] {}: rm -rf {}", root.join("custom").display(), root.join("custom/out").display());
+    assert!(stdout.contains(&just_line), "Custom delete rule should be applied: {stdout}");
+    let shell_line =
+        format!("[shell] {}: sh {} clean", root.join("shell").display(), root.join("shell/build.sh").display());
+    assert!(stdout.contains(&shell_line), "Custom glob command rule should be applied: {stdout}");
+
+    // The user configuration is loaded too, and invalid configuration is a clean error.
+    fs::create_dir_all(config_home.path().join("code-clean")).unwrap();
+    fs::write(config_home.path().join("code-clean/config.toml"), "[[rule]]\nname = \"x\"\nmarker = 5\n").unwrap();
+    let output = Command::new(binary)
+        .current_dir(root)
+        .env("XDG_CONFIG_HOME", config_home.path())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .expect("Failed to run code-clean");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("config.toml: `marker` must be a string, found integer"), "{stderr}");
+
+    // Rules can't reach outside of their projects, the configuration of a checkout isn't trusted.
+    fs::remove_file(config_home.path().join("code-clean/config.toml")).unwrap();
+    create_project(root, "victim", &["important"]);
+    let escape = "[[rule]]\nname = \"escape\"\nmarker = \"justfile\"\ndelete = [\"../victim\"]\n";
+    fs::write(root.join(".code-clean.toml"), escape).unwrap();
+    let output = Command::new(binary)
+        .arg("--no-exec")
+        .current_dir(root)
+        .env("XDG_CONFIG_HOME", config_home.path())
+        .output()
+        .expect("Failed to run code-clean");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    let expected = ".code-clean.toml: rule `escape` has \"../victim\" in `delete`, which must be inside the project";
+    assert!(stderr.contains(expected), "{stderr}");
+    assert!(root.join("victim/important").exists());
+    let mut rules = code_clean::RuleSet::builtin();
+    let fields = ["outputs = [\"/tmp\"]", "fallback = [\"out/../..\"]", "workdir = \"../..\"", "writable = [\"..\"]"];
+    for field in fields {
+        let src = format!("[[rule]]\nname = \"cargo\"\n{field}\n");
+        assert!(rules.merge_str(&src, "test").is_err(), "{field} should be rejected");
+    }
+    let home = "[[rule]]\nname = \"cargo\"\nwritable = [\"~/.cargo\", \"cache\"]\n";
+    assert!(rules.merge_str(home, "test").is_ok(), "The home directory should stay writable");
+}
+
+#[test]
+fn test_python_rules() {
+    use code_clean::{RuleSet, Scanner};
+
+    let temp = TempDir::new();
+    let root = temp.path();
+    let config_home = TempDir::new();
+    let app = root.join("app");
+    create_project(root, "app", &["pyproject.toml", "setup.py", "tox.ini"]);
+    let caches = [
+        "__pycache__",
+        "pkg/__pycache__",
+        ".pytest_cache",
+        ".mypy_cache",
+        ".ruff_cache",
+        ".tox",
+        ".nox",
+        "build",
+        "dist",
+        "app.egg-info",
+        "src/app.egg-info",
+    ];
+    for cache in caches {
+        create_project(root, &format!("app/{cache}"), &["cached"]);
+    }
+    create_project(root, "app/.tox/py312", &["pyvenv.cfg"]);
+    create_project(root, "app/pkg", &["mod.py"]);
+    create_project(root, "app/build/lib/__pycache__", &["cached"]);
+    // Virtual environments, whatever their name, and nothing inside them is a project.
+    create_project(root, "app/.venv/lib/__pycache__", &["cached"]);
+    create_project(root, "app/.venv", &["pyvenv.cfg"]);
+    create_project(root, "app/env/lib/__pycache__", &["cached"]);
+    create_project(root, "app/env/lib/dep", &["setup.py"]);
+    create_project(root, "app/env", &["pyvenv.cfg"]);
+    // Cleaned on its own, the caches of the outer project stop at it.
+    create_project(root, "app/plugins/sub/__pycache__", &["cached"]);
+    create_project(root, "app/plugins/sub", &["setup.py"]);
+    // Only tool configuration, its caches are deleted but not its build directory.
+    create_project(root, "scripts", &["tox.ini", "requirements.txt"]);
+    create_project(root, "scripts/.tox/py312", &["pyvenv.cfg"]);
+    create_project(root, "scripts/build", &["report.html"]);
+    create_project(root, "scripts/lib/__pycache__", &["cached"]);
+
+    let mut rules = RuleSet::builtin();
+    rules.merge_str("[[rule]]\nname = \"venv\"\nenabled = true\n", "test").unwrap();
+    let mut found: Vec<_> = Scanner::new(root, rules)
+        .map(|project| {
+            let project = project.unwrap();
+            (project.kind.name.clone(), project.root)
+        })
+        .collect();
+    found.sort();
+    let expected = vec![
+        ("python".to_owned(), app.clone()),
+        ("python".to_owned(), app.join("plugins/sub")),
+        ("python-tools".to_owned(), root.join("scripts")),
+        ("venv".to_owned(), app.join(".venv")),
+        ("venv".to_owned(), app.join("env")),
+    ];
+    assert_eq!(found, expected);
+
+    // Globs don't follow symlinks out of the project.
+    let victim = TempDir::new();
+    #[cfg(unix)]
+    {
+        create_project(victim.path(), "keep.egg-info", &["PKG-INFO"]);
+        create_project(root, "linked", &["pyproject.toml"]);
+        std::os::unix::fs::symlink(victim.path(), root.join("linked/src")).unwrap();
+        let rules = RuleSet::builtin();
+        let python = rules.rules().iter().find(|rule| rule.name == "python").unwrap();
+        let outputs = python.output_paths(&root.join("linked"));
+        assert!(!outputs.iter().any(|output| output.ends_with("keep.egg-info")), "{outputs:?}");
+    }
+
+    let binary = env!("CARGO_BIN_EXE_code-clean");
+    let clean = || {
+        Command::new(binary)
+            .current_dir(root)
+            .env("XDG_CONFIG_HOME", config_home.path())
+            .env("XDG_STATE_HOME", config_home.path())
+            .env("XDG_RUNTIME_DIR", config_home.path())
+            .env("VIRTUAL_ENV", app.join(".venv"))
+            .output()
+            .expect("Failed to run code-clean")
+    };
+    let output = clean();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    println!("=== STDOUT ===\n{stdout}\n=== STDERR ===\n{stderr}");
+    assert!(output.status.success());
+    for cache in caches {
+        assert!(!app.join(cache).exists(), "{cache} should be deleted");
+    }
+    assert!(!app.join("plugins/sub/__pycache__").exists());
+    assert!(!root.join("scripts/.tox").exists());
+    assert!(root.join("scripts/build/report.html").exists() && root.join("scripts/lib/__pycache__").exists());
+    assert!(app.join("pkg/mod.py").exists());
+    #[cfg(unix)]
+    assert!(victim.path().join("keep.egg-info/PKG-INFO").exists(), "Symlinks should not be followed");
+    // Virtual environments are opt-in.
+    assert!(app.join(".venv/lib/__pycache__").exists());
+    assert!(app.join("env/lib/__pycache__").exists());
+
+    // Once enabled, the active environment is still left alone.
+    fs::write(root.join(".code-clean.toml"), "[[rule]]\nname = \"venv\"\nenabled = true\n").unwrap();
+    let output = clean();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    println!("=== STDOUT ===\n{stdout}");
+    assert!(output.status.success());
+    assert!(!app.join("env").exists());
+    assert!(app.join(".venv/pyvenv.cfg").exists());
+    let venv = app.join(".venv").display().to_string();
+    assert!(stdout.contains(&format!("[venv] {venv}: {venv} is the active virtual environment")), "{stdout}");
+}
+
+#[test]
+fn test_maven_and_gradle_builds() {
+    let temp = TempDir::new();
+    let root = temp.path();
+    let config_home = TempDir::new();
+    // A reactor with a nested module, and one that was commented out and is a project of its own.
+    create_project(root, "shop/target", &["shop.jar"]);
+    fs::write(
+        root.join("shop/pom.xml"),
+        "<project>\n  <modules>\n    <module>core</module>\n    <!-- <module>old</module> -->\n  </modules>\n  \
+         <profiles><profile><modules><module>apps/web/pom.xml</module></modules></profile></profiles>\n</project>\n",
+    )
+    .unwrap();
+    create_project(root, "shop/core/target", &["core.jar"]);
+    fs::write(root.join("shop/core/pom.xml"), "<project><modules><module>api</module></modules></project>").unwrap();
+    create_project(root, "shop/core/api/target", &["api.jar"]);
+    create_project(root, "shop/core/api", &["pom.xml"]);
+    create_project(root, "shop/apps/web/target", &["web.war"]);
+    create_project(root, "shop/apps/web", &["pom.xml"]);
+    create_project(root, "shop/old/target", &["old.jar"]);
+    create_project(root, "shop/old", &["pom.xml"]);
+    // A standalone build, a multi-project build, and a build with a wrapper.
+    create_project(root, "tool/build", &["tool.jar"]);
+    create_project(root, "tool", &["build.gradle.kts"]);
+    create_project(root, "multi/build", &["multi.jar"]);
+    create_project(root, "multi", &["build.gradle"]);
+    fs::write(root.join("multi/settings.gradle"), "rootProject.name = 'multi'\ninclude(\n    ':lib',\n)\n").unwrap();
+    create_project(root, "multi/lib/build", &["lib.jar"]);
+    create_project(root, "multi/lib", &["build.gradle"]);
+    // Not included, so a build of its own.
+    create_project(root, "multi/samples/build", &["sample.jar"]);
+    create_project(root, "multi/samples", &["build.gradle"]);
+    create_project(root, "app/build", &["app.jar"]);
+    create_project(root, "app", &["gradlew", "build.gradle"]);
+    fs::write(root.join("app/settings.gradle.kts"), "include(\"lib\") // the library\n").unwrap();
+    create_project(root, "app/lib/build", &["lib.jar"]);
+    create_project(root, "app/lib", &["build.gradle"]);
+
+    let binary = env!("CARGO_BIN_EXE_code-clean");
+    let dry_run = |args: &[&str]| {
+        let output = Command::new(binary)
+            .current_dir(root)
+            .env("XDG_CONFIG_HOME", config_home.path())
+            .args(["--dry-run", "-j1"])
+            .args(args)
+            .output()
+            .expect("Failed to run code-clean");
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        println!("=== STDOUT ===\n{stdout}");
+        assert_eq!(output.status.code(), Some(2));
+        stdout
+    };
+    let planned = |stdout: &str, rule: &str, project: &str, action: String| {
+        let line = format!("[{rule}] {}: {action}", root.join(project).display());
+        assert!(stdout.contains(&line), "{line} should be planned: {stdout}");
+    };
+
+    // Only the top of every build runs its cleaner.
+    let stdout = dry_run(&[]);
+    planned(&stdout, "maven", "shop", "mvn -q -o clean".into());
+    planned(&stdout, "maven", "shop/old", "mvn -q -o clean".into());
+    planned(&stdout, "gradle-plain", "tool", "gradle clean --offline --no-daemon".into());
+    planned(&stdout, "gradle-plain", "multi", "gradle clean --offline --no-daemon".into());
+    planned(&stdout, "gradle-plain", "multi/samples", "gradle clean --offline --no-daemon".into());
+    planned(&stdout, "gradle", "app", "./gradlew clean --no-daemon".into());
+    assert_eq!(stdout.matches("mvn ").count(), 2, "{stdout}");
+    assert_eq!(stdout.matches("gradle clean").count(), 3, "{stdout}");
+    assert!(stdout.contains("6 actions"), "{stdout}");
+
+    // Builds above the paths to clean are left alone.
+    let stdout = dry_run(&["shop/core", "multi/lib"]);
+    planned(&stdout, "maven", "shop/core", "mvn -q -o clean".into());
+    planned(&stdout, "gradle-plain", "multi/lib", "gradle clean --offline --no-daemon".into());
+    assert!(stdout.contains("2 actions"), "{stdout}");
+
+    // Without running anything, every module deletes its own outputs.
+    let stdout = dry_run(&["--no-exec"]);
+    for module in ["shop", "shop/core", "shop/core/api", "shop/apps/web", "shop/old"] {
+        planned(&stdout, "maven", module, format!("rm -rf {}", root.join(module).join("target").display()));
+    }
+    for project in ["tool", "multi", "multi/lib", "multi/samples"] {
+        planned(&stdout, "gradle-plain", project, format!("rm -rf {}", root.join(project).join("build").display()));
+    }
+    planned(&stdout, "gradle-plain", "app/lib", format!("rm -rf {}", root.join("app/lib/build").display()));
+    assert!(stdout.contains("11 actions"), "{stdout}");
+}
+
+#[test]
+fn test_cmake_and_meson_build_dirs() {
+    let temp = TempDir::new();
+    let root = temp.path();
+    let config_home = TempDir::new();
+    // Out-of-tree builds with the Makefiles and build.ninja they generated, and an in-source build.
+    create_project(root, "engine", &["CMakeLists.txt", "main.cpp"]);
+    create_project(root, "engine/build/CMakeFiles/app.dir", &["Makefile", "main.o"]);
+    create_project(root, "engine/build/_deps/fmt-src", &["CMakeLists.txt"]);
+    create_project(root, "engine/build", &["CMakeCache.txt", "Makefile", "app"]);
+    create_project(root, "engine/cmake-build-debug", &["CMakeCache.txt", "build.ninja", ".ninja_log"]);
+    create_project(root, "viewer", &["meson.build"]);
+    create_project(root, "viewer/builddir/meson-private", &["coredata.dat"]);
+    create_project(root, "viewer/builddir", &["build.ninja", ".ninja_log"]);
+    create_project(root, "legacy", &["CMakeLists.txt", "CMakeCache.txt", "Makefile", "main.c"]);
+
+    let binary = env!("CARGO_BIN_EXE_code-clean");
+    let dry_run = |args: &[&str]| {
+        let output = Command::new(binary)
+            .current_dir(root)
+            .env("XDG_CONFIG_HOME", config_home.path())
+            .args(["--dry-run", "-j1"])
+            .args(args)
+            .output()
+            .expect("Failed to run code-clean");
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        println!("=== STDOUT ===\n{stdout}");
+        assert_eq!(output.status.code(), Some(2));
+        stdout
+    };
+    let line =
+        |rule: &str, project: &str, action: String| format!("[{rule}] {}: {action}", root.join(project).display());
+    let cmake_clean = |dir: &str| format!("cmake --build {} --target clean", root.join(dir).display());
+    let remove = |dir: &str| format!("rm -rf {}", root.join(dir).display());
+
+    // The generated build files don't get a clean of their own.
+    let stdout = dry_run(&[]);
+    for dir in ["engine/build", "engine/cmake-build-debug", "legacy"] {
+        assert!(stdout.contains(&line("cmake", dir, cmake_clean(dir))), "{stdout}");
+    }
+    assert!(stdout.contains(&line("meson", "viewer/builddir", "meson compile --clean".into())), "{stdout}");
+    assert!(!stdout.contains("[make]") && !stdout.contains("[ninja]"), "{stdout}");
+    assert!(stdout.contains("4 actions"), "{stdout}");
+
+    // Deleting whole build directories instead leaves in-source builds alone.
+    fs::write(root.join(".code-clean.toml"), "[[rule]]\nname = \"cmake\"\ndelete = [\".\"]\n").unwrap();
+    let stdout = dry_run(&[]);
+    for dir in ["engine/build", "engine/cmake-build-debug"] {
+        assert!(stdout.contains(&line("cmake", dir, remove(dir))), "{stdout}");
+    }
+    let skipped = line(
+        "cmake",
+        "legacy",
+        "it is an in-source build, deleting its build directory would delete its sources".into(),
+    );
+    assert!(stdout.contains(&skipped), "{stdout}");
+    assert!(stdout.contains(&line("meson", "viewer/builddir", "meson compile --clean".into())), "{stdout}");
+    let stdout = dry_run(&["--no-exec"]);
+    assert!(stdout.contains(&line("meson", "viewer/builddir", remove("viewer/builddir"))), "{stdout}");
+    assert!(stdout.contains("3 actions"), "{stdout}");
+}
+
+#[cfg(unix)] // Setting the modification time of a directory needs to open it, which isn't portable.
+#[test]
+fn test_older_than() {
+    use std::time::{Duration, SystemTime};
+
+    let temp = TempDir::new();
+    let root = temp.path();
+    for name in ["stale", "fresh"] {
+        create_project(root, name, &["package.json", "index.js"]);
+        create_project(root, &format!("{name}/node_modules/dep"), &["index.js"]);
+    }
+    // Age everything in the stale project by 60 days.
+    let old = SystemTime::now() - Duration::from_secs(60 * 24 * 60 * 60);
+    for path in ["", "package.json", "index.js", "node_modules", "node_modules/dep", "node_modules/dep/index.js"] {
+        File::open(root.join("stale").join(path)).unwrap().set_modified(old).unwrap();
+    }
+
+    let binary = env!("CARGO_BIN_EXE_code-clean");
+    let output = Command::new(binary)
+        .current_dir(root)
+        .args(["clean", "--older-than", "30d"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .expect("Failed to run code-clean");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    println!("=== STDOUT ===\n{stdout}");
+    assert!(output.status.success());
+
+    assert!(!root.join("stale/node_modules").exists(), "Stale projects should be cleaned");
+    assert!(root.join("fresh/node_modules").exists(), "Recently active projects should be skipped");
+    let skipped = format!("[npm] {}: modified 0s ago, within --older-than 30d", root.join("fresh").display());
+    assert!(stdout.contains("Skipped 1 projects:"), "The summary should list skipped projects: {stdout}");
+    assert!(stdout.contains(&skipped), "The summary should say why a project was skipped: {stdout}");
+
+    // Longer ago than the system can tell, so nothing is that old.
+    let output = Command::new(binary)
+        .current_dir(root)
+        .args(["--dry-run", "--older-than", "30000000000000w", "."])
+        .output()
+        .expect("Failed to run code-clean");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{output:?}");
+    let skipped = format!("[npm] {}: nothing is older than --older-than 30000000000000w", root.join("fresh").display());
+    assert!(stdout.contains(&skipped), "{stdout}");
+}
+
+#[test]
+fn test_free_disk_budget() {
+    use std::time::{Duration, SystemTime};
+
+    let temp = TempDir::new();
+    let root = temp.path();
+    for (name, size) in [("big", 4), ("stale", 2), ("small", 1)] {
+        create_project(root, name, &["package.json"]);
+        fs::create_dir_all(root.join(name).join("node_modules")).unwrap();
+        fs::write(root.join(name).join("node_modules/dep.js"), vec![b'x'; size * 1024 * 1024]).unwrap();
+    }
+    // Idle for 90 days, which makes it worth more than the bigger but active project.
+    let old = SystemTime::now() - Duration::from_secs(90 * 24 * 60 * 60);
+    for path in ["", "package.json", "node_modules", "node_modules/dep.js"] {
+        File::open(root.join("stale").join(path)).unwrap().set_modified(old).unwrap();
+    }
+
+    let binary = env!("CARGO_BIN_EXE_code-clean");
+    let run = |args: &[&str]| {
+        let output = Command::new(binary).current_dir(root).args(args).output().expect("Failed to run code-clean");
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        println!("=== {args:?} ===\n{stdout}");
+        (output.status.code(), stdout)
+    };
+    let planned = |stdout: &str, name: &str| stdout.contains(&format!("[npm] {}: rm -rf", root.join(name).display()));
+
+    // The stalest project alone is enough for 1 MiB.
+    let (code, stdout) = run(&["--free", "1M", "--dry-run"]);
+    assert_eq!(code, Some(2));
+    assert!(planned(&stdout, "stale") && !planned(&stdout, "big") && !planned(&stdout, "small"), "{stdout}");
+
+    // Then the largest, and the rest is left alone once the estimates cover the target.
+    let (code, stdout) = run(&["--free=5MiB", "--dry-run"]);
+    assert_eq!(code, Some(2));
+    assert!(planned(&stdout, "stale") && planned(&stdout, "big") && !planned(&stdout, "small"), "{stdout}");
+    assert!(stdout.contains("2 actions"), "{stdout}");
+
+    let (code, stdout) = run(&["--free", "1M"]);
+    assert_eq!(code, Some(0));
+    assert!(stdout.contains("Reached the target of 1.0 MiB"), "{stdout}");
+    assert!(!root.join("stale/node_modules").exists());
+    assert!(root.join("big/node_modules").exists() && root.join("small/node_modules").exists());
+
+    let (code, _) = run(&["--free", "50X"]);
+    assert_eq!(code, Some(1));
+}
+
+#[test]
+fn test_quarantine_and_restore() {
+    let temp = TempDir::new();
+    let root = temp.path().join("projects");
+    let quarantine = temp.path().join("quarantine");
+    create_project(&root, "web", &["package.json"]);
+    create_project(&root, "web/node_modules/dep", &["index.js"]);
+
+    let binary = env!("CARGO_BIN_EXE_code-clean");
+    let run = |args: &[&str]| {
+        let output = Command::new(binary)
+            .current_dir(&root)
+            .args(args)
+            .arg(format!("--quarantine={}", quarantine.display()))
+            .output()
+            .expect("Failed to run code-clean");
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        println!("=== {args:?} ===\n{stdout}{}", String::from_utf8_lossy(&output.stderr));
+        assert!(output.status.success());
+        stdout
+    };
+
+    let stdout = run(&["clean"]);
+    assert!(!root.join("web/node_modules").exists());
+    assert!(stdout.contains(&format!("moved {} to {}", root.join("web/node_modules").display(), quarantine.display())));
+    assert!(stdout.contains("Moved "), "{stdout}");
+
+    // Without an id, `restore` lists what can be restored.
+    let stdout = run(&["restore"]);
+    let line = stdout.lines().next().unwrap_or_default();
+    assert!(line.ends_with(&root.join("web/node_modules").display().to_string()), "{stdout}");
+    let id = line.split_whitespace().next().unwrap();
+    assert!(quarantine.join(id).join("node_modules/dep/index.js").exists());
+    // Nothing in the quarantine is cleaned.
+    fs::write(quarantine.join(id).join("Makefile"), "").unwrap();
+    let output = Command::new(binary).current_dir(temp.path()).arg("--dry-run").output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains(&quarantine.display().to_string()), "{stdout}");
+
+    run(&["restore", id]);
+    assert!(root.join("web/node_modules/dep/index.js").exists(), "The directory should be back");
+    assert!(run(&["restore"]).is_empty());
+
+    // Purging only deletes what is old enough.
+    run(&["clean"]);
+    assert!(run(&["purge", "--older-than", "7d"]).contains("Purged 0 directories"));
+    let entries = || fs::read_dir(&quarantine).unwrap().filter(|entry| entry.as_ref().unwrap().path().is_dir()).count();
+    assert_eq!(entries(), 1);
+    // An entry an interrupted move left without its info is skipped, the others can still be listed and purged.
+    fs::create_dir(quarantine.join("0-0")).unwrap();
+    assert_eq!(run(&["restore"]).lines().count(), 1);
+    assert!(run(&["purge"]).contains("Purged 1 directories"));
+    assert_eq!(entries(), 1);
+    assert!(quarantine.join("0-0").exists());
+    assert!(!root.join("web/node_modules").exists());
+}
+
+#[test]
+#[cfg(unix)]
+fn test_trash() {
+    let temp = TempDir::new();
+    let root = temp.path().join("projects");
+    for name in ["one", "two"] {
+        create_project(&root, name, &["package.json"]);
+        create_project(&root, &format!("{name}/node_modules/dep"), &["index.js"]);
+    }
+    let data_home = temp.path().join("data");
 
-    // Test LOG=0 - should NOT show commands
+    let binary = env!("CARGO_BIN_EXE_code-clean");
     let output = Command::new(binary)
+        .current_dir(&root)
+        .args(["clean", "--trash", "--jobs", "1"])
+        .env("XDG_DATA_HOME", &data_home)
+        .output()
+        .expect("Failed to run code-clean");
+    println!("{}", String::from_utf8_lossy(&output.stdout));
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(!root.join("one/node_modules").exists() && !root.join("two/node_modules").exists());
+
+    // Both are in the home trash, the second one under a new name.
+    let trash = data_home.join("Trash");
+    let mut originals = Vec::new();
+    for name in ["node_modules", "node_modules.2"] {
+        assert!(trash.join("files").join(name).join("dep/index.js").exists());
+        let info = fs::read_to_string(trash.join("info").join(format!("{name}.trashinfo"))).unwrap();
+        assert!(info.starts_with("[Trash Info]\nPath=/"), "{info}");
+        assert!(info.contains("\nDeletionDate=20"), "{info}");
+        originals.push(info.lines().nth(1).unwrap().trim_start_matches("Path=").to_string());
+    }
+    originals.sort();
+    let expected: Vec<_> = ["one", "two"].map(|name| root.join(name).join("node_modules").display().to_string()).into();
+    assert_eq!(originals, expected);
+}
+
+#[cfg(unix)] // The fake cleaner is a shell script.
+#[test]
+fn test_cleaner_with_huge_stderr() {
+    use std::time::{Duration, Instant};
+
+    let temp = TempDir::new();
+    let root = temp.path();
+    let config_home = TempDir::new();
+    // 4 MiB of stderr is far more than a pipe buffer holds.
+    let noisy = |status: u8| {
+        format!(
+            "i=0\nwhile [ $i -lt 64 ]; do head -c 65536 /dev/zero | tr '\\0' x >&2; i=$((i+1)); done\necho last line >&2\nexit {status}\n"
+        )
+    };
+    fs::create_dir_all(root.join("fails")).unwrap();
+    fs::write(root.join("fails/noisy.sh"), noisy(3)).unwrap();
+    fs::create_dir_all(root.join("works")).unwrap();
+    fs::write(root.join("works/noisy.sh"), noisy(0)).unwrap();
+    fs::write(
+        root.join(".code-clean.toml"),
+        "[[rule]]\nname = \"noisy\"\nmarker = \"noisy.sh\"\ncommand = [\"sh\", \"{marker}\"]\n",
+    )
+    .unwrap();
+
+    let binary = env!("CARGO_BIN_EXE_code-clean");
+    let mut child = Command::new(binary)
+        .current_dir(root)
+        .env("XDG_CONFIG_HOME", config_home.path())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run code-clean");
+    // Read our own pipe on a thread, so only code-clean itself can deadlock.
+    let mut stderr_pipe = child.stderr.take().unwrap();
+    let reader = std::thread::spawn(move || {
+        let mut stderr = String::new();
+        std::io::Read::read_to_string(&mut stderr_pipe, &mut stderr).unwrap();
+        stderr
+    });
+    let deadline = Instant::now() + Duration::from_secs(60);
+    while child.try_wait().unwrap().is_none() {
+        if Instant::now() > deadline {
+            child.kill().unwrap();
+            panic!("code-clean is stuck on a cleaner writing a lot to stderr");
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    let stderr = reader.join().unwrap();
+    assert!(child.wait().unwrap().success());
+
+    // Only the end of the failed cleaner's output is kept.
+    let fails = format!("Error in: {:?} => exit status: 3, stderr: [", root.join("fails"));
+    assert!(stderr.contains(&fails), "{}", &stderr[..stderr.len().min(500)]);
+    assert!(stderr.contains("bytes skipped]") && stderr.trim_end().ends_with("last line"));
+    assert!(stderr.len() < 128 * 1024, "The output should be bounded, got {} bytes", stderr.len());
+    assert!(!stderr.contains("works"), "Successful cleaners are quiet");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_cleaner_timeout() {
+    use std::time::{Duration, Instant};
+
+    let temp = TempDir::new();
+    let root = temp.path();
+    let config_home = TempDir::new();
+    // Both leave a grandchild behind that has to be killed with them, the stubborn one ignores SIGTERM.
+    fs::create_dir_all(root.join("hangs")).unwrap();
+    fs::write(root.join("hangs/hang.sh"), "sleep 60 &\necho $! > pid\nwait\n").unwrap();
+    fs::create_dir_all(root.join("stubborn")).unwrap();
+    fs::write(root.join("stubborn/stubborn.sh"), "trap '' TERM\nsleep 60 &\necho $! > pid\nwait\n").unwrap();
+    fs::write(
+        root.join(".code-clean.toml"),
+        "[[rule]]\nname = \"hang\"\nmarker = \"hang.sh\"\ncommand = [\"sh\", \"{marker}\"]\n\n\
+         [[rule]]\nname = \"stubborn\"\nmarker = \"stubborn.sh\"\ncommand = [\"sh\", \"{marker}\"]\ntimeout = \"1s\"\n",
+    )
+    .unwrap();
+
+    let binary = env!("CARGO_BIN_EXE_code-clean");
+    let start = Instant::now();
+    let output = Command::new(binary)
+        .current_dir(root)
+        .env("XDG_CONFIG_HOME", config_home.path())
+        .args(["--timeout", "2s"])
+        .output()
+        .expect("Failed to run code-clean");
+    let elapsed = start.elapsed();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    // 2s for the first, 1s and the grace period before SIGKILL for the second.
+    assert!(elapsed < Duration::from_secs(30), "Took {elapsed:?}");
+    assert!(stderr.contains(&format!("Error in: {:?} => timed out after 2s", root.join("hangs"))), "{stderr}");
+    assert!(stderr.contains(&format!("Error in: {:?} => timed out after", root.join("stubborn"))), "{stderr}");
+
+    for project in ["hangs", "stubborn"] {
+        assert_dead(&root.join(project).join("pid"));
+    }
+
+    // A timeout too long to ever expire isn't one.
+    fs::write(root.join(".code-clean.toml"), "[[rule]]\nname = \"hang\"\nmarker = \"hang.sh\"\ncommand = [\"true\"]\n")
+        .unwrap();
+    let output = Command::new(binary)
+        .current_dir(root)
+        .env("XDG_CONFIG_HOME", config_home.path())
+        .args(["--timeout", "30000000000000w"])
+        .output()
+        .expect("Failed to run code-clean");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_sandboxed_cleaners() {
+    let temp = TempDir::new();
+    let root = temp.path();
+    let config_home = TempDir::new();
+    let victim = root.join("victim.txt");
+    fs::write(&victim, "precious").unwrap();
+    // A malicious Makefile, it may still clean its own project.
+    fs::create_dir_all(root.join("evil/build")).unwrap();
+    let makefile = format!("clean:\n\trm -rf build\n\t-rm -f {}\n", victim.display());
+    fs::write(root.join("evil/Makefile"), makefile).unwrap();
+    // Sees only a loopback interface in its network namespace, and none of our variables.
+    fs::create_dir_all(root.join("probe")).unwrap();
+    // And may use the temporary directory.
+    fs::write(root.join("probe/probe.sh"), "cat /proc/net/dev > net\nenv > env\ntouch \"$TMPDIR/scratch\"\n").unwrap();
+    let tmp = root.join("tmp");
+    fs::create_dir_all(&tmp).unwrap();
+    fs::write(
+        root.join(".code-clean.toml"),
+        "[[rule]]\nname = \"probe\"\nmarker = \"probe.sh\"\ncommand = [\"sh\", \"{marker}\"]\n",
+    )
+    .unwrap();
+
+    let binary = env!("CARGO_BIN_EXE_code-clean");
+    let output = Command::new(binary)
+        .arg("--no-network")
+        .current_dir(root)
+        .env("XDG_CONFIG_HOME", config_home.path())
+        .env("CODE_CLEAN_TEST_SECRET", "hunter2")
+        .env("TMPDIR", &tmp)
+        .output()
+        .expect("Failed to run code-clean");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    println!("=== STDERR ===\n{stderr}");
+    assert!(output.status.success());
+
+    assert!(!root.join("evil/build").exists(), "The cleaner should clean its own project");
+    if stderr.contains("Warning: Landlock is unavailable") {
+        return;
+    }
+    assert_eq!(fs::read_to_string(&victim).unwrap(), "precious", "Writes outside the project should fail");
+    let env = fs::read_to_string(root.join("probe/env")).unwrap();
+    assert!(env.contains("PATH=") && !env.contains("CODE_CLEAN_TEST_SECRET"), "{env}");
+    assert!(tmp.join("scratch").exists(), "The temporary directory should be writable");
+    if !stderr.contains("Warning: can't cut cleaners off from the network") {
+        let net = fs::read_to_string(root.join("probe/net")).unwrap();
+        let interfaces: Vec<_> = net.lines().skip(2).filter_map(|line| line.split(':').next()).collect();
+        assert_eq!(interfaces.iter().map(|name| name.trim()).collect::<Vec<_>>(), ["lo"], "{net}");
+    }
+
+    // Nothing stops it without the sandbox.
+    let output = Command::new(binary)
+        .arg("--no-sandbox")
+        .current_dir(root)
+        .env("XDG_CONFIG_HOME", config_home.path())
+        .output()
+        .expect("Failed to run code-clean");
+    assert!(output.status.success());
+    assert!(!victim.exists());
+}
+
+/// Waits a little for the process whose pid is in `pid_file` to die.
+#[cfg(target_os = "linux")]
+fn assert_dead(pid_file: &Path) {
+    use std::time::{Duration, Instant};
+
+    let pid = fs::read_to_string(pid_file).unwrap();
+    let stat = format!("/proc/{}/stat", pid.trim());
+    let deadline = Instant::now() + Duration::from_secs(5);
+    // A zombie is as dead as it gets until its new parent reaps it.
+    while fs::read_to_string(&stat).is_ok_and(|stat| !stat.contains(") Z ")) {
+        assert!(Instant::now() < deadline, "{pid} from {} is still running", pid_file.display());
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_cleaners_lead_their_own_process_group() {
+    let temp = TempDir::new();
+    let root = temp.path();
+    let config_home = TempDir::new();
+    // The fifth field of /proc/<pid>/stat is the process group.
+    fs::create_dir_all(root.join("group")).unwrap();
+    fs::write(root.join("group/group.sh"), "echo $$ $(cut -d' ' -f5 /proc/$$/stat) > group\n").unwrap();
+    fs::write(
+        root.join(".code-clean.toml"),
+        "[[rule]]\nname = \"group\"\nmarker = \"group.sh\"\ncommand = [\"sh\", \"{marker}\"]\n",
+    )
+    .unwrap();
+
+    let binary = env!("CARGO_BIN_EXE_code-clean");
+    let output = Command::new(binary)
+        .current_dir(root)
+        .env("XDG_CONFIG_HOME", config_home.path())
+        .output()
+        .expect("Failed to run code-clean");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let group = fs::read_to_string(root.join("group/group")).unwrap();
+    let (pid, pgid) = group.trim().split_once(' ').unwrap();
+    assert_eq!(pid, pgid, "The cleaner should lead a process group of its own");
+}
+
+#[test]
+fn test_parallel_scan() {
+    use code_clean::{RuleSet, Scanner};
+
+    // 16^3 leaf projects with a few files each, and trees that must not be walked.
+    let temp = TempDir::new();
+    let root = temp.path();
+    for a in 0..16 {
+        for b in 0..16 {
+            for c in 0..16 {
+                create_project(root, &format!("{a}/{b}/{c}"), &["Makefile", "main.c", "util.c", "README"]);
+            }
+        }
+    }
+    create_project(root, ".hidden/project", &["Makefile"]);
+    create_project(root, "0/node_modules/project", &["Makefile"]);
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(root, root.join("0/0/loop")).unwrap();
+
+    let scan = |threads: usize| {
+        let mut markers: Vec<_> = Scanner::new(root, RuleSet::builtin())
+            .threads(threads)
+            .map(|project| project.expect("The synthetic tree can be read").marker)
+            .collect();
+        markers.sort();
+        markers
+    };
+    let sequential = scan(1);
+    let parallel = scan(8);
+
+    assert_eq!(sequential.len(), 16 * 16 * 16, "Hidden, ignored and symlinked directories should be skipped");
+    assert_eq!(sequential, parallel, "Every thread count should find the same projects");
+}
+
+#[test]
+fn test_parallel_deletion() {
+    let temp = TempDir::new();
+    let root = temp.path();
+    let outside = root.join("outside");
+    create_project(root, "outside", &["keep.txt"]);
+
+    let projects = ["one", "two", "three", "four", "five"];
+    for name in projects {
+        create_project(root, name, &["package.json"]);
+        let nm = root.join(name).join("node_modules");
+        // Wide at the top and deep below, so several threads work on the same tree.
+        for dep in 0..20 {
+            let mut dir = nm.join(format!("dep{dep}"));
+            for depth in 0..10 {
+                fs::create_dir_all(&dir).unwrap();
+                fs::write(dir.join("index.js"), vec![b'x'; 4096]).unwrap();
+                dir.push(format!("level{depth}"));
+            }
+        }
+        // Symlinks are removed, never followed.
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&outside, nm.join("dep0/outside")).unwrap();
+            std::os::unix::fs::symlink(outside.join("keep.txt"), nm.join("keep.txt")).unwrap();
+        }
+    }
+
+    let binary = env!("CARGO_BIN_EXE_code-clean");
+    let output = Command::new(binary)
+        .args(["--jobs", "2"])
         .current_dir(root)
-        .env("LOG", "0")
         .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
         .output()
         .expect("Failed to run code-clean");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    println!("=== STDOUT ===\n{stdout}\n=== STDERR ===\n{stderr}");
+    assert!(output.status.success());
+
+    for name in projects {
+        assert!(!root.join(name).join("node_modules").exists(), "{name}/node_modules should be deleted");
+        assert!(root.join(name).join("package.json").exists());
+        let project_line = format!("[npm] {}: freed ", root.join(name).display());
+        assert!(stdout.contains(&project_line), "Should report {name}: {stdout}");
+    }
+    assert!(outside.join("keep.txt").exists(), "Symlink targets must survive");
+    assert!(stdout.contains("Freed "), "Should report the grand total: {stdout}");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_deletion_removes_symlinks_only() {
+    let temp = TempDir::new();
+    let root = temp.path();
+    let outside = TempDir::new();
+    create_project(outside.path(), "dir/sub", &["keep.txt"]);
+    create_project(root, "web", &["package.json"]);
+    create_project(root, "web/node_modules/dep/lib", &["index.js"]);
+    let nm = root.join("web/node_modules");
+    // At the top of the output and deeper, to directories and to files.
+    std::os::unix::fs::symlink(outside.path().join("dir"), nm.join("linked")).unwrap();
+    std::os::unix::fs::symlink(outside.path().join("dir"), nm.join("dep/lib/linked")).unwrap();
+    std::os::unix::fs::symlink(outside.path().join("dir/sub/keep.txt"), nm.join("dep/keep.txt")).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_code-clean")).current_dir(root).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert!(fs::symlink_metadata(&nm).is_err(), "The output and the symlinks in it should be deleted: {stderr}");
+    assert!(outside.path().join("dir/sub/keep.txt").exists(), "Symlink targets must survive");
+}
+
+/// Runs code-clean in `root` as the leader of its own process group, like a shell runs a foreground job.
+#[cfg(unix)]
+fn spawn_job(root: &Path, config_home: &Path, args: &[&str]) -> std::process::Child {
+    use std::os::unix::process::CommandExt;
+
+    Command::new(env!("CARGO_BIN_EXE_code-clean"))
+        .current_dir(root)
+        .env("XDG_CONFIG_HOME", config_home)
+        .env("XDG_STATE_HOME", config_home)
+        .env("XDG_RUNTIME_DIR", config_home)
+        .args(args)
+        .process_group(0)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run code-clean")
+}
+
+/// Sends `signal` to the process group led by `child`, the way the terminal sends Ctrl-C.
+#[cfg(unix)]
+fn signal_job(child: &std::process::Child, signal: &str) {
+    let status = Command::new("kill").args([signal, "--", &format!("-{}", child.id())]).status().unwrap();
+    assert!(status.success(), "Failed to send {signal}");
+}
+
+/// Waits until `path` exists.
+#[cfg(unix)]
+fn wait_for(path: &Path) {
+    use std::time::{Duration, Instant};
+
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while !path.exists() {
+        assert!(Instant::now() < deadline, "{} never showed up", path.display());
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+#[cfg(unix)]
+fn test_interrupt_lets_cleaners_finish() {
+    use std::os::unix::process::ExitStatusExt;
+
+    let temp = TempDir::new();
+    let root = temp.path();
+    let config_home = TempDir::new();
+    for project in ["one", "two"] {
+        fs::create_dir_all(root.join(project)).unwrap();
+        fs::write(root.join(project).join("slow.sh"), "touch started\nsleep 1\ntouch cleaned\n").unwrap();
+    }
+    fs::write(
+        root.join(".code-clean.toml"),
+        "[[rule]]\nname = \"slow\"\nmarker = \"slow.sh\"\ncommand = [\"sh\", \"{marker}\"]\n",
+    )
+    .unwrap();
+
+    // One job at a time, so the second project is still waiting when the signal comes.
+    let child = spawn_job(root, config_home.path(), &["-j", "1"]);
+    let started = ["one", "two"].map(|project| root.join(project).join("started"));
+    while !started.iter().any(|started| started.exists()) {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    signal_job(&child, "-INT");
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
 
+    assert_eq!(output.status.code(), Some(3), "{:?} {stdout} {stderr}", output.status.signal());
+    assert!(stderr.is_empty(), "The running cleaner shouldn't get the signal: {stderr}");
+    let cleaned = ["one", "two"].iter().filter(|project| root.join(project).join("cleaned").exists()).count();
+    let started = started.iter().filter(|started| started.exists()).count();
+    assert_eq!((started, cleaned), (1, 1), "Only the running cleaner should finish, nothing new should start");
+    assert_eq!(stdout.matches("freed").count(), 1, "{stdout}");
+    let interrupted =
+        "Interrupted, the remaining projects were left alone\nRun again with '--resume' to clean them\nDone";
+    assert!(stdout.contains(interrupted), "{stdout}");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_resume_interrupted_clean() {
+    let temp = TempDir::new();
+    let root = temp.path();
+    let config_home = TempDir::new();
+    let projects = ["one", "two", "three", "four"];
+    for project in projects {
+        fs::create_dir_all(root.join(project)).unwrap();
+        fs::write(root.join(project).join("slow.sh"), "echo cleaned >> count\ntouch started\nsleep 1\n").unwrap();
+    }
+    fs::write(
+        root.join(".code-clean.toml"),
+        "[[rule]]\nname = \"slow\"\nmarker = \"slow.sh\"\ncommand = [\"sh\", \"{marker}\"]\n",
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        let output = spawn_job(root, config_home.path(), args).wait_with_output().unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        println!("=== STDOUT ===\n{stdout}\n=== STDERR ===\n{stderr}");
+        (output.status.code(), stdout, stderr)
+    };
+
+    let child = spawn_job(root, config_home.path(), &["-j", "1"]);
+    while !projects.iter().any(|project| root.join(project).join("started").exists()) {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    // Another run of the same root can't start meanwhile.
+    let (code, _, stderr) = run(&[]);
+    assert_eq!(code, Some(1));
+    assert!(stderr.contains(&format!("another code-clean is already cleaning {}", root.display())), "{stderr}");
+    // Nor one of another spelling of it.
+    let (code, _, stderr) = run(&["one/.."]);
+    assert_eq!(code, Some(1));
+    assert!(stderr.contains(&format!("another code-clean is already cleaning {}", root.display())), "{stderr}");
+    // Nor one of a directory inside it.
+    let (code, _, stderr) = run(&["one"]);
+    assert_eq!(code, Some(1));
+    assert!(stderr.contains(&format!("already cleaning {} which contains", root.display())), "{stderr}");
+    signal_job(&child, "-INT");
+    let output = child.wait_with_output().unwrap();
     let stdout = String::from_utf8_lossy(&output.stdout);
-    assert!(!stdout.contains("cargo clean"), "LOG=0 should not show command details");
+    assert_eq!(output.status.code(), Some(3), "{stdout}");
+    assert!(stdout.contains("Run again with '--resume' to clean them"), "{stdout}");
+    let cleaned = || projects.iter().filter(|project| root.join(project).join("count").exists()).count();
+    assert_eq!(cleaned(), 1);
+
+    // The rest is cleaned, and the project that was cleaned isn't cleaned again.
+    let (code, ..) = run(&["clean", "--resume", "-j", "2"]);
+    assert_eq!(code, Some(0));
+    for project in projects {
+        let count = fs::read_to_string(root.join(project).join("count")).unwrap();
+        assert_eq!(count, "cleaned\n", "{project} should be cleaned once");
+    }
+    let (code, stdout, _) = run(&["clean", "--resume"]);
+    assert_eq!(code, Some(0));
+    assert!(stdout.contains("there is nothing to resume"), "{stdout}");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_resume_killed_clean() {
+    use std::io::Write;
+
+    let temp = TempDir::new();
+    let root = temp.path();
+    let config_home = TempDir::new();
+    let projects = ["one", "two", "three", "four"];
+    for project in projects {
+        fs::create_dir_all(root.join(project)).unwrap();
+        fs::write(root.join(project).join("slow.sh"), "touch started\nsleep 1\necho cleaned >> count\n").unwrap();
+    }
+    // A workspace cleaned from its root, which scanning the roots again finds through its members too.
+    fs::create_dir_all(root.join("ws/a")).unwrap();
+    fs::create_dir_all(root.join("ws/b")).unwrap();
+    fs::write(root.join("ws/Cargo.toml"), "[workspace]\nmembers = [\"a\", \"b\"]\n").unwrap();
+    fs::write(root.join("ws/a/Cargo.toml"), "[package]\nname = \"a\"\n").unwrap();
+    fs::write(root.join("ws/b/Cargo.toml"), "[package]\nname = \"b\"\n").unwrap();
+    fs::write(
+        root.join(".code-clean.toml"),
+        "[[rule]]\nname = \"slow\"\nmarker = \"slow.sh\"\ncommand = [\"sh\", \"{marker}\"]\n\n\
+         [[rule]]\nname = \"cargo\"\nenabled = false\n\n\
+         [[rule]]\nname = \"workspace\"\nmarker = \"Cargo.toml\"\nkind = \"cargo\"\n\
+         command = [\"sh\", \"-c\", \"echo cleaned >> count\"]\n",
+    )
+    .unwrap();
+
+    // Killed after cleaning the workspace, while cleaning other projects, in the middle of writing the journal.
+    let mut child = spawn_job(root, config_home.path(), &["-j", "3"]);
+    let started = || projects.iter().filter(|project| root.join(project).join("started").exists()).count();
+    while !root.join("ws/count").exists() || started() < 2 {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    signal_job(&child, "-KILL");
+    child.wait().unwrap();
+    let journal_dir = config_home.path().join("code-clean/journal");
+    let journal = fs::read_dir(&journal_dir).unwrap().next().unwrap().unwrap().path();
+    let mut file = fs::OpenOptions::new().append(true).open(&journal).unwrap();
+    file.write_all(b"\n[[project]]\nstate = \"do").unwrap();
+    drop(file);
+
+    // The project that was being cleaned is cleaned again, the others once.
+    let output = spawn_job(root, config_home.path(), &["clean", "--resume", "-j", "2"]).wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    println!("=== STDOUT ===\n{stdout}\n=== STDERR ===\n{stderr}");
+    assert_eq!(output.status.code(), Some(0));
+    let count = fs::read_to_string(root.join("ws/count")).unwrap();
+    assert_eq!(count, "cleaned\n", "The workspace should be cleaned once");
+    for project in projects {
+        let count = fs::read_to_string(root.join(project).join("count")).unwrap();
+        assert_eq!(count, "cleaned\n", "{project} should be cleaned once");
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_second_interrupt_kills_cleaners() {
+    use std::time::{Duration, Instant};
+
+    let temp = TempDir::new();
+    let root = temp.path();
+    let config_home = TempDir::new();
+    fs::create_dir_all(root.join("hangs")).unwrap();
+    fs::write(root.join("hangs/hang.sh"), "sleep 60 &\necho $! > pid.tmp\nmv pid.tmp pid\nwait\n").unwrap();
+    fs::write(
+        root.join(".code-clean.toml"),
+        "[[rule]]\nname = \"hang\"\nmarker = \"hang.sh\"\ncommand = [\"sh\", \"{marker}\"]\n",
+    )
+    .unwrap();
+
+    let start = Instant::now();
+    let child = spawn_job(root, config_home.path(), &["--format", "ndjson"]);
+    wait_for(&root.join("hangs/pid"));
+    signal_job(&child, "-TERM");
+    std::thread::sleep(Duration::from_millis(200));
+    signal_job(&child, "-TERM");
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(start.elapsed() < Duration::from_secs(30), "The cleaner should have been killed");
+    assert_eq!(output.status.code(), Some(128 + 15), "{stdout}");
+    assert!(stdout.contains(r#""type":"finish""#) && stdout.contains(r#""signal":9,"success":false"#), "{stdout}");
+    assert!(!stdout.contains(r#""type":"summary""#), "{stdout}");
+    // The cleaner's background job is killed with it.
+    assert_dead(&root.join("hangs/pid"));
+}
+
+#[test]
+fn test_tui_requires_terminal() {
+    let temp = TempDir::new();
+    create_project(temp.path(), "web", &["package.json"]);
+    create_project(temp.path(), "web/node_modules", &["index.js"]);
+
+    let binary = env!("CARGO_BIN_EXE_code-clean");
+    let output = Command::new(binary)
+        .current_dir(temp.path())
+        .arg("tui")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .expect("Failed to run code-clean");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.contains("'tui' needs an interactive terminal"), "{stderr}");
+    assert!(temp.path().join("web/node_modules").exists(), "Nothing should be cleaned without a selection");
+}
+
+#[test]
+fn test_json_output() {
+    let temp = TempDir::new();
+    let root = temp.path();
+    create_project(root, "web", &["package.json"]);
+    let nm = root.join("web/node_modules");
+    fs::create_dir_all(&nm).unwrap();
+    fs::write(nm.join("big.js"), vec![b'x'; 4096]).unwrap();
+    let web = root.join("web").display().to_string().replace('\\', "\\\\");
+
+    let binary = env!("CARGO_BIN_EXE_code-clean");
+    let run = |args: &[&str]| {
+        let output = Command::new(binary).current_dir(root).args(args).output().expect("Failed to run code-clean");
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    // `scan` and `report` print a single document once they are done.
+    let stdout = run(&["report", "--format", "json"]);
+    println!("=== REPORT ===\n{stdout}");
+    assert_eq!(stdout.lines().count(), 1, "{stdout}");
+    assert!(stdout.starts_with(r#"{"schema_version":1,"events":[{"type":"project","#), "{stdout}");
+    assert!(stdout.contains(&format!(r#""path":"{web}","#)), "{stdout}");
+    assert!(stdout.contains(r#""ecosystem":"npm","usage":{"apparent_bytes":"#), "{stdout}");
+    assert!(stdout.contains(r#""summary":{"type":"summary","projects":1,"reclaimable":{"apparent_bytes":"#));
+    assert!(stdout.trim_end().ends_with(r#""errors":0}}"#), "{stdout}");
+
+    // `clean` streams one versioned event per line, ending with the summary.
+    let stdout = run(&["clean", "--format=ndjson"]);
+    println!("=== CLEAN ===\n{stdout}");
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3, "Only the spawn, finish and summary events should be printed: {stdout}");
+    assert!(lines.iter().all(|line| line.starts_with(r#"{"schema_version":1,"type":""#) && line.ends_with('}')));
+    let delete = format!(r#""delete":["{web}{}node_modules"]"#, std::path::MAIN_SEPARATOR_STR.replace('\\', "\\\\"));
+    assert!(lines[0].contains(r#""type":"spawn""#) && lines[0].contains(&delete), "{}", lines[0]);
+    assert!(lines[1].contains(r#""type":"finish""#), "{}", lines[1]);
+    assert!(lines[1].contains(r#""exit_code":null,"signal":null,"success":true,"timed_out":false,"duration_secs":"#));
+    assert!(lines[1].contains(r#""freed":{"apparent_bytes":"#), "{}", lines[1]);
+    assert!(lines[2].contains(r#""type":"summary","dry_run":false,"planned":0,"skipped":0,"failed":0,"#));
+    assert!(!nm.exists());
+
+    // The terminal UI has no JSON output.
+    let output = Command::new(binary).current_dir(root).args(["tui", "--format", "json"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("'--format' can only be used with 'clean', 'scan' or 'report'")
+    );
 }
 
 /// A simple temporary directory guard that removes the directory on drop.